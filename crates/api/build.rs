@@ -8,11 +8,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .build_server(true)
         .out_dir("src")
         .format(true)
-        .compile(&["proto/multisig_service/api.proto"], &["proto"])
+        .compile(
+            &[
+                "proto/multisig_service/api.proto",
+                "proto/multisig_service/admin.proto",
+            ],
+            &["proto"],
+        )
         .unwrap_or_else(|e| panic!("error building protos {:?}", e));
 
     let src = Path::new("src");
-    remame_protos(&src).unwrap();
+    remame_protos(src).unwrap();
     Ok(())
 }
 
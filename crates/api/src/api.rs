@@ -15,6 +15,39 @@ pub struct UserMessage {
     /// binary protobuf signed transaction data
     #[prost(bytes = "vec", tag = "5")]
     pub transaction_data: ::prost::alloc::vec::Vec<u8>,
+    /// set instead of transaction_data when the data is end-to-end encrypted to the co-signers
+    #[prost(message, optional, tag = "6")]
+    pub encrypted_transaction_data: ::core::option::Option<EncryptedEnvelope>,
+}
+/// Transaction data encrypted to each co-signer's X25519 public key.
+/// The server stores the envelope as is and can't read the transaction data.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct EncryptedEnvelope {
+    /// envelope format version. Currently 1
+    #[prost(uint32, tag = "1")]
+    pub version: u32,
+    /// sender's one-time X25519 public key
+    #[prost(bytes = "vec", tag = "2")]
+    pub ephemeral_public_key: ::prost::alloc::vec::Vec<u8>,
+    /// AEAD nonce used to encrypt the transaction data
+    #[prost(bytes = "vec", tag = "3")]
+    pub nonce: ::prost::alloc::vec::Vec<u8>,
+    /// encrypted transaction data
+    #[prost(bytes = "vec", tag = "4")]
+    pub ciphertext: ::prost::alloc::vec::Vec<u8>,
+    /// one entry per co-signer that can open the envelope
+    #[prost(message, repeated, tag = "5")]
+    pub recipients: ::prost::alloc::vec::Vec<EnvelopeRecipient>,
+}
+/// The envelope's content key wrapped for a single co-signer
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct EnvelopeRecipient {
+    /// co-signer's X25519 public key
+    #[prost(bytes = "vec", tag = "1")]
+    pub public_key: ::prost::alloc::vec::Vec<u8>,
+    /// content key encrypted with a key agreed between the ephemeral key and public_key
+    #[prost(bytes = "vec", tag = "2")]
+    pub wrapped_key: ::prost::alloc::vec::Vec<u8>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct StoreMessageRequest {
@@ -36,6 +69,44 @@ pub struct GetMessagesResponse {
     #[prost(message, repeated, tag = "2")]
     pub user_messages: ::prost::alloc::vec::Vec<UserMessage>,
 }
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetServerInfoRequest {}
+/// Max transaction data size accepted for a transaction type
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TransactionTypeLimits {
+    #[prost(enumeration = "TransactionType", tag = "1")]
+    pub transaction_type: i32,
+    /// max transaction_data size in bytes. Encrypted data may be up to 16 bytes longer
+    #[prost(uint32, tag = "2")]
+    pub max_transaction_data_size: u32,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetServerInfoResponse {
+    /// server version
+    #[prost(string, tag = "1")]
+    pub version: ::prost::alloc::string::String,
+    /// transaction types known to this server and their limits
+    #[prost(message, repeated, tag = "2")]
+    pub supported_transaction_types: ::prost::alloc::vec::Vec<TransactionTypeLimits>,
+    /// max address size in bytes
+    #[prost(uint32, tag = "3")]
+    pub max_address_size: u32,
+    /// max number of recipients of an encrypted transaction data envelope
+    #[prost(uint32, tag = "4")]
+    pub max_envelope_recipients: u32,
+    /// seconds. Messages created further from the server time are rejected
+    #[prost(uint64, tag = "5")]
+    pub accepted_time_window: u64,
+    /// seconds. Messages older than this are deleted
+    #[prost(uint64, tag = "6")]
+    pub message_retention_duration: u64,
+    /// server current time, seconds since epoch
+    #[prost(uint64, tag = "7")]
+    pub server_time: u64,
+    /// max transaction_data size in bytes of transaction types not in supported_transaction_types
+    #[prost(uint32, tag = "8")]
+    pub default_max_transaction_data_size: u32,
+}
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
 pub enum TransactionType {
@@ -111,6 +182,21 @@ pub mod multi_sig_service_client {
             let path = http::uri::PathAndQuery::from_static("/api.MultiSigService/GetMessages");
             self.inner.unary(request.into_request(), path, codec).await
         }
+        #[doc = " Get the server version and the messages it accepts"]
+        pub async fn get_server_info(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetServerInfoRequest>,
+        ) -> Result<tonic::Response<super::GetServerInfoResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/api.MultiSigService/GetServerInfo");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
     }
     impl<T: Clone> Clone for MultiSigServiceClient<T> {
         fn clone(&self) -> Self {
@@ -142,6 +228,11 @@ pub mod multi_sig_service_server {
             &self,
             request: tonic::Request<super::GetMessagesRequest>,
         ) -> Result<tonic::Response<super::GetMessagesResponse>, tonic::Status>;
+        #[doc = " Get the server version and the messages it accepts"]
+        async fn get_server_info(
+            &self,
+            request: tonic::Request<super::GetServerInfoRequest>,
+        ) -> Result<tonic::Response<super::GetServerInfoResponse>, tonic::Status>;
     }
     #[derive(Debug)]
     pub struct MultiSigServiceServer<T: MultiSigService> {
@@ -241,6 +332,40 @@ pub mod multi_sig_service_server {
                     };
                     Box::pin(fut)
                 }
+                "/api.MultiSigService/GetServerInfo" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetServerInfoSvc<T: MultiSigService>(pub Arc<T>);
+                    impl<T: MultiSigService>
+                        tonic::server::UnaryService<super::GetServerInfoRequest>
+                        for GetServerInfoSvc<T>
+                    {
+                        type Response = super::GetServerInfoResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetServerInfoRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).get_server_info(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let interceptor = inner.1.clone();
+                        let inner = inner.0;
+                        let method = GetServerInfoSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = if let Some(interceptor) = interceptor {
+                            tonic::server::Grpc::with_interceptor(codec, interceptor)
+                        } else {
+                            tonic::server::Grpc::new(codec)
+                        };
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => Box::pin(async move {
                     Ok(http::Response::builder()
                         .status(200)
@@ -272,3 +397,939 @@ pub mod multi_sig_service_server {
         const NAME: &'static str = "api.MultiSigService";
     }
 }
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListAddressesRequest {}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AddressInfo {
+    /// address of the stored messages
+    #[prost(bytes = "vec", tag = "1")]
+    pub address: ::prost::alloc::vec::Vec<u8>,
+    /// number of stored messages
+    #[prost(uint32, tag = "2")]
+    pub message_count: u32,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListAddressesResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub addresses: ::prost::alloc::vec::Vec<AddressInfo>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetDbStatsRequest {}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetDbStatsResponse {
+    /// number of addresses with stored messages
+    #[prost(uint64, tag = "1")]
+    pub address_count: u64,
+    /// number of stored messages
+    #[prost(uint64, tag = "2")]
+    pub message_count: u64,
+    /// estimated db size on disk in bytes
+    #[prost(uint64, tag = "3")]
+    pub db_size: u64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeleteOldMessagesRequest {}
+/// empty response with 0 status code means success
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeleteOldMessagesResponse {}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PurgeAddressRequest {
+    #[prost(bytes = "vec", tag = "1")]
+    pub address: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PurgeAddressResponse {
+    /// number of deleted messages. 0 when no messages were stored for the address
+    #[prost(uint32, tag = "1")]
+    pub deleted_messages: u32,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetConfigRequest {}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetConfigResponse {
+    /// config key to value
+    #[prost(map = "string, string", tag = "1")]
+    pub settings:
+        ::std::collections::HashMap<::prost::alloc::string::String, ::prost::alloc::string::String>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct UpdateConfigRequest {
+    /// config key to new value. Other settings are unchanged
+    #[prost(map = "string, string", tag = "1")]
+    pub settings:
+        ::std::collections::HashMap<::prost::alloc::string::String, ::prost::alloc::string::String>,
+}
+/// empty response with 0 status code means success. The config is unchanged on error
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct UpdateConfigResponse {}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ExportMessagesRequest {
+    /// export file path. An existing file is replaced
+    #[prost(string, tag = "1")]
+    pub path: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ExportMessagesResponse {
+    /// number of exported addresses
+    #[prost(uint64, tag = "1")]
+    pub address_count: u64,
+    /// number of exported messages
+    #[prost(uint64, tag = "2")]
+    pub message_count: u64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ImportMessagesRequest {
+    /// export file path
+    #[prost(string, tag = "1")]
+    pub path: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ImportMessagesResponse {
+    /// number of stored messages
+    #[prost(uint64, tag = "1")]
+    pub imported_messages: u64,
+    /// number of invalid, expired and already stored messages
+    #[prost(uint64, tag = "2")]
+    pub skipped_messages: u64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BackupDbRequest {}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BackupDbResponse {
+    /// backup directory path on the server host
+    #[prost(string, tag = "1")]
+    pub path: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct QuarantinedRecord {
+    /// hex encoded record id
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+    /// db key of the address the record was stored under. Hashed when address hashing is enabled
+    #[prost(bytes = "vec", tag = "2")]
+    pub address_key: ::prost::alloc::vec::Vec<u8>,
+    /// why the record could not be read
+    #[prost(string, tag = "3")]
+    pub reason: ::prost::alloc::string::String,
+    /// quarantine time, seconds since epoch
+    #[prost(uint64, tag = "4")]
+    pub quarantined: u64,
+    /// size of the record data in bytes
+    #[prost(uint32, tag = "5")]
+    pub data_size: u32,
+    /// the record data - an address record or a single message. Only returned by GetQuarantinedRecord
+    #[prost(bytes = "vec", tag = "6")]
+    pub data: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListQuarantinedRecordsRequest {}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListQuarantinedRecordsResponse {
+    /// records by quarantine time
+    #[prost(message, repeated, tag = "1")]
+    pub records: ::prost::alloc::vec::Vec<QuarantinedRecord>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetQuarantinedRecordRequest {
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetQuarantinedRecordResponse {
+    #[prost(message, optional, tag = "1")]
+    pub record: ::core::option::Option<QuarantinedRecord>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeleteQuarantinedRecordRequest {
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+}
+/// empty response with 0 status code means success. NOT_FOUND when there is no such record
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeleteQuarantinedRecordResponse {}
+// An export file is a length-delimited ExportHeader followed by a length-delimited ExportRecord
+// for each stored address
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ExportHeader {
+    /// export format version. Currently 1
+    #[prost(uint32, tag = "1")]
+    pub version: u32,
+    /// version of the exporting server
+    #[prost(string, tag = "2")]
+    pub server_version: ::prost::alloc::string::String,
+    /// export time, seconds since epoch
+    #[prost(uint64, tag = "3")]
+    pub created: u64,
+}
+/// The messages stored for an address
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ExportRecord {
+    #[prost(bytes = "vec", tag = "1")]
+    pub address: ::prost::alloc::vec::Vec<u8>,
+    /// messages in stored order. A message id is its index
+    #[prost(message, repeated, tag = "2")]
+    pub user_messages: ::prost::alloc::vec::Vec<UserMessage>,
+}
+#[doc = r" Generated client implementations."]
+pub mod multi_sig_admin_client {
+    #![allow(unused_variables, dead_code, missing_docs)]
+    use tonic::codegen::*;
+    #[doc = " Operator service. Served on a separate local-only address when an admin port is configured."]
+    pub struct MultiSigAdminClient<T> {
+        inner: tonic::client::Grpc<T>,
+    }
+    impl MultiSigAdminClient<tonic::transport::Channel> {
+        #[doc = r" Attempt to create a new client by connecting to a given endpoint."]
+        pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
+        where
+            D: std::convert::TryInto<tonic::transport::Endpoint>,
+            D::Error: Into<StdError>,
+        {
+            let conn = tonic::transport::Endpoint::new(dst)?.connect().await?;
+            Ok(Self::new(conn))
+        }
+    }
+    impl<T> MultiSigAdminClient<T>
+    where
+        T: tonic::client::GrpcService<tonic::body::BoxBody>,
+        T::ResponseBody: Body + HttpBody + Send + 'static,
+        T::Error: Into<StdError>,
+        <T::ResponseBody as HttpBody>::Error: Into<StdError> + Send,
+    {
+        pub fn new(inner: T) -> Self {
+            let inner = tonic::client::Grpc::new(inner);
+            Self { inner }
+        }
+        pub fn with_interceptor(inner: T, interceptor: impl Into<tonic::Interceptor>) -> Self {
+            let inner = tonic::client::Grpc::with_interceptor(inner, interceptor);
+            Self { inner }
+        }
+        #[doc = " List stored addresses and their message counts"]
+        pub async fn list_addresses(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ListAddressesRequest>,
+        ) -> Result<tonic::Response<super::ListAddressesResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/api.MultiSigAdmin/ListAddresses");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        #[doc = " Get db stats"]
+        pub async fn get_db_stats(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetDbStatsRequest>,
+        ) -> Result<tonic::Response<super::GetDbStatsResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/api.MultiSigAdmin/GetDbStats");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        #[doc = " Delete messages older than the retention duration now, instead of on the next db cleanup"]
+        pub async fn delete_old_messages(
+            &mut self,
+            request: impl tonic::IntoRequest<super::DeleteOldMessagesRequest>,
+        ) -> Result<tonic::Response<super::DeleteOldMessagesResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/api.MultiSigAdmin/DeleteOldMessages");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        #[doc = " Delete all messages of an address"]
+        pub async fn purge_address(
+            &mut self,
+            request: impl tonic::IntoRequest<super::PurgeAddressRequest>,
+        ) -> Result<tonic::Response<super::PurgeAddressResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/api.MultiSigAdmin/PurgeAddress");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        #[doc = " Get the server runtime config"]
+        pub async fn get_config(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetConfigRequest>,
+        ) -> Result<tonic::Response<super::GetConfigResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/api.MultiSigAdmin/GetConfig");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        #[doc = " Update runtime config settings. Only message limits and the retention duration can be updated."]
+        #[doc = " Updates are not written to the config file"]
+        pub async fn update_config(
+            &mut self,
+            request: impl tonic::IntoRequest<super::UpdateConfigRequest>,
+        ) -> Result<tonic::Response<super::UpdateConfigResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/api.MultiSigAdmin/UpdateConfig");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        #[doc = " Export all stored messages to a file on the server host"]
+        pub async fn export_messages(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ExportMessagesRequest>,
+        ) -> Result<tonic::Response<super::ExportMessagesResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/api.MultiSigAdmin/ExportMessages");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        #[doc = " Import messages from an export file on the server host"]
+        pub async fn import_messages(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ImportMessagesRequest>,
+        ) -> Result<tonic::Response<super::ImportMessagesResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/api.MultiSigAdmin/ImportMessages");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        #[doc = " Back up the db to the configured backup directory"]
+        pub async fn backup_db(
+            &mut self,
+            request: impl tonic::IntoRequest<super::BackupDbRequest>,
+        ) -> Result<tonic::Response<super::BackupDbResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/api.MultiSigAdmin/BackupDb");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        #[doc = " List records moved to the quarantine by the db cleanup as they could not be read"]
+        pub async fn list_quarantined_records(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ListQuarantinedRecordsRequest>,
+        ) -> Result<tonic::Response<super::ListQuarantinedRecordsResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path =
+                http::uri::PathAndQuery::from_static("/api.MultiSigAdmin/ListQuarantinedRecords");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        #[doc = " Get a quarantined record and its data"]
+        pub async fn get_quarantined_record(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetQuarantinedRecordRequest>,
+        ) -> Result<tonic::Response<super::GetQuarantinedRecordResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path =
+                http::uri::PathAndQuery::from_static("/api.MultiSigAdmin/GetQuarantinedRecord");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        #[doc = " Delete a quarantined record"]
+        pub async fn delete_quarantined_record(
+            &mut self,
+            request: impl tonic::IntoRequest<super::DeleteQuarantinedRecordRequest>,
+        ) -> Result<tonic::Response<super::DeleteQuarantinedRecordResponse>, tonic::Status>
+        {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path =
+                http::uri::PathAndQuery::from_static("/api.MultiSigAdmin/DeleteQuarantinedRecord");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+    }
+    impl<T: Clone> Clone for MultiSigAdminClient<T> {
+        fn clone(&self) -> Self {
+            Self {
+                inner: self.inner.clone(),
+            }
+        }
+    }
+    impl<T> std::fmt::Debug for MultiSigAdminClient<T> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "MultiSigAdminClient {{ ... }}")
+        }
+    }
+}
+#[doc = r" Generated server implementations."]
+pub mod multi_sig_admin_server {
+    #![allow(unused_variables, dead_code, missing_docs)]
+    use tonic::codegen::*;
+    #[doc = "Generated trait containing gRPC methods that should be implemented for use with MultiSigAdminServer."]
+    #[async_trait]
+    pub trait MultiSigAdmin: Send + Sync + 'static {
+        #[doc = " List stored addresses and their message counts"]
+        async fn list_addresses(
+            &self,
+            request: tonic::Request<super::ListAddressesRequest>,
+        ) -> Result<tonic::Response<super::ListAddressesResponse>, tonic::Status>;
+        #[doc = " Get db stats"]
+        async fn get_db_stats(
+            &self,
+            request: tonic::Request<super::GetDbStatsRequest>,
+        ) -> Result<tonic::Response<super::GetDbStatsResponse>, tonic::Status>;
+        #[doc = " Delete messages older than the retention duration now, instead of on the next db cleanup"]
+        async fn delete_old_messages(
+            &self,
+            request: tonic::Request<super::DeleteOldMessagesRequest>,
+        ) -> Result<tonic::Response<super::DeleteOldMessagesResponse>, tonic::Status>;
+        #[doc = " Delete all messages of an address"]
+        async fn purge_address(
+            &self,
+            request: tonic::Request<super::PurgeAddressRequest>,
+        ) -> Result<tonic::Response<super::PurgeAddressResponse>, tonic::Status>;
+        #[doc = " Get the server runtime config"]
+        async fn get_config(
+            &self,
+            request: tonic::Request<super::GetConfigRequest>,
+        ) -> Result<tonic::Response<super::GetConfigResponse>, tonic::Status>;
+        #[doc = " Update runtime config settings. Only message limits and the retention duration can be updated."]
+        #[doc = " Updates are not written to the config file"]
+        async fn update_config(
+            &self,
+            request: tonic::Request<super::UpdateConfigRequest>,
+        ) -> Result<tonic::Response<super::UpdateConfigResponse>, tonic::Status>;
+        #[doc = " Export all stored messages to a file on the server host"]
+        async fn export_messages(
+            &self,
+            request: tonic::Request<super::ExportMessagesRequest>,
+        ) -> Result<tonic::Response<super::ExportMessagesResponse>, tonic::Status>;
+        #[doc = " Import messages from an export file on the server host"]
+        async fn import_messages(
+            &self,
+            request: tonic::Request<super::ImportMessagesRequest>,
+        ) -> Result<tonic::Response<super::ImportMessagesResponse>, tonic::Status>;
+        #[doc = " Back up the db to the configured backup directory"]
+        async fn backup_db(
+            &self,
+            request: tonic::Request<super::BackupDbRequest>,
+        ) -> Result<tonic::Response<super::BackupDbResponse>, tonic::Status>;
+        #[doc = " List records moved to the quarantine by the db cleanup as they could not be read"]
+        async fn list_quarantined_records(
+            &self,
+            request: tonic::Request<super::ListQuarantinedRecordsRequest>,
+        ) -> Result<tonic::Response<super::ListQuarantinedRecordsResponse>, tonic::Status>;
+        #[doc = " Get a quarantined record and its data"]
+        async fn get_quarantined_record(
+            &self,
+            request: tonic::Request<super::GetQuarantinedRecordRequest>,
+        ) -> Result<tonic::Response<super::GetQuarantinedRecordResponse>, tonic::Status>;
+        #[doc = " Delete a quarantined record"]
+        async fn delete_quarantined_record(
+            &self,
+            request: tonic::Request<super::DeleteQuarantinedRecordRequest>,
+        ) -> Result<tonic::Response<super::DeleteQuarantinedRecordResponse>, tonic::Status>;
+    }
+    #[doc = " Operator service. Served on a separate local-only address when an admin port is configured."]
+    #[derive(Debug)]
+    pub struct MultiSigAdminServer<T: MultiSigAdmin> {
+        inner: _Inner<T>,
+    }
+    struct _Inner<T>(Arc<T>, Option<tonic::Interceptor>);
+    impl<T: MultiSigAdmin> MultiSigAdminServer<T> {
+        pub fn new(inner: T) -> Self {
+            let inner = Arc::new(inner);
+            let inner = _Inner(inner, None);
+            Self { inner }
+        }
+        pub fn with_interceptor(inner: T, interceptor: impl Into<tonic::Interceptor>) -> Self {
+            let inner = Arc::new(inner);
+            let inner = _Inner(inner, Some(interceptor.into()));
+            Self { inner }
+        }
+    }
+    impl<T, B> Service<http::Request<B>> for MultiSigAdminServer<T>
+    where
+        T: MultiSigAdmin,
+        B: HttpBody + Send + Sync + 'static,
+        B::Error: Into<StdError> + Send + 'static,
+    {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = Never;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            let inner = self.inner.clone();
+            match req.uri().path() {
+                "/api.MultiSigAdmin/ListAddresses" => {
+                    #[allow(non_camel_case_types)]
+                    struct ListAddressesSvc<T: MultiSigAdmin>(pub Arc<T>);
+                    impl<T: MultiSigAdmin> tonic::server::UnaryService<super::ListAddressesRequest>
+                        for ListAddressesSvc<T>
+                    {
+                        type Response = super::ListAddressesResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ListAddressesRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).list_addresses(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let interceptor = inner.1.clone();
+                        let inner = inner.0;
+                        let method = ListAddressesSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = if let Some(interceptor) = interceptor {
+                            tonic::server::Grpc::with_interceptor(codec, interceptor)
+                        } else {
+                            tonic::server::Grpc::new(codec)
+                        };
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/api.MultiSigAdmin/GetDbStats" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetDbStatsSvc<T: MultiSigAdmin>(pub Arc<T>);
+                    impl<T: MultiSigAdmin> tonic::server::UnaryService<super::GetDbStatsRequest> for GetDbStatsSvc<T> {
+                        type Response = super::GetDbStatsResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetDbStatsRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).get_db_stats(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let interceptor = inner.1.clone();
+                        let inner = inner.0;
+                        let method = GetDbStatsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = if let Some(interceptor) = interceptor {
+                            tonic::server::Grpc::with_interceptor(codec, interceptor)
+                        } else {
+                            tonic::server::Grpc::new(codec)
+                        };
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/api.MultiSigAdmin/DeleteOldMessages" => {
+                    #[allow(non_camel_case_types)]
+                    struct DeleteOldMessagesSvc<T: MultiSigAdmin>(pub Arc<T>);
+                    impl<T: MultiSigAdmin>
+                        tonic::server::UnaryService<super::DeleteOldMessagesRequest>
+                        for DeleteOldMessagesSvc<T>
+                    {
+                        type Response = super::DeleteOldMessagesResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::DeleteOldMessagesRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).delete_old_messages(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let interceptor = inner.1.clone();
+                        let inner = inner.0;
+                        let method = DeleteOldMessagesSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = if let Some(interceptor) = interceptor {
+                            tonic::server::Grpc::with_interceptor(codec, interceptor)
+                        } else {
+                            tonic::server::Grpc::new(codec)
+                        };
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/api.MultiSigAdmin/PurgeAddress" => {
+                    #[allow(non_camel_case_types)]
+                    struct PurgeAddressSvc<T: MultiSigAdmin>(pub Arc<T>);
+                    impl<T: MultiSigAdmin> tonic::server::UnaryService<super::PurgeAddressRequest>
+                        for PurgeAddressSvc<T>
+                    {
+                        type Response = super::PurgeAddressResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PurgeAddressRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).purge_address(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let interceptor = inner.1.clone();
+                        let inner = inner.0;
+                        let method = PurgeAddressSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = if let Some(interceptor) = interceptor {
+                            tonic::server::Grpc::with_interceptor(codec, interceptor)
+                        } else {
+                            tonic::server::Grpc::new(codec)
+                        };
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/api.MultiSigAdmin/GetConfig" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetConfigSvc<T: MultiSigAdmin>(pub Arc<T>);
+                    impl<T: MultiSigAdmin> tonic::server::UnaryService<super::GetConfigRequest> for GetConfigSvc<T> {
+                        type Response = super::GetConfigResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetConfigRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).get_config(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let interceptor = inner.1.clone();
+                        let inner = inner.0;
+                        let method = GetConfigSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = if let Some(interceptor) = interceptor {
+                            tonic::server::Grpc::with_interceptor(codec, interceptor)
+                        } else {
+                            tonic::server::Grpc::new(codec)
+                        };
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/api.MultiSigAdmin/UpdateConfig" => {
+                    #[allow(non_camel_case_types)]
+                    struct UpdateConfigSvc<T: MultiSigAdmin>(pub Arc<T>);
+                    impl<T: MultiSigAdmin> tonic::server::UnaryService<super::UpdateConfigRequest>
+                        for UpdateConfigSvc<T>
+                    {
+                        type Response = super::UpdateConfigResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::UpdateConfigRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).update_config(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let interceptor = inner.1.clone();
+                        let inner = inner.0;
+                        let method = UpdateConfigSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = if let Some(interceptor) = interceptor {
+                            tonic::server::Grpc::with_interceptor(codec, interceptor)
+                        } else {
+                            tonic::server::Grpc::new(codec)
+                        };
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/api.MultiSigAdmin/ExportMessages" => {
+                    #[allow(non_camel_case_types)]
+                    struct ExportMessagesSvc<T: MultiSigAdmin>(pub Arc<T>);
+                    impl<T: MultiSigAdmin> tonic::server::UnaryService<super::ExportMessagesRequest>
+                        for ExportMessagesSvc<T>
+                    {
+                        type Response = super::ExportMessagesResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ExportMessagesRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).export_messages(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let interceptor = inner.1.clone();
+                        let inner = inner.0;
+                        let method = ExportMessagesSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = if let Some(interceptor) = interceptor {
+                            tonic::server::Grpc::with_interceptor(codec, interceptor)
+                        } else {
+                            tonic::server::Grpc::new(codec)
+                        };
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/api.MultiSigAdmin/ImportMessages" => {
+                    #[allow(non_camel_case_types)]
+                    struct ImportMessagesSvc<T: MultiSigAdmin>(pub Arc<T>);
+                    impl<T: MultiSigAdmin> tonic::server::UnaryService<super::ImportMessagesRequest>
+                        for ImportMessagesSvc<T>
+                    {
+                        type Response = super::ImportMessagesResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ImportMessagesRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).import_messages(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let interceptor = inner.1.clone();
+                        let inner = inner.0;
+                        let method = ImportMessagesSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = if let Some(interceptor) = interceptor {
+                            tonic::server::Grpc::with_interceptor(codec, interceptor)
+                        } else {
+                            tonic::server::Grpc::new(codec)
+                        };
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/api.MultiSigAdmin/BackupDb" => {
+                    #[allow(non_camel_case_types)]
+                    struct BackupDbSvc<T: MultiSigAdmin>(pub Arc<T>);
+                    impl<T: MultiSigAdmin> tonic::server::UnaryService<super::BackupDbRequest> for BackupDbSvc<T> {
+                        type Response = super::BackupDbResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::BackupDbRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).backup_db(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let interceptor = inner.1.clone();
+                        let inner = inner.0;
+                        let method = BackupDbSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = if let Some(interceptor) = interceptor {
+                            tonic::server::Grpc::with_interceptor(codec, interceptor)
+                        } else {
+                            tonic::server::Grpc::new(codec)
+                        };
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/api.MultiSigAdmin/ListQuarantinedRecords" => {
+                    #[allow(non_camel_case_types)]
+                    struct ListQuarantinedRecordsSvc<T: MultiSigAdmin>(pub Arc<T>);
+                    impl<T: MultiSigAdmin>
+                        tonic::server::UnaryService<super::ListQuarantinedRecordsRequest>
+                        for ListQuarantinedRecordsSvc<T>
+                    {
+                        type Response = super::ListQuarantinedRecordsResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ListQuarantinedRecordsRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut =
+                                async move { (*inner).list_quarantined_records(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let interceptor = inner.1.clone();
+                        let inner = inner.0;
+                        let method = ListQuarantinedRecordsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = if let Some(interceptor) = interceptor {
+                            tonic::server::Grpc::with_interceptor(codec, interceptor)
+                        } else {
+                            tonic::server::Grpc::new(codec)
+                        };
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/api.MultiSigAdmin/GetQuarantinedRecord" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetQuarantinedRecordSvc<T: MultiSigAdmin>(pub Arc<T>);
+                    impl<T: MultiSigAdmin>
+                        tonic::server::UnaryService<super::GetQuarantinedRecordRequest>
+                        for GetQuarantinedRecordSvc<T>
+                    {
+                        type Response = super::GetQuarantinedRecordResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetQuarantinedRecordRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).get_quarantined_record(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let interceptor = inner.1.clone();
+                        let inner = inner.0;
+                        let method = GetQuarantinedRecordSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = if let Some(interceptor) = interceptor {
+                            tonic::server::Grpc::with_interceptor(codec, interceptor)
+                        } else {
+                            tonic::server::Grpc::new(codec)
+                        };
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/api.MultiSigAdmin/DeleteQuarantinedRecord" => {
+                    #[allow(non_camel_case_types)]
+                    struct DeleteQuarantinedRecordSvc<T: MultiSigAdmin>(pub Arc<T>);
+                    impl<T: MultiSigAdmin>
+                        tonic::server::UnaryService<super::DeleteQuarantinedRecordRequest>
+                        for DeleteQuarantinedRecordSvc<T>
+                    {
+                        type Response = super::DeleteQuarantinedRecordResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::DeleteQuarantinedRecordRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut =
+                                async move { (*inner).delete_quarantined_record(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let interceptor = inner.1.clone();
+                        let inner = inner.0;
+                        let method = DeleteQuarantinedRecordSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = if let Some(interceptor) = interceptor {
+                            tonic::server::Grpc::with_interceptor(codec, interceptor)
+                        } else {
+                            tonic::server::Grpc::new(codec)
+                        };
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                _ => Box::pin(async move {
+                    Ok(http::Response::builder()
+                        .status(200)
+                        .header("grpc-status", "12")
+                        .header("content-type", "application/grpc")
+                        .body(tonic::body::BoxBody::empty())
+                        .unwrap())
+                }),
+            }
+        }
+    }
+    impl<T: MultiSigAdmin> Clone for MultiSigAdminServer<T> {
+        fn clone(&self) -> Self {
+            let inner = self.inner.clone();
+            Self { inner }
+        }
+    }
+    impl<T: MultiSigAdmin> Clone for _Inner<T> {
+        fn clone(&self) -> Self {
+            Self(self.0.clone(), self.1.clone())
+        }
+    }
+    impl<T: std::fmt::Debug> std::fmt::Debug for _Inner<T> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self.0)
+        }
+    }
+    impl<T: MultiSigAdmin> tonic::transport::NamedService for MultiSigAdminServer<T> {
+        const NAME: &'static str = "api.MultiSigAdmin";
+    }
+}
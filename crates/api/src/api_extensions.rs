@@ -1 +1,313 @@
+use crate::api::{EncryptedEnvelope, EnvelopeRecipient, TransactionType, UserMessage};
+use anyhow::{anyhow, bail, Result};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
 
+/// Current encrypted envelope format version
+pub const ENVELOPE_VERSION: u32 = 1;
+/// Size of an X25519 public or secret key
+pub const ENVELOPE_KEY_SIZE: usize = 32;
+/// Size of the transaction data AEAD nonce
+pub const ENVELOPE_NONCE_SIZE: usize = 12;
+/// Size of the authentication tag appended to all AEAD ciphertexts
+pub const ENVELOPE_TAG_SIZE: usize = 16;
+/// Size of a content key wrapped for a single recipient
+pub const ENVELOPE_WRAPPED_KEY_SIZE: usize = ENVELOPE_KEY_SIZE + ENVELOPE_TAG_SIZE;
+
+// domain separation for key wrapping keys derivation
+const KEY_WRAP_INFO: &[u8] = b"spacemesh-multisig-envelope-v1";
+
+/// An X25519 key pair a co-signer uses to open envelopes encrypted to it
+pub struct EnvelopeKeyPair {
+    secret: StaticSecret,
+    public: PublicKey,
+}
+
+impl EnvelopeKeyPair {
+    /// Generate a new random key pair
+    pub fn generate() -> Self {
+        let secret = StaticSecret::random_from_rng(OsRng);
+        let public = PublicKey::from(&secret);
+        EnvelopeKeyPair { secret, public }
+    }
+
+    /// Restore a key pair from its secret key bytes
+    pub fn from_secret_bytes(bytes: [u8; ENVELOPE_KEY_SIZE]) -> Self {
+        let secret = StaticSecret::from(bytes);
+        let public = PublicKey::from(&secret);
+        EnvelopeKeyPair { secret, public }
+    }
+
+    /// The secret key bytes, to persist the key pair
+    pub fn secret_bytes(&self) -> [u8; ENVELOPE_KEY_SIZE] {
+        self.secret.to_bytes()
+    }
+
+    /// The public key other parties encrypt envelopes to
+    pub fn public_key(&self) -> [u8; ENVELOPE_KEY_SIZE] {
+        self.public.to_bytes()
+    }
+}
+
+impl EncryptedEnvelope {
+    /// Encrypt data to each of the provided X25519 public keys.
+    /// aad is authenticated but not encrypted and must be provided again to open the envelope.
+    pub fn seal(
+        data: &[u8],
+        recipients: &[[u8; ENVELOPE_KEY_SIZE]],
+        aad: &[u8],
+    ) -> Result<EncryptedEnvelope> {
+        if recipients.is_empty() {
+            bail!("an envelope must have at least one recipient")
+        }
+
+        let mut content_key = [0u8; ENVELOPE_KEY_SIZE];
+        OsRng.fill_bytes(&mut content_key);
+        let mut nonce = [0u8; ENVELOPE_NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&content_key))
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: data, aad })
+            .map_err(|_| anyhow!("failed to encrypt transaction data"))?;
+
+        // a single ephemeral key is used for all recipients. Each recipient gets a different wrapping key.
+        let ephemeral_secret = StaticSecret::random_from_rng(OsRng);
+        let ephemeral_public = PublicKey::from(&ephemeral_secret);
+
+        let mut envelope_recipients = Vec::with_capacity(recipients.len());
+        for r in recipients {
+            let recipient_public = PublicKey::from(*r);
+            let cipher = key_wrap_cipher(
+                &ephemeral_secret,
+                &recipient_public,
+                &ephemeral_public,
+                &recipient_public,
+            )?;
+            let wrapped_key = cipher
+                .encrypt(&Nonce::default(), content_key.as_ref())
+                .map_err(|_| anyhow!("failed to wrap content key"))?;
+            envelope_recipients.push(EnvelopeRecipient {
+                public_key: r.to_vec(),
+                wrapped_key,
+            });
+        }
+
+        Ok(EncryptedEnvelope {
+            version: ENVELOPE_VERSION,
+            ephemeral_public_key: ephemeral_public.as_bytes().to_vec(),
+            nonce: nonce.to_vec(),
+            ciphertext,
+            recipients: envelope_recipients,
+        })
+    }
+
+    /// Decrypt the envelope's data using a recipient's key pair
+    pub fn open(&self, key_pair: &EnvelopeKeyPair, aad: &[u8]) -> Result<Vec<u8>> {
+        self.validate()?;
+
+        let public_key = key_pair.public_key();
+        let recipient = self
+            .recipients
+            .iter()
+            .find(|r| r.public_key == public_key)
+            .ok_or_else(|| anyhow!("envelope is not encrypted to this key"))?;
+
+        let ephemeral_public = PublicKey::from(to_key(&self.ephemeral_public_key)?);
+        let cipher = key_wrap_cipher(
+            &key_pair.secret,
+            &ephemeral_public,
+            &ephemeral_public,
+            &key_pair.public,
+        )?;
+        let content_key = cipher
+            .decrypt(&Nonce::default(), recipient.wrapped_key.as_ref())
+            .map_err(|_| anyhow!("failed to unwrap content key"))?;
+
+        ChaCha20Poly1305::new(Key::from_slice(&content_key))
+            .decrypt(
+                Nonce::from_slice(&self.nonce),
+                Payload {
+                    msg: &self.ciphertext,
+                    aad,
+                },
+            )
+            .map_err(|_| anyhow!("failed to decrypt transaction data"))
+    }
+
+    /// Validate the envelope structure. Does not require any key and doesn't decrypt anything.
+    pub fn validate(&self) -> Result<()> {
+        if self.version != ENVELOPE_VERSION {
+            bail!("unsupported envelope version {}", self.version)
+        }
+        if self.ephemeral_public_key.len() != ENVELOPE_KEY_SIZE {
+            bail!("invalid envelope ephemeral public key size")
+        }
+        if self.nonce.len() != ENVELOPE_NONCE_SIZE {
+            bail!("invalid envelope nonce size")
+        }
+        if self.ciphertext.len() <= ENVELOPE_TAG_SIZE {
+            bail!("invalid envelope ciphertext size")
+        }
+        if self.recipients.is_empty() {
+            bail!("envelope has no recipients")
+        }
+        for r in self.recipients.iter() {
+            if r.public_key.len() != ENVELOPE_KEY_SIZE
+                || r.wrapped_key.len() != ENVELOPE_WRAPPED_KEY_SIZE
+            {
+                bail!("invalid envelope recipient")
+            }
+        }
+        Ok(())
+    }
+}
+
+impl UserMessage {
+    /// Create a user message with transaction data encrypted to the provided co-signers public keys.
+    /// The message routing metadata is authenticated so it can't be changed after sealing.
+    pub fn new_encrypted(
+        net_id: u32,
+        created: u64,
+        address: Vec<u8>,
+        transaction_type: TransactionType,
+        transaction_data: &[u8],
+        recipients: &[[u8; ENVELOPE_KEY_SIZE]],
+    ) -> Result<UserMessage> {
+        let mut msg = UserMessage {
+            net_id,
+            created,
+            address,
+            transaction_type: transaction_type as i32,
+            transaction_data: vec![],
+            encrypted_transaction_data: None,
+        };
+        let envelope = EncryptedEnvelope::seal(transaction_data, recipients, &msg.envelope_aad())?;
+        msg.encrypted_transaction_data = Some(envelope);
+        Ok(msg)
+    }
+
+    /// Returns the message transaction data, decrypting it with key_pair if it is encrypted
+    pub fn open_transaction_data(&self, key_pair: &EnvelopeKeyPair) -> Result<Vec<u8>> {
+        match self.encrypted_transaction_data.as_ref() {
+            Some(envelope) => envelope.open(key_pair, &self.envelope_aad()),
+            None => Ok(self.transaction_data.clone()),
+        }
+    }
+
+    // Message metadata bound to the encrypted transaction data
+    fn envelope_aad(&self) -> Vec<u8> {
+        let mut aad = Vec::with_capacity(16 + self.address.len());
+        aad.extend_from_slice(&self.net_id.to_be_bytes());
+        aad.extend_from_slice(&self.created.to_be_bytes());
+        aad.extend_from_slice(&self.transaction_type.to_be_bytes());
+        aad.extend_from_slice(&self.address);
+        aad
+    }
+}
+
+// Returns the cipher used to wrap the content key for a recipient.
+// The sender uses the ephemeral secret and the recipient uses its own secret. Both derive the same key.
+fn key_wrap_cipher(
+    secret: &StaticSecret,
+    other_public: &PublicKey,
+    ephemeral_public: &PublicKey,
+    recipient_public: &PublicKey,
+) -> Result<ChaCha20Poly1305> {
+    let shared = secret.diffie_hellman(other_public);
+    if !shared.was_contributory() {
+        bail!("invalid public key")
+    }
+
+    let mut info = Vec::with_capacity(KEY_WRAP_INFO.len() + 2 * ENVELOPE_KEY_SIZE);
+    info.extend_from_slice(KEY_WRAP_INFO);
+    info.extend_from_slice(ephemeral_public.as_bytes());
+    info.extend_from_slice(recipient_public.as_bytes());
+
+    let mut key = [0u8; ENVELOPE_KEY_SIZE];
+    Hkdf::<Sha256>::new(None, shared.as_bytes())
+        .expand(&info, &mut key)
+        .map_err(|_| anyhow!("failed to derive key wrapping key"))?;
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+fn to_key(bytes: &[u8]) -> Result<[u8; ENVELOPE_KEY_SIZE]> {
+    let mut key = [0u8; ENVELOPE_KEY_SIZE];
+    if bytes.len() != ENVELOPE_KEY_SIZE {
+        bail!("invalid key size")
+    }
+    key.copy_from_slice(bytes);
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encrypted_message(recipients: &[[u8; ENVELOPE_KEY_SIZE]]) -> (UserMessage, Vec<u8>) {
+        let address: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
+        let tx: Vec<u8> = (0..1024).map(|_| rand::random::<u8>()).collect();
+        let msg = UserMessage::new_encrypted(
+            1,
+            1_600_000_000,
+            address,
+            TransactionType::VaultWithdraw,
+            &tx,
+            recipients,
+        )
+        .unwrap();
+        (msg, tx)
+    }
+
+    #[test]
+    fn seal_and_open_envelope() {
+        let alice = EnvelopeKeyPair::generate();
+        let bob = EnvelopeKeyPair::generate();
+        let (msg, tx) = encrypted_message(&[alice.public_key(), bob.public_key()]);
+
+        assert!(msg.transaction_data.is_empty());
+        let envelope = msg.encrypted_transaction_data.as_ref().unwrap();
+        envelope.validate().unwrap();
+        assert_eq!(envelope.recipients.len(), 2);
+        assert_ne!(
+            envelope.ciphertext[..ENVELOPE_TAG_SIZE],
+            tx[..ENVELOPE_TAG_SIZE]
+        );
+
+        assert_eq!(msg.open_transaction_data(&alice).unwrap(), tx);
+        let bob = EnvelopeKeyPair::from_secret_bytes(bob.secret_bytes());
+        assert_eq!(msg.open_transaction_data(&bob).unwrap(), tx);
+    }
+
+    #[test]
+    fn reject_non_recipient() {
+        let alice = EnvelopeKeyPair::generate();
+        let eve = EnvelopeKeyPair::generate();
+        let (msg, _) = encrypted_message(&[alice.public_key()]);
+        assert!(msg.open_transaction_data(&eve).is_err());
+    }
+
+    #[test]
+    fn reject_tampered_metadata() {
+        let alice = EnvelopeKeyPair::generate();
+        let (mut msg, _) = encrypted_message(&[alice.public_key()]);
+        msg.address[0] ^= 1;
+        assert!(msg.open_transaction_data(&alice).is_err());
+
+        let (mut msg, _) = encrypted_message(&[alice.public_key()]);
+        msg.transaction_type = TransactionType::CoinSpend as i32;
+        assert!(msg.open_transaction_data(&alice).is_err());
+    }
+
+    #[test]
+    fn reject_tampered_ciphertext() {
+        let alice = EnvelopeKeyPair::generate();
+        let (mut msg, _) = encrypted_message(&[alice.public_key()]);
+        msg.encrypted_transaction_data.as_mut().unwrap().ciphertext[0] ^= 1;
+        assert!(msg.open_transaction_data(&alice).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+//! Benchmarks of the message store, get and prune paths, and of concurrent requests.
+//! Requests are sent to a server started with a new db in a temp dir, so the benchmarks
+//! measure the storage layout with the grpc overhead of a local connection.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use api::api::multi_sig_admin_client::MultiSigAdminClient;
+use api::api::multi_sig_service_client::MultiSigServiceClient;
+use api::api::{DeleteOldMessagesRequest, GetMessagesRequest};
+use common::*;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::process::Child;
+use tokio::runtime::Runtime;
+use tonic::transport::Channel;
+
+// number of addresses with stored messages when messages are read or pruned
+const STORED_ADDRESSES: usize = 100;
+
+/// A server started for a benchmark, stopped when the benchmark completes
+struct BenchServer {
+    child: Child,
+    client: MultiSigServiceClient<Channel>,
+    admin_client: MultiSigAdminClient<Channel>,
+}
+
+impl BenchServer {
+    fn start(runtime: &Runtime) -> BenchServer {
+        runtime.block_on(async {
+            let dir = test_dir();
+            let port = free_port();
+            let admin_port = free_port();
+            write_config(
+                &dir,
+                port,
+                &format!(
+                    "admin_host = \"127.0.0.1\"\nadmin_port = {}\nlog_level = \"warn\"\n",
+                    admin_port
+                ),
+            );
+            let (child, client) = start_server(&dir, port).await;
+            let admin_client = admin_client(admin_port).await;
+            BenchServer {
+                child,
+                client,
+                admin_client,
+            }
+        })
+    }
+
+    /// Store messages for new addresses. Returns the addresses.
+    fn store_messages(
+        &mut self,
+        runtime: &Runtime,
+        addresses: usize,
+        messages: usize,
+    ) -> Vec<Vec<u8>> {
+        let addresses: Vec<Vec<u8>> = (0..addresses).map(|_| new_address()).collect();
+        runtime.block_on(async {
+            for address in addresses.iter() {
+                for _ in 0..messages {
+                    self.client
+                        .store_message(store_request(address.clone()))
+                        .await
+                        .unwrap();
+                }
+            }
+        });
+        addresses
+    }
+
+    fn stop(self, runtime: &Runtime) {
+        runtime.block_on(terminate(self.child));
+    }
+}
+
+fn new_address() -> Vec<u8> {
+    (0..32).map(|_| rand::random::<u8>()).collect()
+}
+
+fn store_message(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut server = BenchServer::start(&runtime);
+    let mut group = c.benchmark_group("store_message");
+
+    // each message is stored for a new address, so the address record size doesn't grow
+    group.bench_function("new_address", |b| {
+        b.iter(|| {
+            runtime
+                .block_on(server.client.store_message(store_request(new_address())))
+                .unwrap()
+        })
+    });
+
+    // messages are appended to the address record, so this measures the cost of rewriting a
+    // record of a growing number of messages
+    let address = new_address();
+    group.bench_function("existing_address", |b| {
+        b.iter(|| {
+            runtime
+                .block_on(server.client.store_message(store_request(address.clone())))
+                .unwrap()
+        })
+    });
+    group.finish();
+    server.stop(&runtime);
+}
+
+fn get_messages(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut server = BenchServer::start(&runtime);
+    let mut group = c.benchmark_group("get_messages");
+    for messages in [1, 10, 100].iter() {
+        let addresses = server.store_messages(&runtime, STORED_ADDRESSES, *messages);
+        group.throughput(Throughput::Elements(*messages as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(messages), messages, |b, _| {
+            let mut i = 0;
+            b.iter(|| {
+                i = (i + 1) % addresses.len();
+                let request = GetMessagesRequest {
+                    address: addresses[i].clone(),
+                };
+                runtime
+                    .block_on(server.client.get_messages(request))
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+    server.stop(&runtime);
+}
+
+fn delete_old_messages(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut server = BenchServer::start(&runtime);
+    let mut group = c.benchmark_group("delete_old_messages");
+    group.sample_size(10);
+
+    // stored messages are not expired, so each pruning reads all addresses and keeps them
+    let mut stored_addresses = 0;
+    for addresses in [100, 1000].iter() {
+        server.store_messages(&runtime, addresses - stored_addresses, 1);
+        stored_addresses = *addresses;
+        group.throughput(Throughput::Elements(*addresses as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(addresses), addresses, |b, _| {
+            b.iter(|| {
+                runtime
+                    .block_on(
+                        server
+                            .admin_client
+                            .delete_old_messages(DeleteOldMessagesRequest {}),
+                    )
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+    server.stop(&runtime);
+}
+
+fn concurrent_requests(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut server = BenchServer::start(&runtime);
+    let mut group = c.benchmark_group("concurrent_requests");
+    let addresses = server.store_messages(&runtime, STORED_ADDRESSES, 10);
+
+    // each iteration sends one get request per client at the same time, so the throughput with
+    // one client is the baseline of requests handled one at a time
+    for clients in [1, 4, 16].iter() {
+        group.throughput(Throughput::Elements(*clients as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(clients),
+            clients,
+            |b, clients| {
+                let mut i = 0;
+                b.iter(|| {
+                    let requests = (0..*clients).map(|_| {
+                        i = (i + 1) % addresses.len();
+                        let mut client = server.client.clone();
+                        let request = GetMessagesRequest {
+                            address: addresses[i].clone(),
+                        };
+                        async move { client.get_messages(request).await.unwrap() }
+                    });
+                    runtime.block_on(futures::future::join_all(requests))
+                })
+            },
+        );
+    }
+    group.finish();
+    server.stop(&runtime);
+}
+
+criterion_group!(
+    benches,
+    store_message,
+    get_messages,
+    delete_old_messages,
+    concurrent_requests
+);
+criterion_main!(benches);
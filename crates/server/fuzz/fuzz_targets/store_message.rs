@@ -0,0 +1,61 @@
+//! Stores arbitrary store message requests, and checks that accepted messages are returned after
+//! the address' messages in the order they were stored, and that rejected requests are invalid
+//! arguments that don't change the stored messages.
+
+#![no_main]
+
+use api::api::StoreMessageRequest;
+use chrono::prelude::*;
+use lazy_static::lazy_static;
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+use multisig_service::fuzzing::{FuzzStore, MAX_ADDRESS_SIZE};
+use prost::Message;
+use tonic::Code;
+
+// stored messages are not pruned by this target
+const MSG_RETENTION_DURATION: u64 = 60 * 60 * 24;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    /// encoded store message request
+    request: Vec<u8>,
+    /// when set, the message is created at the current time plus the offset in seconds, so not all
+    /// messages are rejected for being outside of the accepted time window
+    created_offset: Option<i32>,
+}
+
+lazy_static! {
+    // a single store keeps the messages of all inputs
+    static ref STORE: FuzzStore = FuzzStore::open(FuzzStore::temp_path(), MSG_RETENTION_DURATION);
+}
+
+fuzz_target!(|input: Input| {
+    let mut request = match StoreMessageRequest::decode(input.request.as_slice()) {
+        Ok(request) => request,
+        Err(_) => return,
+    };
+    if let (Some(user_msg), Some(offset)) = (request.user_message.as_mut(), input.created_offset) {
+        user_msg.created = (Utc::now().timestamp() + offset as i64) as u64;
+    }
+    let address = match request.user_message.as_ref() {
+        Some(user_msg) => user_msg.address.clone(),
+        None => vec![],
+    };
+
+    let stored = STORE.get_messages(&address).unwrap();
+    let res = STORE.store_message(request.clone());
+    let messages = STORE.get_messages(&address).unwrap();
+    match res {
+        Ok(()) => {
+            assert!(!address.is_empty() && address.len() <= MAX_ADDRESS_SIZE);
+            assert_eq!(messages.len(), stored.len() + 1);
+            assert_eq!(messages[..stored.len()], stored[..]);
+            assert_eq!(Some(&messages[stored.len()]), request.user_message.as_ref());
+        }
+        Err(status) => {
+            assert_eq!(status.code(), Code::InvalidArgument, "{}", status);
+            assert_eq!(messages, stored);
+        }
+    }
+});
@@ -0,0 +1,157 @@
+//! Opens a store with an arbitrary record stored for an address, and checks that reading the
+//! address returns the record's messages or an internal error, and that pruning quarantines the
+//! parts of the record that can't be read, deletes exactly the expired messages and keeps the
+//! addresses index in sync with the stored messages.
+
+#![no_main]
+
+use api::api::{TransactionType, UserMessage};
+use chrono::prelude::*;
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+use multisig_service::fuzzing::{FuzzStore, ALL_ADDRESSES_KEY};
+use prost::Message;
+use std::collections::HashSet;
+use std::path::Path;
+use tonic::Code;
+
+const ADDRESS: &[u8] = &[1; 32];
+const MSG_RETENTION_DURATION: u64 = 60 * 60;
+
+#[derive(Arbitrary, Debug)]
+enum StoredMessage {
+    Raw(Vec<u8>),
+    /// an encoded message created age seconds before the record is written
+    Message {
+        age: u16,
+        transaction_data: Vec<u8>,
+    },
+}
+
+/// An address record. The record is a bincode list of encoded messages when it is written by the
+/// server.
+#[derive(Arbitrary, Debug)]
+enum Record {
+    Raw(Vec<u8>),
+    Messages(Vec<StoredMessage>),
+}
+
+impl Record {
+    fn encode(&self) -> Vec<u8> {
+        let messages = match self {
+            Record::Raw(data) => return data.clone(),
+            Record::Messages(messages) => messages,
+        };
+        let now = Utc::now().timestamp() as u64;
+        let messages: Vec<Vec<u8>> = messages
+            .iter()
+            .map(|m| match m {
+                StoredMessage::Raw(data) => data.clone(),
+                StoredMessage::Message {
+                    age,
+                    transaction_data,
+                } => {
+                    let user_msg = UserMessage {
+                        net_id: 1,
+                        created: now.saturating_sub(*age as u64),
+                        address: ADDRESS.to_vec(),
+                        transaction_type: TransactionType::VaultWithdraw as i32,
+                        transaction_data: transaction_data.clone(),
+                        encrypted_transaction_data: None,
+                    };
+                    let mut user_msg_bin = Vec::with_capacity(user_msg.encoded_len());
+                    user_msg.encode(&mut user_msg_bin).unwrap();
+                    user_msg_bin
+                }
+            })
+            .collect();
+        bincode::serialize(&messages).unwrap()
+    }
+}
+
+/// Write a db at path with the record stored for ADDRESS. The db has no schema version, so it is
+/// opened as a db created before versioning and migrated.
+fn write_db(path: &Path, record: &[u8]) {
+    let db = rocksdb::DB::open_default(path).unwrap();
+    let addresses: HashSet<Vec<u8>> = vec![ADDRESS.to_vec()].into_iter().collect();
+    db.put(ALL_ADDRESSES_KEY, bincode::serialize(&addresses).unwrap())
+        .unwrap();
+    db.put(ADDRESS, record).unwrap();
+}
+
+/// Returns the messages that are not older than min_created
+fn unexpired(messages: &[UserMessage], min_created: u64) -> Vec<UserMessage> {
+    messages
+        .iter()
+        .filter(|m| m.created >= min_created)
+        .cloned()
+        .collect()
+}
+
+fuzz_target!(|record: Record| {
+    let record = record.encode();
+    let path = FuzzStore::temp_path();
+    write_db(&path, &record);
+    let start = Utc::now().timestamp() as u64;
+    let store = FuzzStore::open(path, MSG_RETENTION_DURATION);
+
+    // the record's encoded messages, or none when it can't be decoded
+    let encoded: Option<Vec<Vec<u8>>> = bincode::deserialize(&record).ok();
+    let (decoded, unreadable): (Vec<_>, Vec<_>) = encoded
+        .iter()
+        .flatten()
+        .map(|m| (UserMessage::decode(m.as_slice()), m))
+        .partition(|(m, _)| m.is_ok());
+    let decoded: Vec<UserMessage> = decoded.into_iter().map(|(m, _)| m.unwrap()).collect();
+    let unreadable: Vec<Vec<u8>> = unreadable.into_iter().map(|(_, m)| m.clone()).collect();
+    let readable = encoded.is_some() && unreadable.is_empty();
+
+    // a record encrypted with an unknown master key isn't corrupt, so it is kept and pruning fails
+    if store.is_unknown_key(&record) {
+        let status = store.delete_old_messages().unwrap_err();
+        assert_eq!(status.code(), Code::Internal, "{}", status);
+        assert!(store.quarantined().is_empty());
+        let status = store.get_messages(ADDRESS).unwrap_err();
+        assert_eq!(status.code(), Code::Internal, "{}", status);
+        return;
+    }
+
+    // all messages are returned before the record is pruned
+    match store.get_messages(ADDRESS) {
+        Ok(messages) => {
+            assert!(readable);
+            assert_eq!(messages, decoded);
+        }
+        Err(status) => {
+            assert_eq!(status.code(), Code::Internal, "{}", status);
+            assert!(!readable);
+        }
+    }
+
+    store.delete_old_messages().unwrap();
+    let end = store.server_time();
+
+    // the whole record is quarantined when it can't be decoded, otherwise its unreadable messages
+    let mut expected_quarantined = match encoded {
+        Some(_) => unreadable,
+        None => vec![record],
+    };
+    expected_quarantined.sort();
+    let mut quarantined = store.quarantined();
+    quarantined.sort();
+    assert_eq!(quarantined, expected_quarantined);
+
+    // messages created before the retention cutoff of a pruning are deleted
+    let messages = store.get_messages(ADDRESS).unwrap();
+    let min_created = start.saturating_sub(MSG_RETENTION_DURATION);
+    let max_created = end.saturating_sub(MSG_RETENTION_DURATION);
+    assert!((min_created..=max_created).any(|t| messages == unexpired(&decoded, t)));
+
+    // the index has the address only while it has messages
+    let addresses = store.addresses();
+    if messages.is_empty() {
+        assert!(addresses.is_empty());
+    } else {
+        assert_eq!(addresses, vec![(ADDRESS.to_vec(), messages.len() as u32)]);
+    }
+});
@@ -0,0 +1,56 @@
+use crate::encryption::load_secret;
+use anyhow::{anyhow, Result};
+use config::Config;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+pub(crate) const ADDRESS_HASH_SECRET_FILE_CONFIG_KEY_NAME: &str = "address_hash_secret_file";
+pub(crate) const ADDRESS_HASH_SECRET_ENV_VAR: &str = "MULTISIG_ADDRESS_HASH_SECRET";
+
+const SECRET_SIZE: usize = 32;
+// hmac input used to identify the secret without revealing it
+const SECRET_ID_INPUT: &[u8] = b"spacemesh-multisig-address-hash-secret-id";
+
+/// Maps addresses to db keys.
+/// When address hashing is enabled a key is an HMAC of the address with a server secret, so a db
+/// dump doesn't reveal which addresses have messages. Otherwise the address is used as the key.
+#[derive(Default)]
+pub(crate) struct AddressHasher {
+    secret: Option<Vec<u8>>,
+}
+
+impl AddressHasher {
+    pub(crate) fn from_config(config: &Config) -> Result<AddressHasher> {
+        Ok(AddressHasher {
+            secret: load_secret(
+                config,
+                ADDRESS_HASH_SECRET_FILE_CONFIG_KEY_NAME,
+                ADDRESS_HASH_SECRET_ENV_VAR,
+                SECRET_SIZE,
+            )?,
+        })
+    }
+
+    /// Returns the db key of an address
+    pub(crate) fn db_key(&self, address: &[u8]) -> Result<Vec<u8>> {
+        match self.secret.as_ref() {
+            Some(secret) => hmac(secret, address),
+            None => Ok(address.to_vec()),
+        }
+    }
+
+    /// Returns an id of the secret used to hash addresses. None when hashing is disabled.
+    pub(crate) fn secret_id(&self) -> Result<Option<Vec<u8>>> {
+        self.secret
+            .as_ref()
+            .map(|s| hmac(s, SECRET_ID_INPUT))
+            .transpose()
+    }
+}
+
+fn hmac(secret: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret)
+        .map_err(|_| anyhow!("invalid address hash secret"))?;
+    mac.update(data);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
@@ -0,0 +1,32 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, MutexGuard};
+
+// number of locks addresses are mapped to
+const ADDRESS_LOCK_STRIPES: usize = 256;
+
+/// Locks serializing the writes of an address, so writes of different addresses run
+/// concurrently. Addresses are mapped to a fixed number of locks by their db key hash, so
+/// a lock may be shared by a few addresses.
+pub(crate) struct AddressLocks {
+    stripes: Vec<Mutex<()>>,
+}
+
+impl Default for AddressLocks {
+    fn default() -> Self {
+        AddressLocks {
+            stripes: (0..ADDRESS_LOCK_STRIPES).map(|_| Mutex::new(())).collect(),
+        }
+    }
+}
+
+impl AddressLocks {
+    /// Lock the writes of an address db key until the returned guard is dropped
+    pub(crate) fn lock(&self, key: &[u8]) -> MutexGuard<'_, ()> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let stripe = &self.stripes[hasher.finish() as usize % self.stripes.len()];
+        // the lock guards no data, so a panic while it was held leaves nothing inconsistent
+        stripe.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,461 @@
+use crate::api::api::multi_sig_admin_server::MultiSigAdmin;
+use crate::backup::backup;
+use crate::export::{export_messages, import_messages};
+use crate::limits::{
+    MAX_ADDRESS_SIZE_CONFIG_KEY_NAME, MAX_ENVELOPE_RECIPIENTS_CONFIG_KEY_NAME,
+    MAX_TX_DATA_SIZE_CONFIG_KEY_NAME, TIME_WINDOW_CONFIG_KEY_NAME,
+};
+use crate::reload::{config_changes, config_values};
+use crate::server::{
+    DeleteOldMessages, DeleteQuarantinedRecord, GetConfig, GetDbStats, GetQuarantinedRecord,
+    ListAddresses, ListQuarantinedRecords, PurgeAddress, Server, SetConfig, Traced,
+    MSG_RETENTION_DUR_CONFIG_KEY_NAME,
+};
+use crate::service::{rpc_span, to_status, traced_rpc};
+use crate::settings::Settings;
+use api::api::{
+    BackupDbRequest, BackupDbResponse, DeleteOldMessagesRequest, DeleteOldMessagesResponse,
+    DeleteQuarantinedRecordRequest, DeleteQuarantinedRecordResponse, ExportMessagesRequest,
+    ExportMessagesResponse, GetConfigRequest, GetConfigResponse, GetDbStatsRequest,
+    GetDbStatsResponse, GetQuarantinedRecordRequest, GetQuarantinedRecordResponse,
+    ImportMessagesRequest, ImportMessagesResponse, ListAddressesRequest, ListAddressesResponse,
+    ListQuarantinedRecordsRequest, ListQuarantinedRecordsResponse, PurgeAddressRequest,
+    PurgeAddressResponse, UpdateConfigRequest, UpdateConfigResponse,
+};
+use std::path::Path;
+use tonic::{Request, Response, Status};
+use xactor::{Addr, Handler};
+
+pub(crate) const ADMIN_HOST_CONFIG_KEY_NAME: &str = "admin_host";
+pub(crate) const ADMIN_PORT_CONFIG_KEY_NAME: &str = "admin_port";
+
+/// AdminService implements MultiSigAdmin
+pub(crate) struct AdminService {
+    server: Addr<Server>,
+}
+
+impl AdminService {
+    pub(crate) fn new(server: Addr<Server>) -> AdminService {
+        info!("Multisig admin grpc service started");
+        AdminService { server }
+    }
+
+    // Call the server in the current span
+    async fn call<M, T>(&self, msg: M) -> Result<T, Status>
+    where
+        M: xactor::Message<Result = anyhow::Result<T>>,
+        Server: Handler<Traced<M>>,
+        T: Send + 'static,
+    {
+        self.server
+            .call(Traced::new(msg))
+            .await
+            .map_err(|e| Status::internal(format!("internal call error: {}", e)))?
+            .map_err(to_status)
+    }
+
+    async fn handle_update_config(
+        &self,
+        request: Request<UpdateConfigRequest>,
+    ) -> Result<Response<UpdateConfigResponse>, Status> {
+        let settings = request.into_inner().settings;
+        if let Some(key) = settings.keys().find(|k| !runtime_updatable(k)) {
+            return Err(Status::invalid_argument(format!(
+                "{} can't be updated at runtime",
+                key
+            )));
+        }
+
+        let current = self.call(GetConfig {}).await?;
+        let mut config = current.clone();
+        for (key, value) in settings {
+            config
+                .set(&key, value)
+                .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        }
+        Settings::from_config(&config)
+            .map_err(|e| Status::invalid_argument(format!("invalid config: {}", e)))?;
+        self.call(SetConfig(config.clone())).await?;
+
+        for change in config_changes(&current, &config).map_err(to_status)? {
+            info!("config changed by admin: {}", change);
+        }
+        Ok(Response::new(UpdateConfigResponse {}))
+    }
+}
+
+// Returns true for settings applied by the server on SetConfig. Other settings are applied on
+// startup or on config reload.
+fn runtime_updatable(key: &str) -> bool {
+    key == MSG_RETENTION_DUR_CONFIG_KEY_NAME
+        || key == MAX_ADDRESS_SIZE_CONFIG_KEY_NAME
+        || key == MAX_ENVELOPE_RECIPIENTS_CONFIG_KEY_NAME
+        || key == TIME_WINDOW_CONFIG_KEY_NAME
+        || key.starts_with(MAX_TX_DATA_SIZE_CONFIG_KEY_NAME)
+}
+
+#[tonic::async_trait]
+impl MultiSigAdmin for AdminService {
+    /// Lists stored addresses and their message counts
+    async fn list_addresses(
+        &self,
+        request: Request<ListAddressesRequest>,
+    ) -> Result<Response<ListAddressesResponse>, Status> {
+        let span = rpc_span("ListAddresses", &request, None);
+        traced_rpc("ListAddresses", span, async {
+            let addresses = self.call(ListAddresses {}).await?;
+            Ok(Response::new(ListAddressesResponse { addresses }))
+        })
+        .await
+    }
+
+    /// Returns db stats
+    async fn get_db_stats(
+        &self,
+        request: Request<GetDbStatsRequest>,
+    ) -> Result<Response<GetDbStatsResponse>, Status> {
+        let span = rpc_span("GetDbStats", &request, None);
+        traced_rpc("GetDbStats", span, async {
+            Ok(Response::new(self.call(GetDbStats {}).await?))
+        })
+        .await
+    }
+
+    /// Deletes old messages now
+    async fn delete_old_messages(
+        &self,
+        request: Request<DeleteOldMessagesRequest>,
+    ) -> Result<Response<DeleteOldMessagesResponse>, Status> {
+        let span = rpc_span("DeleteOldMessages", &request, None);
+        traced_rpc("DeleteOldMessages", span, async {
+            self.call(DeleteOldMessages {}).await?;
+            Ok(Response::new(DeleteOldMessagesResponse {}))
+        })
+        .await
+    }
+
+    /// Deletes all messages of an address
+    async fn purge_address(
+        &self,
+        request: Request<PurgeAddressRequest>,
+    ) -> Result<Response<PurgeAddressResponse>, Status> {
+        let span = rpc_span("PurgeAddress", &request, Some(&request.get_ref().address));
+        traced_rpc("PurgeAddress", span, async {
+            let deleted_messages = self
+                .call(PurgeAddress(request.into_inner().address))
+                .await?;
+            Ok(Response::new(PurgeAddressResponse {
+                deleted_messages: deleted_messages as u32,
+            }))
+        })
+        .await
+    }
+
+    /// Returns the server runtime config
+    async fn get_config(
+        &self,
+        request: Request<GetConfigRequest>,
+    ) -> Result<Response<GetConfigResponse>, Status> {
+        let span = rpc_span("GetConfig", &request, None);
+        traced_rpc("GetConfig", span, async {
+            let config = self.call(GetConfig {}).await?;
+            Ok(Response::new(GetConfigResponse {
+                settings: config_values(&config).map_err(to_status)?,
+            }))
+        })
+        .await
+    }
+
+    /// Updates runtime config settings
+    async fn update_config(
+        &self,
+        request: Request<UpdateConfigRequest>,
+    ) -> Result<Response<UpdateConfigResponse>, Status> {
+        let span = rpc_span("UpdateConfig", &request, None);
+        traced_rpc("UpdateConfig", span, self.handle_update_config(request)).await
+    }
+
+    /// Exports all stored messages to a file
+    async fn export_messages(
+        &self,
+        request: Request<ExportMessagesRequest>,
+    ) -> Result<Response<ExportMessagesResponse>, Status> {
+        let span = rpc_span("ExportMessages", &request, None);
+        traced_rpc("ExportMessages", span, async {
+            let path = request.into_inner().path;
+            let (address_count, message_count) = export_messages(&self.server, Path::new(&path))
+                .await
+                .map_err(to_status)?;
+            Ok(Response::new(ExportMessagesResponse {
+                address_count,
+                message_count,
+            }))
+        })
+        .await
+    }
+
+    /// Imports messages from an export file
+    async fn import_messages(
+        &self,
+        request: Request<ImportMessagesRequest>,
+    ) -> Result<Response<ImportMessagesResponse>, Status> {
+        let span = rpc_span("ImportMessages", &request, None);
+        traced_rpc("ImportMessages", span, async {
+            let path = request.into_inner().path;
+            let (imported_messages, skipped_messages) =
+                import_messages(&self.server, Path::new(&path))
+                    .await
+                    .map_err(to_status)?;
+            Ok(Response::new(ImportMessagesResponse {
+                imported_messages,
+                skipped_messages,
+            }))
+        })
+        .await
+    }
+
+    /// Backs up the db to the configured backup directory
+    async fn backup_db(
+        &self,
+        request: Request<BackupDbRequest>,
+    ) -> Result<Response<BackupDbResponse>, Status> {
+        let span = rpc_span("BackupDb", &request, None);
+        traced_rpc("BackupDb", span, async {
+            let config = self.call(GetConfig {}).await?;
+            let settings = Settings::from_config(&config).map_err(to_status)?;
+            let path = backup(
+                &self.server,
+                Path::new(&settings.backup_dir),
+                settings.backup_retention as usize,
+            )
+            .await
+            .map_err(to_status)?;
+            Ok(Response::new(BackupDbResponse {
+                path: path.display().to_string(),
+            }))
+        })
+        .await
+    }
+
+    /// Lists quarantined records without their data
+    async fn list_quarantined_records(
+        &self,
+        request: Request<ListQuarantinedRecordsRequest>,
+    ) -> Result<Response<ListQuarantinedRecordsResponse>, Status> {
+        let span = rpc_span("ListQuarantinedRecords", &request, None);
+        traced_rpc("ListQuarantinedRecords", span, async {
+            let records = self.call(ListQuarantinedRecords {}).await?;
+            Ok(Response::new(ListQuarantinedRecordsResponse { records }))
+        })
+        .await
+    }
+
+    /// Returns a quarantined record and its data
+    async fn get_quarantined_record(
+        &self,
+        request: Request<GetQuarantinedRecordRequest>,
+    ) -> Result<Response<GetQuarantinedRecordResponse>, Status> {
+        let span = rpc_span("GetQuarantinedRecord", &request, None);
+        traced_rpc("GetQuarantinedRecord", span, async {
+            let id = request.into_inner().id;
+            match self.call(GetQuarantinedRecord(id)).await? {
+                Some(record) => Ok(Response::new(GetQuarantinedRecordResponse {
+                    record: Some(record),
+                })),
+                None => Err(Status::not_found("no such quarantined record")),
+            }
+        })
+        .await
+    }
+
+    /// Deletes a quarantined record
+    async fn delete_quarantined_record(
+        &self,
+        request: Request<DeleteQuarantinedRecordRequest>,
+    ) -> Result<Response<DeleteQuarantinedRecordResponse>, Status> {
+        let span = rpc_span("DeleteQuarantinedRecord", &request, None);
+        traced_rpc("DeleteQuarantinedRecord", span, async {
+            let id = request.into_inner().id;
+            if !self.call(DeleteQuarantinedRecord(id)).await? {
+                return Err(Status::not_found("no such quarantined record"));
+            }
+            Ok(Response::new(DeleteQuarantinedRecordResponse {}))
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_default_config;
+    use crate::server::{DeleteDb, StoreMessage};
+    use crate::PORT_CONFIG_KEY_NAME;
+    use api::api::{AddressInfo, StoreMessageRequest, TransactionType, UserMessage};
+    use chrono::prelude::*;
+    use serial_test::*;
+    use std::collections::HashMap;
+    use tonic::Code;
+    use xactor::Actor;
+
+    fn update_request(key: &str, value: &str) -> Request<UpdateConfigRequest> {
+        let mut settings = HashMap::new();
+        settings.insert(key.to_string(), value.to_string());
+        Request::new(UpdateConfigRequest { settings })
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn admin_service() {
+        let _ = std::fs::remove_dir_all("./data_store");
+        let server = Server::default().start().await.unwrap();
+        server
+            .call(SetConfig(get_default_config()))
+            .await
+            .unwrap()
+            .unwrap();
+        let admin = AdminService::new(server.clone());
+
+        let address1 = vec![1; 32];
+        let address2 = vec![2; 32];
+        for address in [&address1, &address1, &address2] {
+            server
+                .call(StoreMessage(StoreMessageRequest {
+                    user_message: Some(UserMessage {
+                        net_id: 1,
+                        created: Utc::now().timestamp() as u64,
+                        address: address.clone(),
+                        transaction_type: TransactionType::VaultWithdraw as i32,
+                        transaction_data: vec![0; 32],
+                        encrypted_transaction_data: None,
+                    }),
+                }))
+                .await
+                .unwrap()
+                .unwrap();
+        }
+
+        let addresses = admin
+            .list_addresses(Request::new(ListAddressesRequest {}))
+            .await
+            .unwrap()
+            .into_inner()
+            .addresses;
+        assert_eq!(
+            addresses,
+            vec![
+                AddressInfo {
+                    address: address1.clone(),
+                    message_count: 2
+                },
+                AddressInfo {
+                    address: address2.clone(),
+                    message_count: 1
+                },
+            ]
+        );
+        let stats = admin
+            .get_db_stats(Request::new(GetDbStatsRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(stats.address_count, 2);
+        assert_eq!(stats.message_count, 3);
+
+        // purge an address
+        let purge =
+            |address: Vec<u8>| admin.purge_address(Request::new(PurgeAddressRequest { address }));
+        assert_eq!(
+            purge(address1.clone())
+                .await
+                .unwrap()
+                .into_inner()
+                .deleted_messages,
+            2
+        );
+        assert_eq!(
+            purge(address1).await.unwrap().into_inner().deleted_messages,
+            0
+        );
+        assert_eq!(
+            purge(b"all_addresses".to_vec()).await.unwrap_err().code(),
+            Code::InvalidArgument
+        );
+        let addresses = admin
+            .list_addresses(Request::new(ListAddressesRequest {}))
+            .await
+            .unwrap()
+            .into_inner()
+            .addresses;
+        assert_eq!(addresses.len(), 1);
+        assert_eq!(addresses[0].address, address2);
+
+        admin
+            .delete_old_messages(Request::new(DeleteOldMessagesRequest {}))
+            .await
+            .unwrap();
+        let quarantined = admin
+            .list_quarantined_records(Request::new(ListQuarantinedRecordsRequest {}))
+            .await
+            .unwrap()
+            .into_inner()
+            .records;
+        assert!(quarantined.is_empty());
+        let id = "00".to_string();
+        assert_eq!(
+            admin
+                .get_quarantined_record(Request::new(GetQuarantinedRecordRequest {
+                    id: id.clone()
+                }))
+                .await
+                .unwrap_err()
+                .code(),
+            Code::NotFound
+        );
+        assert_eq!(
+            admin
+                .delete_quarantined_record(Request::new(DeleteQuarantinedRecordRequest { id }))
+                .await
+                .unwrap_err()
+                .code(),
+            Code::NotFound
+        );
+
+        // update runtime config
+        admin
+            .update_config(update_request(MAX_ADDRESS_SIZE_CONFIG_KEY_NAME, "64"))
+            .await
+            .unwrap();
+        let settings = admin
+            .get_config(Request::new(GetConfigRequest {}))
+            .await
+            .unwrap()
+            .into_inner()
+            .settings;
+        assert_eq!(settings[MAX_ADDRESS_SIZE_CONFIG_KEY_NAME], "64");
+
+        // settings applied on startup and invalid settings are rejected
+        for (key, value) in [
+            (PORT_CONFIG_KEY_NAME, "7000"),
+            (MAX_ADDRESS_SIZE_CONFIG_KEY_NAME, "0"),
+            (MSG_RETENTION_DUR_CONFIG_KEY_NAME, "10"),
+            ("max_tx_data_size_unknown", "100"),
+        ] {
+            let status = admin
+                .update_config(update_request(key, value))
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), Code::InvalidArgument, "{}", key);
+        }
+        let settings = admin
+            .get_config(Request::new(GetConfigRequest {}))
+            .await
+            .unwrap()
+            .into_inner()
+            .settings;
+        assert_eq!(settings[MAX_ADDRESS_SIZE_CONFIG_KEY_NAME], "64");
+
+        // cleanup
+        server.call(DeleteDb {}).await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+use crate::server::{open_db_path, BackupDb, Server, DB_FILE_PATH};
+use anyhow::{Context as _, Result};
+use chrono::prelude::*;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use xactor::Addr;
+
+pub(crate) const BACKUP_DIR_CONFIG_KEY_NAME: &str = "backup_dir";
+pub(crate) const BACKUP_INTERVAL_CONFIG_KEY_NAME: &str = "backup_interval";
+pub(crate) const BACKUP_RETENTION_CONFIG_KEY_NAME: &str = "backup_retention";
+
+pub(crate) const DEFAULT_BACKUP_DIR: &str = "./backups";
+// scheduled backups are disabled by default
+pub(crate) const DEFAULT_BACKUP_INTERVAL_SECS: u64 = 0;
+pub(crate) const DEFAULT_BACKUP_RETENTION: u64 = 7;
+
+const BACKUP_NAME_PREFIX: &str = "backup-";
+
+/// Back up the db to a new timestamped directory in dir, and remove all but the latest retention
+/// backups. Returns the backup path.
+/// Backups are rocksdb checkpoints - consistent copies of the db taken while the server is
+/// running. Records are backed up as stored, so encrypted records are restored with the same
+/// master key.
+pub(crate) async fn backup(server: &Addr<Server>, dir: &Path, retention: usize) -> Result<PathBuf> {
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    let path = dir.join(format!(
+        "{}{}",
+        BACKUP_NAME_PREFIX,
+        Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+    ));
+    server.call(BackupDb(path.clone())).await??;
+    info!("db backed up to {}", path.display());
+    remove_old_backups(dir, retention)?;
+    Ok(path)
+}
+
+// Remove all but the latest retention backups in dir. Backup names sort by backup time.
+fn remove_old_backups(dir: &Path, retention: usize) -> Result<()> {
+    let mut backups: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            p.is_dir()
+                && p.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with(BACKUP_NAME_PREFIX))
+        })
+        .collect();
+    backups.sort();
+    let removed = backups.len().saturating_sub(retention);
+    for path in &backups[..removed] {
+        fs::remove_dir_all(path)?;
+        info!("removed old backup {}", path.display());
+    }
+    Ok(())
+}
+
+/// Replace the db with a copy of a backup. Must be called before the server is started.
+/// The backup is checked to be a readable db before the current db is replaced, and the current
+/// db is kept in a data_store.pre-restore-<time> directory.
+pub(crate) fn restore(backup: &Path) -> Result<()> {
+    let db_path = Path::new(DB_FILE_PATH);
+    let restoring = PathBuf::from(format!("{}.restoring", DB_FILE_PATH));
+    let _ = fs::remove_dir_all(&restoring);
+    copy_dir(backup, &restoring)
+        .with_context(|| format!("failed to copy backup {}", backup.display()))?;
+    open_db_path(&restoring).with_context(|| format!("invalid backup {}", backup.display()))?;
+
+    if db_path.exists() {
+        let previous = format!(
+            "{}.pre-restore-{}",
+            DB_FILE_PATH,
+            Utc::now().format("%Y%m%dT%H%M%SZ")
+        );
+        fs::rename(db_path, &previous)?;
+        info!("moved the current db to {}", previous);
+    }
+    fs::rename(&restoring, db_path)?;
+    info!("restored db from backup {}", backup.display());
+    Ok(())
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keep_latest_backups() {
+        let dir = std::env::temp_dir().join(format!(
+            "multisig_backups_{}",
+            hex::encode(rand::random::<[u8; 8]>())
+        ));
+        let names = [
+            "backup-20210101T000000.000Z",
+            "backup-20210102T000000.000Z",
+            "backup-20210103T000000.000Z",
+            "other",
+        ];
+        for name in names.iter() {
+            fs::create_dir_all(dir.join(name)).unwrap();
+        }
+
+        remove_old_backups(&dir, 2).unwrap();
+        let mut kept: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        kept.sort();
+        assert_eq!(kept, names[1..].to_vec());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
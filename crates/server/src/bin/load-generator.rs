@@ -0,0 +1,260 @@
+//! Load generator for the multisig message service.
+//! Sends concurrent StoreMessage and GetMessages requests to a running server and reports the
+//! request throughput and latency percentiles.
+
+use anyhow::{bail, Context, Result};
+use api::api::multi_sig_service_client::MultiSigServiceClient;
+use api::api::{GetMessagesRequest, StoreMessageRequest, TransactionType, UserMessage};
+use chrono::prelude::*;
+use clap::{App, Arg, ArgMatches};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tonic::transport::Channel;
+
+const DEFAULT_ENDPOINT: &str = "http://[::1]:6667";
+const ADDRESS_SIZE: usize = 32;
+// reported latency percentiles
+const PERCENTILES: &[usize] = &[50, 90, 99];
+
+/// Load generation settings
+struct LoadConfig {
+    endpoint: String,
+    // number of addresses messages are stored for and read from
+    addresses: usize,
+    // transaction data size of stored messages
+    message_size: usize,
+    // fraction of requests that are reads
+    read_ratio: f64,
+    requests: usize,
+    concurrency: usize,
+}
+
+impl LoadConfig {
+    fn from_args(args: &ArgMatches) -> Result<LoadConfig> {
+        let config = LoadConfig {
+            endpoint: args
+                .value_of("endpoint")
+                .unwrap_or(DEFAULT_ENDPOINT)
+                .to_string(),
+            addresses: parse_arg(args, "addresses")?,
+            message_size: parse_arg(args, "message-size")?,
+            read_ratio: parse_arg(args, "read-ratio")?,
+            requests: parse_arg(args, "requests")?,
+            concurrency: parse_arg(args, "concurrency")?,
+        };
+        if config.addresses == 0 || config.concurrency == 0 {
+            bail!("addresses and concurrency must be positive");
+        }
+        if !(0.0..=1.0).contains(&config.read_ratio) {
+            bail!("read ratio must be between 0 and 1");
+        }
+        Ok(config)
+    }
+}
+
+fn parse_arg<T: std::str::FromStr>(args: &ArgMatches, name: &str) -> Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let value = args.value_of(name).unwrap_or_default();
+    value
+        .parse()
+        .with_context(|| format!("invalid {}: {}", name, value))
+}
+
+#[derive(PartialEq)]
+enum RequestKind {
+    Store,
+    Get,
+}
+
+/// A completed request
+struct Sample {
+    kind: RequestKind,
+    latency: Duration,
+    ok: bool,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = App::new("Spacemesh Multisig Message Server load generator")
+        .about("Sends concurrent requests to a server and reports throughput and latency")
+        .arg(
+            Arg::with_name("endpoint")
+                .long("endpoint")
+                .takes_value(true)
+                .value_name("URL")
+                .help("server grpc endpoint (default: http://[::1]:6667)"),
+        )
+        .arg(
+            Arg::with_name("addresses")
+                .long("addresses")
+                .takes_value(true)
+                .default_value("100")
+                .help("number of addresses messages are stored for and read from"),
+        )
+        .arg(
+            Arg::with_name("message-size")
+                .long("message-size")
+                .takes_value(true)
+                .default_value("512")
+                .help("transaction data size of stored messages in bytes"),
+        )
+        .arg(
+            Arg::with_name("read-ratio")
+                .long("read-ratio")
+                .takes_value(true)
+                .default_value("0.8")
+                .help("fraction of requests that are GetMessages requests"),
+        )
+        .arg(
+            Arg::with_name("requests")
+                .long("requests")
+                .takes_value(true)
+                .default_value("10000")
+                .help("total number of requests"),
+        )
+        .arg(
+            Arg::with_name("concurrency")
+                .long("concurrency")
+                .takes_value(true)
+                .default_value("16")
+                .help("number of concurrent clients"),
+        )
+        .get_matches();
+    let config = LoadConfig::from_args(&args)?;
+
+    let client = MultiSigServiceClient::connect(config.endpoint.clone())
+        .await
+        .with_context(|| format!("failed to connect to {}", config.endpoint))?;
+    let addresses: Vec<Vec<u8>> = (0..config.addresses)
+        .map(|_| (0..ADDRESS_SIZE).map(|_| rand::random::<u8>()).collect())
+        .collect();
+
+    // store a message for each address first, so reads return messages
+    let mut setup_client = client.clone();
+    for address in addresses.iter() {
+        setup_client
+            .store_message(store_request(address, config.message_size))
+            .await
+            .context("failed to store a message")?;
+    }
+
+    println!(
+        "sending {} requests to {} with {} clients: {} addresses, {} bytes messages, {:.0}% reads",
+        config.requests,
+        config.endpoint,
+        config.concurrency,
+        config.addresses,
+        config.message_size,
+        config.read_ratio * 100.0
+    );
+    let config = Arc::new(config);
+    let addresses = Arc::new(addresses);
+    let sent = Arc::new(AtomicUsize::new(0));
+    let start = Instant::now();
+    let mut clients = vec![];
+    for _ in 0..config.concurrency {
+        let client = client.clone();
+        let config = config.clone();
+        let addresses = addresses.clone();
+        let sent = sent.clone();
+        clients.push(tokio::spawn(async move {
+            run_client(client, &config, &addresses, &sent).await
+        }));
+    }
+    let mut samples = vec![];
+    for client in clients {
+        samples.extend(client.await?);
+    }
+    let elapsed = start.elapsed();
+
+    println!("completed in {:.2}s", elapsed.as_secs_f64());
+    print_report("all", &samples, elapsed);
+    let (stores, gets): (Vec<Sample>, Vec<Sample>) = samples
+        .into_iter()
+        .partition(|s| s.kind == RequestKind::Store);
+    print_report("store", &stores, elapsed);
+    print_report("get", &gets, elapsed);
+    Ok(())
+}
+
+/// Send requests until the total number of requests was sent. Returns the completed requests.
+async fn run_client(
+    mut client: MultiSigServiceClient<Channel>,
+    config: &LoadConfig,
+    addresses: &[Vec<u8>],
+    sent: &AtomicUsize,
+) -> Vec<Sample> {
+    let mut samples = vec![];
+    while sent.fetch_add(1, Ordering::Relaxed) < config.requests {
+        let address = &addresses[rand::random::<usize>() % addresses.len()];
+        let start = Instant::now();
+        let (kind, ok) = if rand::random::<f64>() < config.read_ratio {
+            let request = GetMessagesRequest {
+                address: address.clone(),
+            };
+            (RequestKind::Get, client.get_messages(request).await.is_ok())
+        } else {
+            let request = store_request(address, config.message_size);
+            (
+                RequestKind::Store,
+                client.store_message(request).await.is_ok(),
+            )
+        };
+        samples.push(Sample {
+            kind,
+            latency: start.elapsed(),
+            ok,
+        });
+    }
+    samples
+}
+
+/// Returns a store request of a message created now
+fn store_request(address: &[u8], message_size: usize) -> StoreMessageRequest {
+    StoreMessageRequest {
+        user_message: Some(UserMessage {
+            net_id: 1,
+            created: Utc::now().timestamp() as u64,
+            address: address.to_vec(),
+            transaction_type: TransactionType::VaultWithdraw as i32,
+            transaction_data: (0..message_size).map(|_| rand::random::<u8>()).collect(),
+            encrypted_transaction_data: None,
+        }),
+    }
+}
+
+/// Print the throughput, error count and latency percentiles of requests
+fn print_report(name: &str, samples: &[Sample], elapsed: Duration) {
+    if samples.is_empty() {
+        return;
+    }
+    let mut latencies: Vec<Duration> = samples.iter().map(|s| s.latency).collect();
+    latencies.sort_unstable();
+    let errors = samples.iter().filter(|s| !s.ok).count();
+    let percentiles: Vec<String> = PERCENTILES
+        .iter()
+        .map(|p| format!("p{}: {:.2}ms", p, as_millis(percentile(&latencies, *p))))
+        .collect();
+    println!(
+        "{}: {} requests, {} errors, {:.0} req/s, {}, max: {:.2}ms",
+        name,
+        samples.len(),
+        errors,
+        samples.len() as f64 / elapsed.as_secs_f64(),
+        percentiles.join(", "),
+        as_millis(latencies[latencies.len() - 1])
+    );
+}
+
+/// Returns the p percentile of sorted latencies
+fn percentile(latencies: &[Duration], p: usize) -> Duration {
+    let rank = (p * latencies.len() + 99) / 100;
+    latencies[rank.clamp(1, latencies.len()) - 1]
+}
+
+fn as_millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
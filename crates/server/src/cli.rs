@@ -0,0 +1,191 @@
+use crate::backup::backup;
+use crate::export::{export_messages, import_messages};
+use crate::server::{
+    CompactDb, DeleteOldMessages, FlushDb, GetDbStats, GetMessages, ListAddresses, OldMessages,
+    Server, SetConfig, VerifyDb,
+};
+use crate::settings::Settings;
+use anyhow::{bail, Context as _, Result};
+use api::api::{GetMessagesRequest, TransactionType, UserMessage};
+use chrono::prelude::*;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use config::Config;
+use std::path::Path;
+use xactor::{Actor, Addr};
+
+pub(crate) const SERVE_COMMAND: &str = "serve";
+const STATS_COMMAND: &str = "stats";
+const LIST_ADDRESSES_COMMAND: &str = "list-addresses";
+const DUMP_COMMAND: &str = "dump";
+const PRUNE_COMMAND: &str = "prune";
+const COMPACT_COMMAND: &str = "compact";
+const VERIFY_COMMAND: &str = "verify";
+const EXPORT_COMMAND: &str = "export";
+const IMPORT_COMMAND: &str = "import";
+const BACKUP_COMMAND: &str = "backup";
+
+/// Returns the server subcommands. Commands other than serve are maintenance commands that open
+/// the data store directly, and can only run while the server isn't running.
+pub(crate) fn subcommands() -> Vec<App<'static, 'static>> {
+    vec![
+        SubCommand::with_name(SERVE_COMMAND).about("run the server. The default command"),
+        SubCommand::with_name(STATS_COMMAND)
+            .about("print the number of stored addresses and messages, and the db size"),
+        SubCommand::with_name(LIST_ADDRESSES_COMMAND)
+            .about("list stored addresses and their message counts"),
+        SubCommand::with_name(DUMP_COMMAND)
+            .about("print the stored messages of an address")
+            .arg(
+                Arg::with_name("address")
+                    .required(true)
+                    .help("hex encoded address"),
+            ),
+        SubCommand::with_name(PRUNE_COMMAND)
+            .about("delete messages older than the retention duration")
+            .arg(
+                Arg::with_name("dry-run")
+                    .long("dry-run")
+                    .help("print the number of old messages without deleting them"),
+            ),
+        SubCommand::with_name(COMPACT_COMMAND).about("compact the db files"),
+        SubCommand::with_name(VERIFY_COMMAND)
+            .about("check that all stored records are readable and indexed"),
+        SubCommand::with_name(EXPORT_COMMAND)
+            .about("export all stored messages to a file")
+            .arg(
+                Arg::with_name("file")
+                    .required(true)
+                    .help("export file path"),
+            ),
+        SubCommand::with_name(IMPORT_COMMAND)
+            .about("import messages from an export file")
+            .arg(
+                Arg::with_name("file")
+                    .required(true)
+                    .help("export file path"),
+            ),
+        SubCommand::with_name(BACKUP_COMMAND).about("back up the db to the backup directory"),
+    ]
+}
+
+/// Run a maintenance command on the data store with the server config
+pub(crate) async fn run(
+    command: &str,
+    args: &ArgMatches<'_>,
+    config: Config,
+    settings: Settings,
+) -> Result<()> {
+    let mut server = Server::default()
+        .start()
+        .await
+        .context("failed to open the data store - is the server running?")?;
+    server.call(SetConfig(config)).await??;
+
+    let res = run_command(&server, command, args, &settings).await;
+
+    server.call(FlushDb {}).await??;
+    server.stop(None)?;
+    server.wait_for_stop().await;
+    res
+}
+
+async fn run_command(
+    server: &Addr<Server>,
+    command: &str,
+    args: &ArgMatches<'_>,
+    settings: &Settings,
+) -> Result<()> {
+    match command {
+        STATS_COMMAND => {
+            let stats = server.call(GetDbStats {}).await??;
+            println!("addresses: {}", stats.address_count);
+            println!("messages: {}", stats.message_count);
+            println!("db size: {} bytes", stats.db_size);
+        }
+        LIST_ADDRESSES_COMMAND => {
+            for a in server.call(ListAddresses {}).await?? {
+                println!("{} {}", hex::encode(&a.address), a.message_count);
+            }
+        }
+        DUMP_COMMAND => {
+            let address = hex::decode(args.value_of("address").unwrap_or_default())
+                .context("invalid address - expected hex")?;
+            for m in server
+                .call(GetMessages(GetMessagesRequest { address }))
+                .await??
+            {
+                println!("{}", format_message(&m));
+            }
+        }
+        PRUNE_COMMAND => {
+            let (messages, addresses) = server.call(OldMessages {}).await??;
+            if args.is_present("dry-run") {
+                println!(
+                    "{} old messages and {} addresses would be deleted",
+                    messages, addresses
+                );
+            } else {
+                server.call(DeleteOldMessages {}).await??;
+                println!(
+                    "deleted {} old messages and {} addresses",
+                    messages, addresses
+                );
+            }
+        }
+        COMPACT_COMMAND => {
+            let before = server.call(GetDbStats {}).await??.db_size;
+            server.call(CompactDb {}).await??;
+            let after = server.call(GetDbStats {}).await??.db_size;
+            println!("compacted db: {} -> {} bytes", before, after);
+        }
+        VERIFY_COMMAND => {
+            let problems = server.call(VerifyDb {}).await??;
+            for problem in problems.iter() {
+                println!("{}", problem);
+            }
+            if !problems.is_empty() {
+                bail!("found {} problems", problems.len());
+            }
+            println!("no problems found");
+        }
+        EXPORT_COMMAND => {
+            let path = Path::new(args.value_of("file").unwrap_or_default());
+            let (addresses, messages) = export_messages(server, path).await?;
+            println!("exported {} messages of {} addresses", messages, addresses);
+        }
+        IMPORT_COMMAND => {
+            let path = Path::new(args.value_of("file").unwrap_or_default());
+            let (imported, skipped) = import_messages(server, path).await?;
+            println!("imported {} messages, skipped {}", imported, skipped);
+        }
+        BACKUP_COMMAND => {
+            let dir = Path::new(&settings.backup_dir);
+            let path = backup(server, dir, settings.backup_retention as usize).await?;
+            println!("backed up db to {}", path.display());
+        }
+        other => bail!("unknown command: {}", other),
+    }
+    Ok(())
+}
+
+// Format a message as a single line
+fn format_message(m: &UserMessage) -> String {
+    let created = Utc
+        .timestamp_opt(m.created as i64, 0)
+        .single()
+        .map_or_else(|| m.created.to_string(), |t| t.to_rfc3339());
+    let transaction_type = TransactionType::from_i32(m.transaction_type)
+        .map_or_else(|| m.transaction_type.to_string(), |t| format!("{:?}", t));
+    let data = match m.encrypted_transaction_data.as_ref() {
+        Some(envelope) => format!(
+            "encrypted_transaction_data: {} bytes to {} recipients",
+            envelope.ciphertext.len(),
+            envelope.recipients.len()
+        ),
+        None => format!("transaction_data: {}", hex::encode(&m.transaction_data)),
+    };
+    format!(
+        "created: {}, net_id: {}, transaction_type: {}, {}",
+        created, m.net_id, transaction_type, data
+    )
+}
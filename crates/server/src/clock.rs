@@ -0,0 +1,39 @@
+use chrono::prelude::*;
+
+/// The source of the current time used to validate and prune messages
+pub(crate) trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The system clock
+#[derive(Default)]
+pub(crate) struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only changes when it is set or advanced
+#[cfg(test)]
+pub(crate) struct MockClock(std::sync::Mutex<DateTime<Utc>>);
+
+#[cfg(test)]
+impl MockClock {
+    /// Returns a mock clock set to the current system time
+    pub(crate) fn new() -> MockClock {
+        MockClock(std::sync::Mutex::new(Utc::now()))
+    }
+
+    pub(crate) fn advance(&self, secs: i64) {
+        *self.0.lock().unwrap() += chrono::Duration::seconds(secs);
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock().unwrap()
+    }
+}
@@ -0,0 +1,201 @@
+use anyhow::{anyhow, bail, Result};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use config::Config;
+use hkdf::Hkdf;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::env;
+
+pub(crate) const MASTER_KEY_FILE_CONFIG_KEY_NAME: &str = "master_key_file";
+pub(crate) const PREV_MASTER_KEY_FILE_CONFIG_KEY_NAME: &str = "previous_master_key_file";
+pub(crate) const MASTER_KEY_ENV_VAR: &str = "MULTISIG_MASTER_KEY";
+pub(crate) const PREV_MASTER_KEY_ENV_VAR: &str = "MULTISIG_PREVIOUS_MASTER_KEY";
+
+const MASTER_KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 12;
+const KEY_ID_SIZE: usize = 4;
+// prefix of encrypted records. Can't be the start of a bincode encoded record as it would encode a
+// collection length of over 23 million items.
+const RECORD_MAGIC: &[u8] = b"mse\x01";
+const HEADER_SIZE: usize = RECORD_MAGIC.len() + KEY_ID_SIZE + NONCE_SIZE;
+const DATA_KEY_INFO: &[u8] = b"spacemesh-multisig-data-key-v1";
+
+/// A data key derived from a master key
+struct DataKey {
+    // identifies the master key a record was encrypted with
+    id: [u8; KEY_ID_SIZE],
+    cipher: ChaCha20Poly1305,
+}
+
+impl DataKey {
+    fn from_master_key(master_key: &[u8]) -> Result<DataKey> {
+        let mut id = [0u8; KEY_ID_SIZE];
+        id.copy_from_slice(&Sha256::digest(master_key)[..KEY_ID_SIZE]);
+
+        let mut key = [0u8; MASTER_KEY_SIZE];
+        Hkdf::<Sha256>::new(None, master_key)
+            .expand(DATA_KEY_INFO, &mut key)
+            .map_err(|_| anyhow!("failed to derive data key"))?;
+
+        Ok(DataKey {
+            id,
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&key)),
+        })
+    }
+}
+
+/// Encrypts and decrypts values stored in the db.
+/// Records are written with the current key and can be read with the current or the previous key,
+/// so the master key can be rotated while records encrypted with the old key are re-encrypted.
+#[derive(Default)]
+pub(crate) struct DataCipher {
+    current: Option<DataKey>,
+    previous: Option<DataKey>,
+}
+
+impl DataCipher {
+    /// Create a cipher from the master keys in the config or the environment.
+    /// Encryption at rest is disabled when no master key is provided.
+    pub(crate) fn from_config(config: &Config) -> Result<DataCipher> {
+        let current = load_secret(
+            config,
+            MASTER_KEY_FILE_CONFIG_KEY_NAME,
+            MASTER_KEY_ENV_VAR,
+            MASTER_KEY_SIZE,
+        )?;
+        let previous = load_secret(
+            config,
+            PREV_MASTER_KEY_FILE_CONFIG_KEY_NAME,
+            PREV_MASTER_KEY_ENV_VAR,
+            MASTER_KEY_SIZE,
+        )?;
+
+        if current.is_none() && previous.is_some() {
+            bail!("a previous master key was provided without a current master key")
+        }
+
+        Ok(DataCipher {
+            current: current.map(|k| DataKey::from_master_key(&k)).transpose()?,
+            previous: previous.map(|k| DataKey::from_master_key(&k)).transpose()?,
+        })
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Encrypt a value stored under key. The value is bound to the key so it can't be moved to
+    /// another key. Returns the value as is when encryption is disabled.
+    pub(crate) fn encrypt(&self, key: &[u8], value: &[u8]) -> Result<Vec<u8>> {
+        let data_key = match self.current.as_ref() {
+            Some(k) => k,
+            None => return Ok(value.to_vec()),
+        };
+
+        let mut nonce = [0u8; NONCE_SIZE];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = data_key
+            .cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: value,
+                    aad: key,
+                },
+            )
+            .map_err(|_| anyhow!("failed to encrypt record"))?;
+
+        let mut record = Vec::with_capacity(HEADER_SIZE + ciphertext.len());
+        record.extend_from_slice(RECORD_MAGIC);
+        record.extend_from_slice(&data_key.id);
+        record.extend_from_slice(&nonce);
+        record.extend_from_slice(&ciphertext);
+        Ok(record)
+    }
+
+    /// Returns true if a record is encrypted with a master key that isn't configured. Such a
+    /// record can't be decrypted but isn't corrupt.
+    pub(crate) fn is_unknown_key(&self, record: &[u8]) -> bool {
+        if !is_encrypted(record) || record.len() < HEADER_SIZE {
+            return false;
+        }
+        let key_id = &record[RECORD_MAGIC.len()..RECORD_MAGIC.len() + KEY_ID_SIZE];
+        !self
+            .current
+            .iter()
+            .chain(self.previous.iter())
+            .any(|k| k.id == key_id)
+    }
+
+    /// Decrypt a value stored under key. Records written before encryption was enabled are
+    /// returned as is.
+    pub(crate) fn decrypt(&self, key: &[u8], record: &[u8]) -> Result<Vec<u8>> {
+        if !is_encrypted(record) {
+            return Ok(record.to_vec());
+        }
+        if record.len() < HEADER_SIZE {
+            bail!("encrypted record is too short")
+        }
+
+        let key_id = &record[RECORD_MAGIC.len()..RECORD_MAGIC.len() + KEY_ID_SIZE];
+        let data_key = self
+            .current
+            .iter()
+            .chain(self.previous.iter())
+            .find(|k| k.id == key_id)
+            .ok_or_else(|| anyhow!("record is encrypted with an unknown master key"))?;
+
+        let nonce = &record[RECORD_MAGIC.len() + KEY_ID_SIZE..HEADER_SIZE];
+        data_key
+            .cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: &record[HEADER_SIZE..],
+                    aad: key,
+                },
+            )
+            .map_err(|_| anyhow!("failed to decrypt record"))
+    }
+
+    /// Returns true if the record isn't encrypted with the current key
+    pub(crate) fn needs_reencryption(&self, record: &[u8]) -> bool {
+        match self.current.as_ref() {
+            Some(k) => {
+                !is_encrypted(record)
+                    || record.len() < HEADER_SIZE
+                    || record[RECORD_MAGIC.len()..RECORD_MAGIC.len() + KEY_ID_SIZE] != k.id
+            }
+            None => false,
+        }
+    }
+}
+
+fn is_encrypted(record: &[u8]) -> bool {
+    record.starts_with(RECORD_MAGIC)
+}
+
+/// Read a hex encoded secret from the file set in the config, or from an env var.
+/// Returns None when neither is set.
+pub(crate) fn load_secret(
+    config: &Config,
+    file_key: &str,
+    env_var: &str,
+    size: usize,
+) -> Result<Option<Vec<u8>>> {
+    let hex_secret = match config.get_str(file_key) {
+        Ok(path) if !path.is_empty() => std::fs::read_to_string(&path)
+            .map_err(|e| anyhow!("failed to read secret file {}: {}", path, e))?,
+        _ => match env::var(env_var) {
+            Ok(k) => k,
+            Err(_) => return Ok(None),
+        },
+    };
+    let secret =
+        hex::decode(hex_secret.trim()).map_err(|_| anyhow!("{} must be hex encoded", file_key))?;
+    if secret.len() != size {
+        bail!("{} must be {} bytes", file_key, size)
+    }
+    Ok(Some(secret))
+}
@@ -0,0 +1,219 @@
+use crate::server::{GetMessages, ImportRecord, ListAddresses, Server, SERVER_VERSION};
+use anyhow::{bail, Context as _, Result};
+use api::api::{ExportHeader, ExportRecord, GetMessagesRequest};
+use chrono::prelude::*;
+use prost::Message;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use xactor::Addr;
+
+/// Version of the export file format
+pub(crate) const EXPORT_FORMAT_VERSION: u32 = 1;
+
+/// Export all stored messages to a file, replacing an existing file.
+/// The export is written to a temp file that is renamed when the export completes, so a failed
+/// export doesn't leave a partial file.
+/// Returns the number of exported addresses and messages.
+pub(crate) async fn export_messages(server: &Addr<Server>, path: &Path) -> Result<(u64, u64)> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = Path::new(&tmp_path);
+    let file = fs::File::create(tmp_path)
+        .with_context(|| format!("failed to create {}", tmp_path.display()))?;
+    let mut writer = BufWriter::new(file);
+
+    write_record(
+        &mut writer,
+        &ExportHeader {
+            version: EXPORT_FORMAT_VERSION,
+            server_version: SERVER_VERSION.to_string(),
+            created: Utc::now().timestamp() as u64,
+        },
+    )?;
+    let mut address_count = 0;
+    let mut message_count = 0;
+    for info in server.call(ListAddresses {}).await?? {
+        let user_messages = server
+            .call(GetMessages(GetMessagesRequest {
+                address: info.address.clone(),
+            }))
+            .await??;
+        address_count += 1;
+        message_count += user_messages.len() as u64;
+        write_record(
+            &mut writer,
+            &ExportRecord {
+                address: info.address,
+                user_messages,
+            },
+        )?;
+    }
+    writer.into_inner()?.sync_all()?;
+    fs::rename(tmp_path, path)?;
+
+    info!(
+        "exported {} messages of {} addresses to {}",
+        message_count,
+        address_count,
+        path.display()
+    );
+    Ok((address_count, message_count))
+}
+
+/// Import messages from an export file. Messages are validated with the server limits, and
+/// expired and already stored messages are skipped.
+/// Returns the number of imported and skipped messages.
+pub(crate) async fn import_messages(server: &Addr<Server>, path: &Path) -> Result<(u64, u64)> {
+    let data = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let mut buf = data.as_slice();
+    let header =
+        ExportHeader::decode_length_delimited(&mut buf).context("invalid export file header")?;
+    if header.version != EXPORT_FORMAT_VERSION {
+        bail!("unsupported export format version: {}", header.version);
+    }
+
+    let mut imported_messages = 0;
+    let mut skipped_messages = 0;
+    while !buf.is_empty() {
+        let record = ExportRecord::decode_length_delimited(&mut buf)
+            .context("invalid export file record")?;
+        let (imported, skipped) = server.call(ImportRecord(record)).await??;
+        imported_messages += imported as u64;
+        skipped_messages += skipped as u64;
+    }
+
+    info!(
+        "imported {} messages from {} exported by server version {}. skipped {} messages",
+        imported_messages,
+        path.display(),
+        header.server_version,
+        skipped_messages
+    );
+    Ok((imported_messages, skipped_messages))
+}
+
+fn write_record(writer: &mut impl Write, record: &impl Message) -> Result<()> {
+    let mut buf = Vec::with_capacity(record.encoded_len() + 10);
+    record.encode_length_delimited(&mut buf)?;
+    writer.write_all(&buf)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address_hash::ADDRESS_HASH_SECRET_FILE_CONFIG_KEY_NAME;
+    use crate::get_default_config;
+    use crate::server::{DeleteDb, SetConfig, StoreMessage};
+    use api::api::{StoreMessageRequest, TransactionType, UserMessage};
+    use api::api_extensions::EnvelopeKeyPair;
+    use serial_test::*;
+    use xactor::Actor;
+
+    fn user_message(address: &[u8]) -> UserMessage {
+        UserMessage {
+            net_id: 1,
+            created: Utc::now().timestamp() as u64,
+            address: address.to_vec(),
+            transaction_type: TransactionType::VaultWithdraw as i32,
+            transaction_data: (0..100).map(|_| rand::random::<u8>()).collect(),
+            encrypted_transaction_data: None,
+        }
+    }
+
+    async fn get_messages(server: &Addr<Server>, address: &[u8]) -> Vec<UserMessage> {
+        server
+            .call(GetMessages(GetMessagesRequest {
+                address: address.to_vec(),
+            }))
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn export_import_round_trip() {
+        let _ = std::fs::remove_dir_all("./data_store");
+        let server = Server::default().start().await.unwrap();
+        server
+            .call(SetConfig(get_default_config()))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let address1 = vec![1; 32];
+        let address2 = vec![2; 32];
+        let alice = EnvelopeKeyPair::generate();
+        let encrypted = UserMessage::new_encrypted(
+            1,
+            Utc::now().timestamp() as u64,
+            address2.clone(),
+            TransactionType::CoinSpend,
+            &[1; 64],
+            &[alice.public_key()],
+        )
+        .unwrap();
+        for m in [user_message(&address1), user_message(&address1), encrypted] {
+            server
+                .call(StoreMessage(StoreMessageRequest {
+                    user_message: Some(m),
+                }))
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        let messages1 = get_messages(&server, &address1).await;
+        let messages2 = get_messages(&server, &address2).await;
+
+        let path = std::env::temp_dir().join(format!(
+            "multisig_export_{}",
+            hex::encode(rand::random::<[u8; 8]>())
+        ));
+        let counts = export_messages(&server, &path).await.unwrap();
+        assert_eq!(counts, (2, 3));
+
+        // import to an empty db with hashed address keys
+        let mut server = server;
+        server.stop(None).unwrap();
+        server.wait_for_stop().await;
+        let _ = std::fs::remove_dir_all("./data_store");
+        let server = Server::default().start().await.unwrap();
+        let secret = std::env::temp_dir().join("multisig_export_test_secret");
+        std::fs::write(&secret, hex::encode([7u8; 32])).unwrap();
+        let mut config = get_default_config();
+        config
+            .set(
+                ADDRESS_HASH_SECRET_FILE_CONFIG_KEY_NAME,
+                secret.to_str().unwrap(),
+            )
+            .unwrap();
+        server.call(SetConfig(config)).await.unwrap().unwrap();
+        assert!(get_messages(&server, &address1).await.is_empty());
+
+        assert_eq!(import_messages(&server, &path).await.unwrap(), (3, 0));
+        assert_eq!(get_messages(&server, &address1).await, messages1);
+        assert_eq!(get_messages(&server, &address2).await, messages2);
+
+        // stored messages are not imported again
+        assert_eq!(import_messages(&server, &path).await.unwrap(), (0, 3));
+        assert_eq!(get_messages(&server, &address1).await, messages1);
+
+        // unsupported versions are rejected
+        let mut data = vec![];
+        ExportHeader {
+            version: EXPORT_FORMAT_VERSION + 1,
+            server_version: SERVER_VERSION.to_string(),
+            created: 0,
+        }
+        .encode_length_delimited(&mut data)
+        .unwrap();
+        std::fs::write(&path, data).unwrap();
+        assert!(import_messages(&server, &path).await.is_err());
+
+        // cleanup
+        let _ = std::fs::remove_file(&path);
+        server.call(DeleteDb {}).await.unwrap().unwrap();
+    }
+}
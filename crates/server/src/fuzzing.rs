@@ -0,0 +1,106 @@
+//! In-process entry points of the fuzz targets. Requests are handled by the message store as the
+//! grpc services handle them, and errors are mapped to the grpc status the services return.
+
+use crate::encryption::DataCipher;
+use crate::server::{MessageStore, MSG_RETENTION_DUR_CONFIG_KEY_NAME};
+use crate::service::to_status;
+use api::api::{GetMessagesRequest, StoreMessageRequest, UserMessage};
+use config::Config;
+use std::path::PathBuf;
+use tonic::Status;
+
+/// Db key of the addresses index
+pub const ALL_ADDRESSES_KEY: &[u8] = crate::server::ALL_ADDRESSES_KEY;
+/// Max address size in bytes of a store opened with the default limits
+pub const MAX_ADDRESS_SIZE: usize = crate::limits::DEFAULT_MAX_ADDRESS_SIZE_BYTES;
+
+/// A message store with a db in a temp dir. The dir is removed when the store is dropped.
+pub struct FuzzStore {
+    store: MessageStore,
+    cipher: DataCipher,
+    path: PathBuf,
+}
+
+impl FuzzStore {
+    /// Returns a new temp path for a db
+    pub fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "multisig-server-fuzz-{}-{}",
+            std::process::id(),
+            rand::random::<u32>()
+        ))
+    }
+
+    /// Open the db at path as the server opens it on startup, creating it if it doesn't exist.
+    /// A db written by a fuzz target is migrated to the current schema version.
+    pub fn open(path: PathBuf, msg_retention_duration: u64) -> FuzzStore {
+        let mut config = Config::default();
+        config
+            .set(
+                MSG_RETENTION_DUR_CONFIG_KEY_NAME,
+                msg_retention_duration as i64,
+            )
+            .unwrap();
+        let store = MessageStore::default();
+        store.open(&path).unwrap();
+        store.configure(&config).unwrap();
+        FuzzStore {
+            store,
+            cipher: DataCipher::from_config(&config).unwrap(),
+            path,
+        }
+    }
+
+    pub fn store_message(&self, request: StoreMessageRequest) -> Result<(), Status> {
+        self.store.store_message(request).map_err(to_status)
+    }
+
+    pub fn get_messages(&self, address: &[u8]) -> Result<Vec<UserMessage>, Status> {
+        let request = GetMessagesRequest {
+            address: address.to_vec(),
+        };
+        self.store.get_messages(request).map_err(to_status)
+    }
+
+    pub fn delete_old_messages(&self) -> Result<(), Status> {
+        self.store.delete_old_messages().map_err(to_status)
+    }
+
+    /// Returns the stored addresses and their message counts
+    pub fn addresses(&self) -> Vec<(Vec<u8>, u32)> {
+        self.store
+            .list_addresses()
+            .unwrap()
+            .into_iter()
+            .map(|a| (a.address, a.message_count))
+            .collect()
+    }
+
+    /// Returns the data of the quarantined records
+    pub fn quarantined(&self) -> Vec<Vec<u8>> {
+        self.store
+            .quarantined_records()
+            .unwrap()
+            .into_iter()
+            .map(|r| r.data)
+            .collect()
+    }
+
+    /// Returns the server time in seconds since epoch
+    pub fn server_time(&self) -> u64 {
+        self.store.server_info().server_time
+    }
+
+    /// Returns true if a record is encrypted with a master key the store doesn't have. Such a
+    /// record isn't corrupt, so it is kept rather than quarantined.
+    pub fn is_unknown_key(&self, record: &[u8]) -> bool {
+        self.cipher.is_unknown_key(record)
+    }
+}
+
+impl Drop for FuzzStore {
+    fn drop(&mut self) {
+        self.store.set_db(None);
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,177 @@
+use crate::server::{CheckDb, Server};
+use crate::service::GrpcService;
+use api::api::multi_sig_service_server::MultiSigServiceServer;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tonic::transport::NamedService;
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
+use xactor::Addr;
+
+// the empty service name reports the overall server health
+const SERVER_HEALTH_SERVICE_NAME: &str = "";
+
+#[derive(Default)]
+struct HealthState {
+    db_ok: bool,
+    // set to false when the last db cleanup task run failed
+    cleanup_ok: bool,
+}
+
+/// Reports the server health over the grpc.health.v1.Health service.
+/// The server is serving once the db is checked to be available, and is not serving while the
+/// db is unavailable or the last db cleanup failed.
+#[derive(Clone)]
+pub(crate) struct HealthMonitor {
+    reporter: HealthReporter,
+    state: Arc<Mutex<HealthState>>,
+}
+
+impl HealthMonitor {
+    /// Create a monitor reporting NOT_SERVING until the db is checked
+    pub(crate) async fn new(reporter: HealthReporter) -> HealthMonitor {
+        let monitor = HealthMonitor {
+            reporter,
+            state: Arc::new(Mutex::new(HealthState {
+                db_ok: false,
+                cleanup_ok: true,
+            })),
+        };
+        monitor.report(ServingStatus::NotServing).await;
+        monitor
+    }
+
+    /// Check that the db is available and update the health status
+    pub(crate) async fn check_db(&self, server: &Addr<Server>) {
+        let db_ok = match server.call(CheckDb {}).await {
+            Ok(Ok(())) => true,
+            Ok(Err(e)) => {
+                error!("db health check failed: {}", e);
+                false
+            }
+            Err(e) => {
+                error!("failed to call service method: {}", e);
+                false
+            }
+        };
+        self.state.lock().await.db_ok = db_ok;
+        self.update().await;
+    }
+
+    /// Update the health status with the result of a db cleanup task run
+    pub(crate) async fn set_cleanup_status(&self, ok: bool) {
+        self.state.lock().await.cleanup_ok = ok;
+        self.update().await;
+    }
+
+    async fn update(&self) {
+        let status = {
+            let state = self.state.lock().await;
+            if state.db_ok && state.cleanup_ok {
+                ServingStatus::Serving
+            } else {
+                ServingStatus::NotServing
+            }
+        };
+        self.report(status).await;
+    }
+
+    async fn report(&self, status: ServingStatus) {
+        let mut reporter = self.reporter.clone();
+        reporter
+            .set_service_status(SERVER_HEALTH_SERVICE_NAME, status)
+            .await;
+        reporter
+            .set_service_status(
+                <MultiSigServiceServer<GrpcService> as NamedService>::NAME,
+                status,
+            )
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_default_config;
+    use crate::server::{DeleteDb, SetConfig};
+    use serial_test::*;
+    use tonic_health::proto::health_check_response::ServingStatus as ProtoServingStatus;
+    use tonic_health::proto::health_client::HealthClient;
+    use tonic_health::proto::HealthCheckRequest;
+    use xactor::Actor;
+
+    async fn check(client: &mut HealthClient<tonic::transport::Channel>, service: &str) -> i32 {
+        client
+            .check(HealthCheckRequest {
+                service: service.to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .status
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn report_health() {
+        let (reporter, health_service) = tonic_health::server::health_reporter();
+        let monitor = HealthMonitor::new(reporter).await;
+
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addr = format!("127.0.0.1:{}", port).parse().unwrap();
+        tokio::spawn(async move {
+            tonic::transport::Server::builder()
+                .add_service(health_service)
+                .serve(addr)
+                .await
+                .unwrap();
+        });
+
+        let endpoint = format!("http://127.0.0.1:{}", port);
+        let mut client = loop {
+            match HealthClient::connect(endpoint.clone()).await {
+                Ok(client) => break client,
+                Err(_) => tokio::time::sleep(tokio::time::Duration::from_millis(10)).await,
+            }
+        };
+        let service_name = <MultiSigServiceServer<GrpcService> as NamedService>::NAME;
+
+        // not serving until the db was checked
+        let not_serving = ProtoServingStatus::NotServing as i32;
+        let serving = ProtoServingStatus::Serving as i32;
+        assert_eq!(check(&mut client, "").await, not_serving);
+        assert_eq!(check(&mut client, service_name).await, not_serving);
+
+        let _ = std::fs::remove_dir_all("./data_store");
+        let server = Server::default().start().await.unwrap();
+        server
+            .call(SetConfig(get_default_config()))
+            .await
+            .unwrap()
+            .unwrap();
+        monitor.check_db(&server).await;
+        assert_eq!(check(&mut client, "").await, serving);
+        assert_eq!(check(&mut client, service_name).await, serving);
+
+        // a failed cleanup task makes the server not serving until cleanup succeeds
+        monitor.set_cleanup_status(false).await;
+        assert_eq!(check(&mut client, "").await, not_serving);
+        monitor.check_db(&server).await;
+        assert_eq!(check(&mut client, "").await, not_serving);
+        monitor.set_cleanup_status(true).await;
+        assert_eq!(check(&mut client, "").await, serving);
+
+        // db is unavailable when the server is stopped
+        server.call(DeleteDb {}).await.unwrap().unwrap();
+        let mut stopped_server = server.clone();
+        stopped_server.stop(None).unwrap();
+        stopped_server.wait_for_stop().await;
+        monitor.check_db(&server).await;
+        assert_eq!(check(&mut client, service_name).await, not_serving);
+    }
+}
@@ -0,0 +1,25 @@
+//! The server modules used by the fuzz targets. The library is only built when fuzzing, so the
+//! fuzz targets call the message store in-process and the fuzzer is guided by the coverage of
+//! the server's code. The server itself is the multisig-service binary.
+
+#![cfg(fuzzing)]
+// the library only uses the message store and its dependencies
+#![allow(dead_code)]
+
+#[macro_use]
+extern crate log;
+extern crate api;
+
+mod address_hash;
+mod address_locks;
+mod clock;
+mod encryption;
+mod limits;
+mod logging;
+mod metrics;
+mod schema;
+mod server;
+mod service;
+mod telemetry;
+
+pub mod fuzzing;
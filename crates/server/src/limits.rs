@@ -0,0 +1,137 @@
+use anyhow::{bail, Result};
+use api::api::TransactionType;
+use config::{Config, ConfigError};
+
+pub(crate) const MAX_ADDRESS_SIZE_CONFIG_KEY_NAME: &str = "max_address_size";
+pub(crate) const MAX_TX_DATA_SIZE_CONFIG_KEY_NAME: &str = "max_tx_data_size";
+pub(crate) const MAX_ENVELOPE_RECIPIENTS_CONFIG_KEY_NAME: &str = "max_envelope_recipients";
+pub(crate) const TIME_WINDOW_CONFIG_KEY_NAME: &str = "accepted_messages_time_window";
+
+pub(crate) const DEFAULT_MAX_ADDRESS_SIZE_BYTES: usize = 128;
+pub(crate) const DEFAULT_MAX_TX_DATA_SIZE_BYTES: usize = 2048;
+pub(crate) const DEFAULT_MAX_ENVELOPE_RECIPIENTS: usize = 32;
+// new messages with creation time bigger than window relative to server time will be rejected
+pub(crate) const DEFAULT_ACCEPTED_MESSAGES_TIME_WINDOW_SECS: i64 = 60 * 60 * 24;
+
+const MAX_ADDRESS_SIZE_BOUNDS: (i64, i64) = (1, 1024);
+// a stored message must fit in a grpc response
+const MAX_TX_DATA_SIZE_BOUNDS: (i64, i64) = (1, 1024 * 1024);
+const MAX_ENVELOPE_RECIPIENTS_BOUNDS: (i64, i64) = (1, 256);
+const TIME_WINDOW_BOUNDS: (i64, i64) = (60, 60 * 60 * 24 * 7);
+
+/// Supported transaction types and their config names.
+/// max_tx_data_size can be overridden per type with a max_tx_data_size_<name> key,
+/// e.g. max_tx_data_size_vault_withdraw.
+pub(crate) const TRANSACTION_TYPES: &[(TransactionType, &str)] = &[
+    (TransactionType::VaultWithdraw, "vault_withdraw"),
+    (
+        TransactionType::VaultChangeDailySpendAccount,
+        "vault_change_daily_spend_account",
+    ),
+    (
+        TransactionType::VaultChangeDailySpendAmount,
+        "vault_change_daily_spend_amount",
+    ),
+    (TransactionType::CoinSpend, "coin_spend"),
+];
+
+/// Validation limits for new messages
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Limits {
+    pub(crate) max_address_size: usize,
+    pub(crate) max_envelope_recipients: usize,
+    pub(crate) accepted_time_window_secs: i64,
+    // max transaction data size, indexed by transaction type
+    max_tx_data_size: Vec<usize>,
+    // max transaction data size of transaction types this server doesn't know
+    pub(crate) default_max_tx_data_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_address_size: DEFAULT_MAX_ADDRESS_SIZE_BYTES,
+            max_envelope_recipients: DEFAULT_MAX_ENVELOPE_RECIPIENTS,
+            accepted_time_window_secs: DEFAULT_ACCEPTED_MESSAGES_TIME_WINDOW_SECS,
+            max_tx_data_size: vec![DEFAULT_MAX_TX_DATA_SIZE_BYTES; TRANSACTION_TYPES.len()],
+            default_max_tx_data_size: DEFAULT_MAX_TX_DATA_SIZE_BYTES,
+        }
+    }
+}
+
+impl Limits {
+    /// Read limits from the config. Missing keys get their default value and out of bounds
+    /// values are rejected.
+    pub(crate) fn from_config(config: &Config) -> Result<Limits> {
+        let default = Limits::default();
+        let max_tx_data_size = get_bounded(
+            config,
+            MAX_TX_DATA_SIZE_CONFIG_KEY_NAME,
+            MAX_TX_DATA_SIZE_BOUNDS,
+        )?
+        .unwrap_or(DEFAULT_MAX_TX_DATA_SIZE_BYTES as i64) as usize;
+
+        let mut per_type_max_tx_data_size = Vec::with_capacity(TRANSACTION_TYPES.len());
+        for (_, name) in TRANSACTION_TYPES {
+            let key = format!("{}_{}", MAX_TX_DATA_SIZE_CONFIG_KEY_NAME, name);
+            per_type_max_tx_data_size.push(
+                get_bounded(config, &key, MAX_TX_DATA_SIZE_BOUNDS)?
+                    .map_or(max_tx_data_size, |v| v as usize),
+            );
+        }
+
+        Ok(Limits {
+            max_address_size: get_bounded(
+                config,
+                MAX_ADDRESS_SIZE_CONFIG_KEY_NAME,
+                MAX_ADDRESS_SIZE_BOUNDS,
+            )?
+            .map_or(default.max_address_size, |v| v as usize),
+            max_envelope_recipients: get_bounded(
+                config,
+                MAX_ENVELOPE_RECIPIENTS_CONFIG_KEY_NAME,
+                MAX_ENVELOPE_RECIPIENTS_BOUNDS,
+            )?
+            .map_or(default.max_envelope_recipients, |v| v as usize),
+            accepted_time_window_secs: get_bounded(
+                config,
+                TIME_WINDOW_CONFIG_KEY_NAME,
+                TIME_WINDOW_BOUNDS,
+            )?
+            .unwrap_or(default.accepted_time_window_secs),
+            max_tx_data_size: per_type_max_tx_data_size,
+            default_max_tx_data_size: max_tx_data_size,
+        })
+    }
+
+    /// Returns all supported transaction types with their max transaction data size
+    pub(crate) fn supported_transaction_types(
+        &self,
+    ) -> impl Iterator<Item = (TransactionType, usize)> + '_ {
+        TRANSACTION_TYPES
+            .iter()
+            .zip(self.max_tx_data_size.iter())
+            .map(|((t, _), size)| (*t, *size))
+    }
+
+    /// Max transaction data size for a transaction type. Types unknown to this server get the
+    /// max_tx_data_size limit, so clients can send types added after this server version.
+    pub(crate) fn max_tx_data_size(&self, transaction_type: i32) -> usize {
+        TRANSACTION_TYPES
+            .iter()
+            .position(|(t, _)| *t as i32 == transaction_type)
+            .map_or(self.default_max_tx_data_size, |i| self.max_tx_data_size[i])
+    }
+}
+
+// Returns an integer config value, or None if it is not set
+fn get_bounded(config: &Config, key: &str, bounds: (i64, i64)) -> Result<Option<i64>> {
+    match config.get_int(key) {
+        Ok(v) if v < bounds.0 || v > bounds.1 => {
+            bail!("{} must be between {} and {}", key, bounds.0, bounds.1)
+        }
+        Ok(v) => Ok(Some(v)),
+        Err(ConfigError::NotFound(_)) => Ok(None),
+        Err(e) => bail!("invalid {}: {}", key, e),
+    }
+}
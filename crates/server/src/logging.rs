@@ -0,0 +1,199 @@
+use crate::telemetry;
+use anyhow::{anyhow, bail, Result};
+use config::Config;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter, Layer, Registry};
+
+pub(crate) const LOG_FORMAT_CONFIG_KEY_NAME: &str = "log_format";
+pub(crate) const LOG_LEVEL_CONFIG_KEY_NAME: &str = "log_level";
+pub(crate) const LOG_REDACT_ADDRESSES_CONFIG_KEY_NAME: &str = "log_redact_addresses";
+
+pub(crate) const LOG_FORMAT_TEXT: &str = "text";
+pub(crate) const LOG_FORMAT_JSON: &str = "json";
+pub(crate) const DEFAULT_LOG_LEVEL: &str = "info";
+
+// number of leading address bytes logged when addresses are redacted
+const REDACTED_ADDRESS_PREFIX_SIZE: usize = 4;
+
+static REDACT_ADDRESSES: AtomicBool = AtomicBool::new(true);
+// set when the log filter is set on startup with RUST_LOG, which takes precedence over log_level
+static ENV_LOG_FILTER: AtomicBool = AtomicBool::new(false);
+// handle to replace the log filter of the running subscriber
+static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Init logging with the configured log format.
+/// Text logs are colored human-readable lines. Json logs are one json object per line, including
+/// the fields of the event's spans, e.g. the request id of the grpc request it belongs to.
+/// Spans are also exported to an OTLP collector when one is configured.
+/// The log filter is the configured log level, and may be overridden on startup with RUST_LOG.
+pub(crate) fn init_logging(config: &Config) -> Result<()> {
+    set_redact_addresses(config.get_bool(LOG_REDACT_ADDRESSES_CONFIG_KEY_NAME)?);
+
+    let fmt_layer = tracing_subscriber::fmt::layer()
+        .with_file(true)
+        .with_line_number(true)
+        .with_target(false);
+    let fmt_layer = match config.get_str(LOG_FORMAT_CONFIG_KEY_NAME)?.as_str() {
+        LOG_FORMAT_TEXT => fmt_layer.boxed(),
+        LOG_FORMAT_JSON => fmt_layer
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+        other => bail!(
+            "invalid {}: {}. expected {} or {}",
+            LOG_FORMAT_CONFIG_KEY_NAME,
+            other,
+            LOG_FORMAT_TEXT,
+            LOG_FORMAT_JSON
+        ),
+    };
+    let otel_layer =
+        telemetry::tracer(config)?.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer));
+
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => {
+            ENV_LOG_FILTER.store(true, Ordering::Relaxed);
+            filter
+        }
+        Err(_) => log_filter(&config.get_str(LOG_LEVEL_CONFIG_KEY_NAME)?)?,
+    };
+    let (filter, filter_handle) = reload::Layer::new(filter);
+
+    // log records are forwarded to the subscriber
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt_layer)
+        .with(otel_layer)
+        .try_init()
+        .map_err(|e| anyhow!("failed to init logging: {}", e))?;
+    let _ = LOG_FILTER.set(filter_handle);
+    Ok(())
+}
+
+/// Returns a log filter for a log level, e.g. debug, or a RUST_LOG style filter
+pub(crate) fn log_filter(level: &str) -> Result<EnvFilter> {
+    EnvFilter::try_new(level).map_err(|e| anyhow!("invalid {}: {}", LOG_LEVEL_CONFIG_KEY_NAME, e))
+}
+
+/// Replace the log filter of the running logger. A filter set on startup with RUST_LOG is kept, as
+/// it takes precedence over the configured log level.
+pub(crate) fn set_log_filter(filter: EnvFilter) -> Result<()> {
+    if ENV_LOG_FILTER.load(Ordering::Relaxed) {
+        warn!(
+            "{} change is not applied - the log filter is set with RUST_LOG",
+            LOG_LEVEL_CONFIG_KEY_NAME
+        );
+        return Ok(());
+    }
+    if let Some(handle) = LOG_FILTER.get() {
+        handle.reload(filter)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn set_redact_addresses(redact: bool) {
+    REDACT_ADDRESSES.store(redact, Ordering::Relaxed);
+}
+
+/// Returns an address for logging. Only a short prefix is logged when addresses are redacted.
+pub(crate) fn log_address(address: &[u8]) -> String {
+    if REDACT_ADDRESSES.load(Ordering::Relaxed) && address.len() > REDACTED_ADDRESS_PREFIX_SIZE {
+        format!(
+            "{}..",
+            hex::encode(&address[..REDACTED_ADDRESS_PREFIX_SIZE])
+        )
+    } else {
+        hex::encode(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_default_config;
+    use crate::server::{DeleteDb, Server, SetConfig, StoreMessage, Traced};
+    use api::api::{StoreMessageRequest, TransactionType, UserMessage};
+    use chrono::prelude::*;
+    use serial_test::*;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use xactor::Actor;
+
+    #[derive(Clone, Default)]
+    struct TestWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for TestWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn redact_addresses() {
+        assert_eq!(log_address(&[0xab; 32]), "abababab..");
+        assert_eq!(log_address(&[0xab; 2]), "abab");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn trace_rejected_message() {
+        let writer = TestWriter::default();
+        let make_writer = writer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .with_writer(move || make_writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let _ = std::fs::remove_dir_all("./data_store");
+        let server = Server::default().start().await.unwrap();
+        server
+            .call(SetConfig(get_default_config()))
+            .await
+            .unwrap()
+            .unwrap();
+
+        // the handler's rejection event is logged in the caller's span
+        let span = tracing::info_span!("rpc", request_id = "test-request-id");
+        let res = server
+            .call(Traced(
+                StoreMessage(StoreMessageRequest {
+                    user_message: Some(UserMessage {
+                        net_id: 1,
+                        created: Utc::now().timestamp() as u64,
+                        address: vec![1; 4096],
+                        transaction_type: TransactionType::VaultWithdraw as i32,
+                        transaction_data: vec![0; 32],
+                        encrypted_transaction_data: None,
+                    }),
+                }),
+                span,
+            ))
+            .await
+            .unwrap();
+        assert!(res.is_err());
+
+        let output = String::from_utf8(writer.0.lock().unwrap().clone()).unwrap();
+        let line = output
+            .lines()
+            .find(|l| l.contains("message rejected"))
+            .unwrap();
+        assert!(line.contains(r#""level":"WARN""#));
+        assert!(line.contains(r#""reason":"address_size""#));
+        assert!(line.contains(r#""request_id":"test-request-id""#));
+
+        // cleanup
+        server.call(DeleteDb {}).await.unwrap().unwrap();
+    }
+}
@@ -4,164 +4,482 @@ extern crate api;
 extern crate hex;
 extern crate serial_test;
 
-use crate::server::{DeleteOldMessages, Server, SetConfig};
+use crate::admin::{AdminService, ADMIN_HOST_CONFIG_KEY_NAME, ADMIN_PORT_CONFIG_KEY_NAME};
+use crate::backup::{
+    BACKUP_DIR_CONFIG_KEY_NAME, BACKUP_INTERVAL_CONFIG_KEY_NAME, BACKUP_RETENTION_CONFIG_KEY_NAME,
+    DEFAULT_BACKUP_DIR, DEFAULT_BACKUP_INTERVAL_SECS, DEFAULT_BACKUP_RETENTION,
+};
+use crate::cli::SERVE_COMMAND;
+use crate::health::HealthMonitor;
+use crate::limits::{
+    DEFAULT_ACCEPTED_MESSAGES_TIME_WINDOW_SECS, DEFAULT_MAX_ADDRESS_SIZE_BYTES,
+    DEFAULT_MAX_ENVELOPE_RECIPIENTS, DEFAULT_MAX_TX_DATA_SIZE_BYTES,
+    MAX_ADDRESS_SIZE_CONFIG_KEY_NAME, MAX_ENVELOPE_RECIPIENTS_CONFIG_KEY_NAME,
+    MAX_TX_DATA_SIZE_CONFIG_KEY_NAME, TIME_WINDOW_CONFIG_KEY_NAME,
+};
+use crate::logging::{
+    init_logging, log_filter, set_log_filter, set_redact_addresses, DEFAULT_LOG_LEVEL,
+    LOG_FORMAT_CONFIG_KEY_NAME, LOG_FORMAT_TEXT, LOG_LEVEL_CONFIG_KEY_NAME,
+    LOG_REDACT_ADDRESSES_CONFIG_KEY_NAME,
+};
+use crate::metrics::{METRICS_HOST_CONFIG_KEY_NAME, METRICS_PORT_CONFIG_KEY_NAME};
+use crate::reload::{config_changes, runtime_config};
+use crate::server::{
+    FlushDb, GetMessageStore, ReencryptRecords, ReloadConfig, Server, SetConfig,
+    MSG_RETENTION_DUR_CONFIG_KEY_NAME, SERVER_VERSION,
+};
 use crate::service::GrpcService;
+use crate::settings::{
+    add_config_args, apply_arg_overrides, apply_env_overrides, config_keys, config_to_toml,
+    ConfigKey, Settings, CONFIG_PRECEDENCE_HELP,
+};
+use crate::telemetry::{
+    OTLP_ENDPOINT_CONFIG_KEY_NAME, OTLP_PROTOCOL_CONFIG_KEY_NAME, OTLP_PROTOCOL_GRPC,
+};
+use anyhow::{bail, Context as _};
+use api::api::multi_sig_admin_server::MultiSigAdminServer;
 use api::api::multi_sig_service_server::MultiSigServiceServer;
-use chrono::prelude::*;
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches};
 use config::Config;
-use env_logger::fmt::Color;
-use env_logger::Builder;
 use log::*;
-use std::env;
-use std::io::Write;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time;
 use tokio::time::Duration;
-use tokio::{signal, time};
 use xactor::*;
 
+mod address_hash;
+mod address_locks;
+mod admin;
+mod backup;
+mod cli;
+mod clock;
+mod encryption;
+mod export;
+mod health;
+mod limits;
+mod logging;
+mod metrics;
+mod reload;
+mod schema;
 mod server;
 mod service;
+mod settings;
+mod telemetry;
 
 const DEFAULT_GRPC_PORT: u32 = 6667;
 const DEFAULT_HOST: &str = "[::1]";
 const DB_CLEANUP_INTERVAL_SECS: u64 = 60 * 60 * 24 * 10;
 const MSG_RETENTION_DURATION: u64 = DB_CLEANUP_INTERVAL_SECS * 2;
+const REENCRYPT_BATCH_SIZE: usize = 1000;
+const HEALTH_CHECK_INTERVAL_SECS: u64 = 10;
+// max time to wait for in-flight requests on shutdown
+const SHUTDOWN_DEADLINE_SECS: u64 = 30;
+// metrics are served when a metrics port is set
+const DEFAULT_METRICS_PORT: u32 = 0;
+// the admin service is served when an admin port is set
+const DEFAULT_ADMIN_PORT: u32 = 0;
 const DB_INTERVAL_CONFIG_KEY_NAME: &str = "db_cleanup_interval";
-const MSG_RETENTION_DUR_CONFIG_KEY_NAME: &str = "msg_retention_duration";
+const HEALTH_CHECK_INTERVAL_CONFIG_KEY_NAME: &str = "health_check_interval";
+const SHUTDOWN_DEADLINE_CONFIG_KEY_NAME: &str = "shutdown_deadline";
 const PORT_CONFIG_KEY_NAME: &str = "port";
 const HOST_CONFIG_KEY_NAME: &str = "host";
 
 #[tokio::main]
 async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
-    init_logging();
-    let mut config = get_default_config();
-    let args = App::new("Spacemesh Multisig Message Server")
-        .version("0.1.0")
+    let config_keys = config_keys();
+    let app = App::new("Spacemesh Multisig Message Server")
+        .version(SERVER_VERSION)
         .author("Aviv Eyal <a@spacemesh.io>")
         .about("Provides a basic service for users to exchange multisig messages")
+        .after_help(CONFIG_PRECEDENCE_HELP)
         .arg(
             Arg::with_name("config")
                 .short("c")
                 .long("config")
                 .takes_value(true)
                 .value_name("FILE")
-                .help("provide server configuration file")
+                .help("provide server configuration file. The file is re-read on SIGHUP")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("restore-from")
+                .long("restore-from")
+                .takes_value(true)
+                .value_name("BACKUP_DIR")
+                .help("replace the db with a backup before the server starts"),
+        )
+        .arg(
+            Arg::with_name("print-config")
+                .long("print-config")
+                .help("print the effective config and exit"),
+        );
+    let args = add_config_args(app, &config_keys)
+        .subcommands(cli::subcommands())
         .get_matches();
 
+    let config = load_config(&args, &config_keys)?;
+    if args.is_present("print-config") {
+        print!("{}", config_to_toml(&config)?);
+        return Ok(());
+    }
+    let settings = Settings::from_config(&config).context("invalid config")?;
+
+    match args.subcommand() {
+        (command, Some(command_args)) if command != SERVE_COMMAND => {
+            Ok(cli::run(command, command_args, config, settings).await?)
+        }
+        _ => serve(&args, &config_keys, config, settings).await,
+    }
+}
+
+/// Run the server until it is terminated
+async fn serve(
+    args: &ArgMatches<'_>,
+    config_keys: &[ConfigKey],
+    config: Config,
+    settings: Settings,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    init_logging(&config)?;
+    if let Some(backup) = args.value_of("restore-from") {
+        backup::restore(std::path::Path::new(backup))?;
+    }
+
+    let mut running_server = start_server(config, settings).await?;
+
+    // reload the config on SIGHUP and block app process until it is terminated
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut sighup = signal(SignalKind::hangup())?;
+    loop {
+        tokio::select! {
+            res = tokio::signal::ctrl_c() => {
+                res?;
+                break;
+            }
+            _ = sigterm.recv() => break,
+            _ = sighup.recv() => {
+                info!("got SIGHUP - reloading config...");
+                match load_config(args, config_keys) {
+                    Ok(config) => {
+                        if let Err(e) = running_server.reload(config).await {
+                            error!("failed to reload config - keeping current config: {}", e);
+                        }
+                    }
+                    Err(e) => error!("failed to load config - keeping current config: {}", e),
+                }
+            }
+        }
+    }
+
+    info!("got signal - shutting down server...");
+    let exit_code = match running_server.shutdown().await {
+        Ok(()) => {
+            info!("server shut down");
+            0
+        }
+        Err(e) => {
+            error!("server shutdown error: {}", e);
+            1
+        }
+    };
+    telemetry::shutdown();
+    std::process::exit(exit_code)
+}
+
+/// Load the server config from the defaults, the config file, the environment and the command
+/// line args, in increasing precedence
+fn load_config(args: &ArgMatches, config_keys: &[ConfigKey]) -> Result<Config> {
+    let mut config = get_default_config();
     if let Some(conf_file) = args.value_of("config") {
-        config
-            .merge(config::File::with_name(conf_file).required(false))
-            .unwrap();
+        config.merge(config::File::with_name(conf_file).required(false))?;
     }
-    start_server(config).await?;
+    apply_env_overrides(&mut config, config_keys, |name| std::env::var(name).ok())?;
+    apply_arg_overrides(&mut config, config_keys, args)?;
+    Ok(config)
+}
+
+/// Handle of a started server
+struct RunningServer {
+    server: Addr<Server>,
+    // the applied config
+    config: Config,
+    // tasks are notified when settings are reloaded
+    settings_sender: watch::Sender<Settings>,
+    shutdown_sender: watch::Sender<bool>,
+    grpc_task: JoinHandle<()>,
+    cleanup_task: JoinHandle<()>,
+    // set when the admin service and the scheduled backup are enabled
+    admin_task: Option<JoinHandle<()>>,
+    backup_task: Option<JoinHandle<()>>,
+    shutdown_deadline: Duration,
+}
+
+impl RunningServer {
+    /// Apply the runtime changeable settings of a reloaded config, and log the changed settings.
+    /// Changed settings that are only applied on startup are reported and kept at their current
+    /// values. Nothing is applied if the config is invalid.
+    async fn reload(&mut self, new_config: Config) -> Result<()> {
+        let changes = config_changes(&self.config, &new_config)?;
+        if changes.is_empty() {
+            info!("config unchanged");
+            return Ok(());
+        }
+        let config = runtime_config(&self.config, &new_config)?;
 
-    // block app process until it is terminated
-    signal::ctrl_c()
-        .await
-        .expect("failed to listen for ctrl-c signal");
+        // validate settings before any is applied
+        let settings = Settings::from_config(&new_config)?;
+        self.server.call(ReloadConfig(config.clone())).await??;
 
-    info!("got signal - terminating server");
-    Ok(())
+        if changes.iter().any(|c| c.key == LOG_LEVEL_CONFIG_KEY_NAME) {
+            set_log_filter(log_filter(&settings.log_level)?)?;
+        }
+        set_redact_addresses(settings.log_redact_addresses);
+        let _ = self.settings_sender.send(Settings::from_config(&config)?);
+        self.config = config;
+
+        for change in changes {
+            if change.restart_required() {
+                warn!("config changed - restart required to apply: {}", change);
+            } else {
+                info!("config changed: {}", change);
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop accepting new connections, wait for in-flight requests, admin requests, and a running
+    /// db cleanup and backup to complete, and flush and close the db.
+    /// Returns an error if they didn't complete within the shutdown deadline.
+    async fn shutdown(self) -> Result<()> {
+        let deadline = time::Instant::now() + self.shutdown_deadline;
+        let _ = self.shutdown_sender.send(true);
+
+        let drained = time::timeout_at(deadline, self.grpc_task).await.is_ok();
+        if !drained {
+            warn!("in-flight requests did not complete within the shutdown deadline");
+        }
+        let admin_drained = join_before(deadline, self.admin_task).await;
+        if !admin_drained {
+            warn!("in-flight admin requests did not complete within the shutdown deadline");
+        }
+        let cleaned = time::timeout_at(deadline, self.cleanup_task).await.is_ok();
+        if !cleaned {
+            warn!("db cleanup did not complete within the shutdown deadline");
+        }
+        let backed_up = join_before(deadline, self.backup_task).await;
+        if !backed_up {
+            warn!("db backup did not complete within the shutdown deadline");
+        }
+
+        self.server.call(FlushDb {}).await??;
+        let mut server = self.server;
+        server.stop(None)?;
+        server.wait_for_stop().await;
+
+        if !drained || !admin_drained || !cleaned || !backed_up {
+            bail!("shutdown deadline exceeded");
+        }
+        Ok(())
+    }
 }
 
-async fn start_server(config: Config) -> Result<()> {
+// Wait for an optional task until the deadline. Returns false if it didn't complete in time.
+async fn join_before(deadline: time::Instant, task: Option<JoinHandle<()>>) -> bool {
+    match task {
+        Some(task) => time::timeout_at(deadline, task).await.is_ok(),
+        None => true,
+    }
+}
+
+async fn start_server(config: Config, settings: Settings) -> Result<RunningServer> {
+    // the server reports not serving until the db is checked
+    let (health_reporter, health_service) = tonic_health::server::health_reporter();
+    let health = HealthMonitor::new(health_reporter).await;
+
     // init the server with the provided config
     let server = Server::from_registry().await?;
     server.call(SetConfig(config.clone())).await??;
+    // rpc requests and db cleanups are handled concurrently with the shared message store
+    let store = server.call(GetMessageStore {}).await??;
 
     info!("server starting...");
 
-    let port = config.get_int(PORT_CONFIG_KEY_NAME)? as u32;
-    let host = config.get_str(HOST_CONFIG_KEY_NAME)?;
-    let addr = format!("{}:{}", host, port).parse().unwrap();
-    info!("starting grpc service on: {}...", addr);
+    // re-encrypt records written with a previous master key or before encryption was enabled.
+    // records are processed in batches so requests are handled while this runs.
+    // tasks stop when shutdown is set
+    let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+    let (settings_sender, mut settings_receiver) = watch::channel(settings.clone());
 
+    let reencrypt_server = server.clone();
+    let reencrypt_shutdown = shutdown_receiver.clone();
     tokio::spawn(async move {
+        let mut from = None;
+        while !*reencrypt_shutdown.borrow() {
+            match reencrypt_server
+                .call(ReencryptRecords {
+                    from,
+                    batch_size: REENCRYPT_BATCH_SIZE,
+                })
+                .await
+            {
+                Err(e) => {
+                    error!("failed to call service method: {}", e);
+                    break;
+                }
+                Ok(Err(e)) => {
+                    error!("db re-encryption task error: {}", e);
+                    break;
+                }
+                Ok(Ok(Some(next))) => from = Some(next),
+                Ok(Ok(None)) => {
+                    info!("db re-encryption task completed");
+                    break;
+                }
+            }
+        }
+    });
+
+    let addr = settings.grpc_addr()?;
+    info!("starting grpc service on: {}...", addr);
+
+    let grpc_service = GrpcService::new(store.clone());
+    let mut grpc_shutdown = shutdown_receiver.clone();
+    let grpc_task = tokio::spawn(async move {
         let res = tonic::transport::Server::builder()
-            .add_service(MultiSigServiceServer::new(GrpcService::default()))
-            .serve(addr)
+            .add_service(health_service)
+            .add_service(MultiSigServiceServer::new(grpc_service))
+            .serve_with_shutdown(addr, async move {
+                let _ = grpc_shutdown.changed().await;
+            })
             .await;
         if res.is_err() {
-            panic!("grpc server stopped due to error: {:?}", res.err().unwrap());
+            // exit so the process can be restarted - the service is unreachable without the grpc server
+            error!("grpc server stopped due to error: {:?}", res.err().unwrap());
+            std::process::exit(1);
         } else {
             info!("grpc server stopped");
         }
     });
 
-    let db_cleanup_interval = config.get_int(DB_INTERVAL_CONFIG_KEY_NAME).unwrap() as u64;
+    if settings.metrics_port != 0 {
+        let metrics_addr = settings.metrics_addr()?;
+        info!("starting metrics service on: {}...", metrics_addr);
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(metrics_addr).await {
+                error!("metrics server stopped due to error: {}", e);
+            }
+        });
+    }
+
+    let mut admin_task = None;
+    if settings.admin_port != 0 {
+        let admin_addr = settings.admin_addr()?;
+        info!("starting admin grpc service on: {}...", admin_addr);
+        let admin_service = MultiSigAdminServer::new(AdminService::new(server.clone()));
+        let mut admin_shutdown = shutdown_receiver.clone();
+        admin_task = Some(tokio::spawn(async move {
+            let res = tonic::transport::Server::builder()
+                .add_service(admin_service)
+                .serve_with_shutdown(admin_addr, async move {
+                    let _ = admin_shutdown.changed().await;
+                })
+                .await;
+            match res {
+                Err(e) => error!("admin grpc server stopped due to error: {}", e),
+                Ok(_) => info!("admin grpc server stopped"),
+            }
+        }));
+    }
+
+    // spawn the scheduled db backup task. A running backup completes before the task stops.
+    let mut backup_task = None;
+    if settings.backup_interval != 0 {
+        let backup_server = server.clone();
+        let backup_dir = std::path::PathBuf::from(&settings.backup_dir);
+        let backup_retention = settings.backup_retention as usize;
+        let period = Duration::from_secs(settings.backup_interval);
+        let mut backup_shutdown = shutdown_receiver.clone();
+        backup_task = Some(tokio::spawn(async move {
+            let mut interval = time::interval_at(time::Instant::now() + period, period);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        if let Err(e) =
+                            backup::backup(&backup_server, &backup_dir, backup_retention).await
+                        {
+                            error!("db backup task error: {}", e);
+                        }
+                    }
+                    _ = backup_shutdown.changed() => break,
+                }
+            }
+            info!("db backup task stopped");
+        }));
+    }
 
-    // spawn the db cleanup task on interval
+    // spawn the db health check task on interval
+    let health_check_interval = settings.health_check_interval;
+    let db_health = health.clone();
+    let health_server = server.clone();
+    let mut health_shutdown = shutdown_receiver.clone();
     tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(health_check_interval));
+        loop {
+            tokio::select! {
+                _ = interval.tick() => db_health.check_db(&health_server).await,
+                _ = health_shutdown.changed() => break,
+            }
+        }
+    });
+
+    let mut db_cleanup_interval = settings.db_cleanup_interval;
+
+    // spawn the db cleanup task on interval. A running cleanup completes before the task stops.
+    // Cleanups run on the blocking thread pool and don't block request handling.
+    let mut cleanup_shutdown = shutdown_receiver;
+    let cleanup_task = tokio::spawn(async move {
         let mut interval = time::interval(Duration::from_secs(db_cleanup_interval));
         loop {
-            interval.tick().await;
-            match Server::from_registry().await {
+            tokio::select! {
+                _ = interval.tick() => (),
+                _ = cleanup_shutdown.changed() => break,
+                _ = settings_receiver.changed() => {
+                    let new_interval = settings_receiver.borrow().db_cleanup_interval;
+                    if new_interval != db_cleanup_interval {
+                        // the next cleanup runs a full new interval from now
+                        db_cleanup_interval = new_interval;
+                        let period = Duration::from_secs(db_cleanup_interval);
+                        interval = time::interval_at(time::Instant::now() + period, period);
+                    }
+                    continue;
+                }
+            }
+            match store.spawn(|store| store.delete_old_messages()).await {
                 Err(e) => {
-                    error!("failed to get server system service: {}", e);
+                    error!("db cleanup task error: {}", e);
+                    health.set_cleanup_status(false).await;
+                }
+                Ok(_) => {
+                    info!("db cleanup task completed without errors");
+                    health.set_cleanup_status(true).await;
                 }
-                Ok(server) => match server.call(DeleteOldMessages {}).await {
-                    Err(e) => {
-                        error!("failed to call service method: {}", e)
-                    }
-                    Ok(res) => match res {
-                        Err(e) => {
-                            error!("db cleanup task error: {}", e);
-                        }
-                        Ok(_) => {
-                            info!("db cleanup task completed without errors");
-                        }
-                    },
-                },
             }
         }
     });
 
     info!("server running");
-    Ok(())
-}
-
-fn init_logging() {
-    let mut builder = Builder::new();
-
-    builder
-        .format_level(true)
-        .format_timestamp(None)
-        .format(move |buf, record| {
-            let level_style = buf.default_level_style(record.level());
-
-            let now: DateTime<Local> = Local::now();
-            let date_format = now.to_rfc3339().to_string();
-
-            let mut date_style = buf.style();
-            date_style.set_color(Color::Yellow).set_bold(true);
-
-            let mut file_name_style = buf.style();
-            file_name_style.set_color(Color::Blue);
-
-            let file_name = format!(
-                "{} {}",
-                record.file().unwrap().split('/').last().unwrap(),
-                record.line().unwrap()
-            );
-
-            writeln!(
-                buf,
-                "{} {} {} {}",
-                date_style.value(date_format),
-                level_style.value(record.level()),
-                file_name_style.value(file_name),
-                record.args(),
-            )
-        })
-        .filter(None, LevelFilter::Info);
-
-    if env::var("RUST_LOG").is_ok() {
-        builder.parse_filters(&env::var("RUST_LOG").unwrap());
-    }
-
-    builder.init();
+    Ok(RunningServer {
+        server,
+        config,
+        settings_sender,
+        shutdown_sender,
+        grpc_task,
+        cleanup_task,
+        admin_task,
+        backup_task,
+        shutdown_deadline: Duration::from_secs(settings.shutdown_deadline),
+    })
 }
 
 /// Returns the default server configurations
@@ -182,5 +500,68 @@ fn get_default_config() -> config::Config {
             MSG_RETENTION_DURATION.to_string(),
         )
         .unwrap()
+        .set_default(LOG_FORMAT_CONFIG_KEY_NAME, LOG_FORMAT_TEXT)
+        .unwrap()
+        .set_default(LOG_LEVEL_CONFIG_KEY_NAME, DEFAULT_LOG_LEVEL)
+        .unwrap()
+        .set_default(LOG_REDACT_ADDRESSES_CONFIG_KEY_NAME, true)
+        .unwrap()
+        .set_default(OTLP_ENDPOINT_CONFIG_KEY_NAME, "")
+        .unwrap()
+        .set_default(OTLP_PROTOCOL_CONFIG_KEY_NAME, OTLP_PROTOCOL_GRPC)
+        .unwrap()
+        .set_default(METRICS_HOST_CONFIG_KEY_NAME, DEFAULT_HOST)
+        .unwrap()
+        .set_default(
+            METRICS_PORT_CONFIG_KEY_NAME,
+            DEFAULT_METRICS_PORT.to_string(),
+        )
+        .unwrap()
+        .set_default(ADMIN_HOST_CONFIG_KEY_NAME, DEFAULT_HOST)
+        .unwrap()
+        .set_default(ADMIN_PORT_CONFIG_KEY_NAME, DEFAULT_ADMIN_PORT.to_string())
+        .unwrap()
+        .set_default(BACKUP_DIR_CONFIG_KEY_NAME, DEFAULT_BACKUP_DIR)
+        .unwrap()
+        .set_default(
+            BACKUP_INTERVAL_CONFIG_KEY_NAME,
+            DEFAULT_BACKUP_INTERVAL_SECS.to_string(),
+        )
+        .unwrap()
+        .set_default(
+            BACKUP_RETENTION_CONFIG_KEY_NAME,
+            DEFAULT_BACKUP_RETENTION.to_string(),
+        )
+        .unwrap()
+        .set_default(
+            SHUTDOWN_DEADLINE_CONFIG_KEY_NAME,
+            SHUTDOWN_DEADLINE_SECS.to_string(),
+        )
+        .unwrap()
+        .set_default(
+            HEALTH_CHECK_INTERVAL_CONFIG_KEY_NAME,
+            HEALTH_CHECK_INTERVAL_SECS.to_string(),
+        )
+        .unwrap()
+        .set_default(
+            MAX_ADDRESS_SIZE_CONFIG_KEY_NAME,
+            DEFAULT_MAX_ADDRESS_SIZE_BYTES.to_string(),
+        )
+        .unwrap()
+        .set_default(
+            MAX_TX_DATA_SIZE_CONFIG_KEY_NAME,
+            DEFAULT_MAX_TX_DATA_SIZE_BYTES.to_string(),
+        )
+        .unwrap()
+        .set_default(
+            MAX_ENVELOPE_RECIPIENTS_CONFIG_KEY_NAME,
+            DEFAULT_MAX_ENVELOPE_RECIPIENTS.to_string(),
+        )
+        .unwrap()
+        .set_default(
+            TIME_WINDOW_CONFIG_KEY_NAME,
+            DEFAULT_ACCEPTED_MESSAGES_TIME_WINDOW_SECS.to_string(),
+        )
+        .unwrap()
         .clone()
 }
@@ -0,0 +1,284 @@
+use anyhow::Result;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram, register_histogram_vec, register_int_counter, register_int_counter_vec,
+    register_int_gauge, Encoder, Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    TextEncoder,
+};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+pub(crate) const METRICS_HOST_CONFIG_KEY_NAME: &str = "metrics_host";
+pub(crate) const METRICS_PORT_CONFIG_KEY_NAME: &str = "metrics_port";
+
+lazy_static! {
+    static ref RPC_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "multisig_rpc_requests_total",
+        "Number of handled rpc requests by rpc and grpc status code",
+        &["rpc", "code"]
+    )
+    .unwrap();
+    static ref RPC_DURATION: HistogramVec = register_histogram_vec!(
+        "multisig_rpc_duration_seconds",
+        "Rpc request handling duration",
+        &["rpc"]
+    )
+    .unwrap();
+    static ref REJECTED_MESSAGES: IntCounterVec = register_int_counter_vec!(
+        "multisig_rejected_messages_total",
+        "Number of messages rejected by validation by rejection reason",
+        &["reason"]
+    )
+    .unwrap();
+    static ref STORED_MESSAGES: IntGauge = register_int_gauge!(
+        "multisig_stored_messages",
+        "Number of stored messages. Recounted when the db is opened and on each db cleanup"
+    )
+    .unwrap();
+    static ref STORED_ADDRESSES: IntGauge = register_int_gauge!(
+        "multisig_stored_addresses",
+        "Number of addresses with stored messages. Recounted when the db is opened and on each db cleanup"
+    )
+    .unwrap();
+    static ref DB_SIZE: IntGauge =
+        register_int_gauge!("multisig_db_size_bytes", "Estimated db size on disk").unwrap();
+    static ref CLEANUP_DURATION: Histogram = register_histogram!(
+        "multisig_db_cleanup_duration_seconds",
+        "Duration of db cleanup task runs"
+    )
+    .unwrap();
+    static ref DELETED_MESSAGES: IntCounter = register_int_counter!(
+        "multisig_deleted_messages_total",
+        "Number of messages deleted by the db cleanup task"
+    )
+    .unwrap();
+    static ref DELETED_ADDRESSES: IntCounter = register_int_counter!(
+        "multisig_deleted_addresses_total",
+        "Number of addresses deleted by the db cleanup task"
+    )
+    .unwrap();
+}
+
+/// Record a handled rpc request and its grpc status code
+pub(crate) fn observe_rpc(rpc: &str, code: &str, start: Instant) {
+    RPC_REQUESTS.with_label_values(&[rpc, code]).inc();
+    RPC_DURATION
+        .with_label_values(&[rpc])
+        .observe(start.elapsed().as_secs_f64());
+}
+
+/// Record a message rejected by validation
+pub(crate) fn message_rejected(reason: &str) {
+    REJECTED_MESSAGES.with_label_values(&[reason]).inc();
+}
+
+/// Record a newly stored message. new_address is true when it is the address' first message.
+pub(crate) fn message_stored(new_address: bool) {
+    STORED_MESSAGES.inc();
+    if new_address {
+        STORED_ADDRESSES.inc();
+    }
+}
+
+/// Record messages imported for an address. new_address is true when the address had no messages.
+pub(crate) fn messages_imported(count: usize, new_address: bool) {
+    STORED_MESSAGES.add(count as i64);
+    if new_address {
+        STORED_ADDRESSES.inc();
+    }
+}
+
+/// Record a purged address and its deleted messages
+pub(crate) fn address_purged(deleted_messages: usize) {
+    STORED_MESSAGES.sub(deleted_messages as i64);
+    STORED_ADDRESSES.dec();
+}
+
+/// Set the number of stored messages and addresses counted in the db
+pub(crate) fn set_stored(stored_messages: i64, stored_addresses: i64) {
+    STORED_MESSAGES.set(stored_messages);
+    STORED_ADDRESSES.set(stored_addresses);
+}
+
+/// Record a db cleanup task run
+pub(crate) fn cleanup_completed(
+    start: Instant,
+    deleted_messages: u64,
+    deleted_addresses: u64,
+    stored_messages: i64,
+    stored_addresses: i64,
+) {
+    CLEANUP_DURATION.observe(start.elapsed().as_secs_f64());
+    DELETED_MESSAGES.inc_by(deleted_messages);
+    DELETED_ADDRESSES.inc_by(deleted_addresses);
+    set_stored(stored_messages, stored_addresses);
+}
+
+pub(crate) fn set_db_size(size: u64) {
+    DB_SIZE.set(size as i64);
+}
+
+/// Returns all metrics in the prometheus text format
+pub(crate) fn gather() -> Result<Vec<u8>> {
+    let mut buffer = vec![];
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    Ok(buffer)
+}
+
+/// Serve metrics over http on /metrics
+pub(crate) async fn serve(addr: SocketAddr) -> Result<()> {
+    let make_service =
+        make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle_request)) });
+    hyper::Server::try_bind(&addr)?.serve(make_service).await?;
+    Ok(())
+}
+
+async fn handle_request(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if req.method() != Method::GET || req.uri().path() != "/metrics" {
+        let mut not_found = Response::new(Body::empty());
+        *not_found.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(not_found);
+    }
+
+    Ok(match gather() {
+        Ok(buffer) => Response::builder()
+            .header(
+                hyper::header::CONTENT_TYPE,
+                TextEncoder::new().format_type(),
+            )
+            .body(Body::from(buffer))
+            .unwrap(),
+        Err(e) => {
+            error!("failed to encode metrics: {}", e);
+            let mut error = Response::new(Body::empty());
+            *error.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            error
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_default_config;
+    use crate::server::{DeleteDb, InvalidInput, PurgeAddress, Server, SetConfig, StoreMessage};
+    use api::api::{StoreMessageRequest, TransactionType, UserMessage};
+    use chrono::prelude::*;
+    use serial_test::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use xactor::Actor;
+
+    #[tokio::test]
+    #[serial]
+    async fn count_rejected_messages() {
+        let _ = std::fs::remove_dir_all("./data_store");
+        let server = Server::default().start().await.unwrap();
+        server
+            .call(SetConfig(get_default_config()))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let rejected = REJECTED_MESSAGES.with_label_values(&["address_size"]).get();
+        let res = server
+            .call(StoreMessage(StoreMessageRequest {
+                user_message: Some(UserMessage {
+                    net_id: 1,
+                    created: Utc::now().timestamp() as u64,
+                    address: vec![0; 4096],
+                    transaction_type: TransactionType::VaultWithdraw as i32,
+                    transaction_data: vec![0; 32],
+                    encrypted_transaction_data: None,
+                }),
+            }))
+            .await
+            .unwrap();
+
+        let err = res.unwrap_err();
+        assert!(err.downcast_ref::<InvalidInput>().is_some());
+        assert_eq!(
+            REJECTED_MESSAGES.with_label_values(&["address_size"]).get(),
+            rejected + 1
+        );
+
+        // cleanup
+        server.call(DeleteDb {}).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn count_stored_messages() {
+        let _ = std::fs::remove_dir_all("./data_store");
+        let server = Server::default().start().await.unwrap();
+        server
+            .call(SetConfig(get_default_config()))
+            .await
+            .unwrap()
+            .unwrap();
+        let address = vec![7; 32];
+        for i in 0..2 {
+            server
+                .call(StoreMessage(StoreMessageRequest {
+                    user_message: Some(UserMessage {
+                        net_id: 1,
+                        created: Utc::now().timestamp() as u64,
+                        address: address.clone(),
+                        transaction_type: TransactionType::VaultWithdraw as i32,
+                        transaction_data: vec![i; 32],
+                        encrypted_transaction_data: None,
+                    }),
+                }))
+                .await
+                .unwrap()
+                .unwrap();
+        }
+
+        // the counts are read from the db when it is opened, e.g. after a restart
+        set_stored(0, 0);
+        server
+            .call(SetConfig(get_default_config()))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(STORED_MESSAGES.get(), 2);
+        assert_eq!(STORED_ADDRESSES.get(), 1);
+
+        server.call(PurgeAddress(address)).await.unwrap().unwrap();
+        assert_eq!(STORED_MESSAGES.get(), 0);
+        assert_eq!(STORED_ADDRESSES.get(), 0);
+
+        // cleanup
+        server.call(DeleteDb {}).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn serve_metrics() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+        tokio::spawn(async move { serve(addr).await.unwrap() });
+
+        message_rejected("test");
+        let mut stream = loop {
+            match tokio::net::TcpStream::connect(addr).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(tokio::time::Duration::from_millis(10)).await,
+            }
+        };
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("multisig_rejected_messages_total{reason=\"test\"}"));
+    }
+}
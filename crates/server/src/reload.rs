@@ -0,0 +1,166 @@
+use crate::address_hash::ADDRESS_HASH_SECRET_FILE_CONFIG_KEY_NAME;
+use crate::admin::{ADMIN_HOST_CONFIG_KEY_NAME, ADMIN_PORT_CONFIG_KEY_NAME};
+use crate::backup::{
+    BACKUP_DIR_CONFIG_KEY_NAME, BACKUP_INTERVAL_CONFIG_KEY_NAME, BACKUP_RETENTION_CONFIG_KEY_NAME,
+};
+use crate::encryption::{MASTER_KEY_FILE_CONFIG_KEY_NAME, PREV_MASTER_KEY_FILE_CONFIG_KEY_NAME};
+use crate::logging::LOG_FORMAT_CONFIG_KEY_NAME;
+use crate::metrics::{METRICS_HOST_CONFIG_KEY_NAME, METRICS_PORT_CONFIG_KEY_NAME};
+use crate::telemetry::{OTLP_ENDPOINT_CONFIG_KEY_NAME, OTLP_PROTOCOL_CONFIG_KEY_NAME};
+use crate::{
+    HEALTH_CHECK_INTERVAL_CONFIG_KEY_NAME, HOST_CONFIG_KEY_NAME, PORT_CONFIG_KEY_NAME,
+    SHUTDOWN_DEADLINE_CONFIG_KEY_NAME,
+};
+use anyhow::Result;
+use config::{Config, Source, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+/// Settings that are only applied on server startup.
+/// The db path is not configurable and always requires a restart.
+pub(crate) const RESTART_REQUIRED_KEYS: &[&str] = &[
+    HOST_CONFIG_KEY_NAME,
+    PORT_CONFIG_KEY_NAME,
+    METRICS_HOST_CONFIG_KEY_NAME,
+    METRICS_PORT_CONFIG_KEY_NAME,
+    ADMIN_HOST_CONFIG_KEY_NAME,
+    ADMIN_PORT_CONFIG_KEY_NAME,
+    BACKUP_DIR_CONFIG_KEY_NAME,
+    BACKUP_INTERVAL_CONFIG_KEY_NAME,
+    BACKUP_RETENTION_CONFIG_KEY_NAME,
+    LOG_FORMAT_CONFIG_KEY_NAME,
+    OTLP_ENDPOINT_CONFIG_KEY_NAME,
+    OTLP_PROTOCOL_CONFIG_KEY_NAME,
+    HEALTH_CHECK_INTERVAL_CONFIG_KEY_NAME,
+    SHUTDOWN_DEADLINE_CONFIG_KEY_NAME,
+    MASTER_KEY_FILE_CONFIG_KEY_NAME,
+    PREV_MASTER_KEY_FILE_CONFIG_KEY_NAME,
+    ADDRESS_HASH_SECRET_FILE_CONFIG_KEY_NAME,
+];
+
+/// A changed config setting
+#[derive(Debug, PartialEq)]
+pub(crate) struct ConfigChange {
+    pub(crate) key: String,
+    pub(crate) old: Option<String>,
+    pub(crate) new: Option<String>,
+}
+
+impl ConfigChange {
+    pub(crate) fn restart_required(&self) -> bool {
+        RESTART_REQUIRED_KEYS.contains(&self.key.as_str())
+    }
+}
+
+impl fmt::Display for ConfigChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.key,
+            self.old.as_deref().unwrap_or("<unset>"),
+            self.new.as_deref().unwrap_or("<unset>")
+        )
+    }
+}
+
+/// Returns the settings changed between two configs, sorted by key
+pub(crate) fn config_changes(old: &Config, new: &Config) -> Result<Vec<ConfigChange>> {
+    let old = config_values(old)?;
+    let new = config_values(new)?;
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    Ok(keys
+        .into_iter()
+        .filter(|k| old.get(*k) != new.get(*k))
+        .map(|k| ConfigChange {
+            key: k.clone(),
+            old: old.get(k).cloned(),
+            new: new.get(k).cloned(),
+        })
+        .collect())
+}
+
+/// Returns the config to apply on reload - the new config with the settings that require a
+/// restart kept at their current values
+pub(crate) fn runtime_config(current: &Config, new: &Config) -> Result<Config> {
+    let current = current.collect()?;
+    let mut values = new.collect()?;
+    for key in RESTART_REQUIRED_KEYS {
+        match current.get(*key) {
+            Some(value) => values.insert(key.to_string(), value.clone()),
+            None => values.remove(*key),
+        };
+    }
+
+    let mut config = Config::default();
+    for (key, value) in values {
+        config.set(&key, value)?;
+    }
+    Ok(config)
+}
+
+/// Returns all config settings as strings
+pub(crate) fn config_values(config: &Config) -> Result<HashMap<String, String>> {
+    Ok(config
+        .collect()?
+        .into_iter()
+        .map(|(k, v)| (k, value_string(v)))
+        .collect())
+}
+
+fn value_string(value: Value) -> String {
+    let debug = format!("{:?}", value);
+    value.into_str().unwrap_or(debug)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_default_config;
+    use crate::limits::MAX_ADDRESS_SIZE_CONFIG_KEY_NAME;
+
+    #[test]
+    fn keep_restart_required_settings() {
+        let current = get_default_config();
+        let mut new = get_default_config();
+        new.set(PORT_CONFIG_KEY_NAME, 7000)
+            .unwrap()
+            .set(MAX_ADDRESS_SIZE_CONFIG_KEY_NAME, 64)
+            .unwrap()
+            .set(MASTER_KEY_FILE_CONFIG_KEY_NAME, "./master_key")
+            .unwrap();
+
+        let changes = config_changes(&current, &new).unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                ConfigChange {
+                    key: MASTER_KEY_FILE_CONFIG_KEY_NAME.to_string(),
+                    old: None,
+                    new: Some("./master_key".to_string()),
+                },
+                ConfigChange {
+                    key: MAX_ADDRESS_SIZE_CONFIG_KEY_NAME.to_string(),
+                    old: Some("128".to_string()),
+                    new: Some("64".to_string()),
+                },
+                ConfigChange {
+                    key: PORT_CONFIG_KEY_NAME.to_string(),
+                    old: Some("6667".to_string()),
+                    new: Some("7000".to_string()),
+                },
+            ]
+        );
+        let restart_required: Vec<bool> = changes.iter().map(|c| c.restart_required()).collect();
+        assert_eq!(restart_required, vec![true, false, true]);
+        assert_eq!(changes[2].to_string(), "port: 6667 -> 7000");
+
+        // only the runtime setting is applied
+        let config = runtime_config(&current, &new).unwrap();
+        let applied = config_changes(&current, &config).unwrap();
+        assert_eq!(applied.len(), 1);
+        assert_eq!(applied[0].key, MAX_ADDRESS_SIZE_CONFIG_KEY_NAME);
+        assert_eq!(config.get_int(PORT_CONFIG_KEY_NAME).unwrap(), 6667);
+        assert!(config.get_str(MASTER_KEY_FILE_CONFIG_KEY_NAME).is_err());
+    }
+}
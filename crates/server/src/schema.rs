@@ -0,0 +1,150 @@
+use crate::encryption::DataCipher;
+use crate::server::QUARANTINE_CF;
+use anyhow::{bail, Result};
+use rocksdb::{IteratorMode, Options, WriteBatch, DB};
+use std::convert::TryInto;
+use tracing::info_span;
+
+/// Key of the db schema version, stored unencrypted as a big endian u32.
+/// Dbs created before the schema version was added have no version key, and are version 0.
+pub(crate) const SCHEMA_VERSION_KEY: &[u8] = b"schema_version";
+
+/// Adds the writes that upgrade the db from the previous schema version to a batch.
+/// The batch is written together with the new schema version, so a failed migration leaves the
+/// db records unchanged. Column families can't be added in a batch, so they are created by the
+/// migration and are kept if it fails.
+type Migration = fn(&mut DB, &DataCipher, &mut WriteBatch) -> Result<()>;
+
+/// MIGRATIONS[i] upgrades the db from schema version i to i + 1
+const MIGRATIONS: &[Migration] = &[migrate_v0_to_v1, migrate_v1_to_v2];
+
+/// Schema version of the db layout used by this server
+pub(crate) const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Version 1 has the version 0 layout - a bincode list of encoded messages stored under each
+/// address key, and a bincode set of the address keys stored under all_addresses - and adds the
+/// schema version key
+fn migrate_v0_to_v1(_db: &mut DB, _cipher: &DataCipher, _batch: &mut WriteBatch) -> Result<()> {
+    Ok(())
+}
+
+/// Version 2 adds the quarantine column family of records that could not be read
+fn migrate_v1_to_v2(db: &mut DB, _cipher: &DataCipher, _batch: &mut WriteBatch) -> Result<()> {
+    create_quarantine_cf(db)
+}
+
+// Create the quarantine column family, unless a failed migration already created it
+fn create_quarantine_cf(db: &mut DB) -> Result<()> {
+    if db.cf_handle(QUARANTINE_CF).is_none() {
+        db.create_cf(QUARANTINE_CF, &Options::default())?;
+    }
+    Ok(())
+}
+
+/// Returns the db schema version, or None for a db without a version key
+pub(crate) fn schema_version(db: &DB) -> Result<Option<u32>> {
+    match db.get(SCHEMA_VERSION_KEY)? {
+        Some(data) => match data.as_slice().try_into() {
+            Ok(bytes) => Ok(Some(u32::from_be_bytes(bytes))),
+            Err(_) => bail!("invalid db schema version record"),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Check the schema version of an opened db. A new db is set to the current schema version,
+/// and a db written by a newer server version is rejected.
+pub(crate) fn check_schema_version(db: &mut DB) -> Result<()> {
+    match schema_version(db)? {
+        Some(version) if version > SCHEMA_VERSION => bail!(
+            "db schema version {} is newer than the supported version {} - upgrade the server",
+            version,
+            SCHEMA_VERSION
+        ),
+        None if db.iterator(IteratorMode::Start).next().is_none() => {
+            // column families of the current version
+            create_quarantine_cf(db)?;
+            db.put(SCHEMA_VERSION_KEY, SCHEMA_VERSION.to_be_bytes())?;
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Returns true if the db schema version is older than the current version
+pub(crate) fn needs_migration(db: &DB) -> Result<bool> {
+    Ok(schema_version(db)?.unwrap_or(0) < SCHEMA_VERSION)
+}
+
+/// Upgrade the db to the current schema version, one version at a time.
+/// Migrations run when the server config is set, as records are decrypted with the configured
+/// master key.
+pub(crate) fn migrate_schema(db: &mut DB, cipher: &DataCipher) -> Result<()> {
+    check_schema_version(db)?;
+    let mut version = schema_version(db)?.unwrap_or(0);
+    while version < SCHEMA_VERSION {
+        info!(
+            "migrating db schema version {} to {}...",
+            version,
+            version + 1
+        );
+        let mut batch = WriteBatch::default();
+        MIGRATIONS[version as usize](db, cipher, &mut batch)?;
+        batch.put(SCHEMA_VERSION_KEY, (version + 1).to_be_bytes());
+        info_span!("db.write").in_scope(|| db.write(batch))?;
+        version += 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_db_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!(
+            "multisig_schema_{}",
+            hex::encode(rand::random::<[u8; 8]>())
+        ))
+    }
+
+    #[test]
+    fn migrate_db_schema() {
+        let cipher = DataCipher::default();
+
+        // a new db is created with the current version
+        let path = temp_db_path();
+        let mut db = DB::open_default(&path).unwrap();
+        check_schema_version(&mut db).unwrap();
+        assert_eq!(schema_version(&db).unwrap(), Some(SCHEMA_VERSION));
+        assert!(db.cf_handle(QUARANTINE_CF).is_some());
+        drop(db);
+        let _ = std::fs::remove_dir_all(&path);
+
+        // an unversioned db is migrated and its records are kept
+        let path = temp_db_path();
+        let mut db = DB::open_default(&path).unwrap();
+        db.put(b"all_addresses", b"addresses").unwrap();
+        db.put([1; 32], b"messages").unwrap();
+        check_schema_version(&mut db).unwrap();
+        assert_eq!(schema_version(&db).unwrap(), None);
+        assert!(needs_migration(&db).unwrap());
+        migrate_schema(&mut db, &cipher).unwrap();
+        assert_eq!(schema_version(&db).unwrap(), Some(SCHEMA_VERSION));
+        assert!(!needs_migration(&db).unwrap());
+        assert!(db.cf_handle(QUARANTINE_CF).is_some());
+        assert_eq!(db.get([1; 32]).unwrap().unwrap(), b"messages");
+        assert_eq!(db.get(b"all_addresses").unwrap().unwrap(), b"addresses");
+
+        // a db of a newer server is rejected
+        db.put(SCHEMA_VERSION_KEY, (SCHEMA_VERSION + 1).to_be_bytes())
+            .unwrap();
+        assert!(check_schema_version(&mut db).is_err());
+        assert!(migrate_schema(&mut db, &cipher).is_err());
+
+        db.put(SCHEMA_VERSION_KEY, b"bad").unwrap();
+        assert!(check_schema_version(&mut db).is_err());
+        drop(db);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+}
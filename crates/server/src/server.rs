@@ -1,163 +1,620 @@
-use crate::MSG_RETENTION_DUR_CONFIG_KEY_NAME;
-use anyhow::{anyhow, bail, Result};
-use api::api::{GetMessagesRequest, StoreMessageRequest, UserMessage};
+use crate::address_hash::AddressHasher;
+use crate::address_locks::AddressLocks;
+use crate::clock::{Clock, SystemClock};
+use crate::encryption::DataCipher;
+use crate::limits::Limits;
+use crate::logging::log_address;
+use crate::metrics;
+use crate::schema::{check_schema_version, migrate_schema, needs_migration, SCHEMA_VERSION_KEY};
+use anyhow::{bail, Result};
+use api::api::{
+    AddressInfo, ExportRecord, GetDbStatsResponse, GetMessagesRequest, GetServerInfoResponse,
+    QuarantinedRecord, StoreMessageRequest, TransactionTypeLimits, UserMessage,
+};
+use api::api_extensions::ENVELOPE_TAG_SIZE;
 use chrono::prelude::*;
 use config::Config;
 use prost::Message;
-use rocksdb::{Options, DB};
+use rocksdb::checkpoint::Checkpoint;
+use rocksdb::{ColumnFamily, Direction, IteratorMode, Options, WriteBatch, DB};
 use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::time::Instant;
+use tracing::{info_span, Instrument, Span};
 use xactor::*;
 
-const MAX_ADDRESS_SIZE_BYTES: usize = 128;
-const MAX_TX_DATA_SIZE_BYTES: usize = 2048;
-const ALL_ADDRESSES_KEY: &[u8] = b"all_addresses";
-const DB_FILE_PATH: &str = "./data_store";
-// new messages with creation time bigger than window relative to server time will be rejected
-const ACCEPTED_MESSAGES_TIME_WINDOW_SECS: i64 = 60 * 60 * 24;
-
+pub(crate) const ALL_ADDRESSES_KEY: &[u8] = b"all_addresses";
+// id of the secret used to hash address keys. Not set when address keys are not hashed.
+const ADDRESS_HASH_SECRET_ID_KEY: &[u8] = b"address_hash_secret_id";
+// keys used by the server that can't be used as addresses
+const RESERVED_KEYS: &[&[u8]] = &[
+    ALL_ADDRESSES_KEY,
+    ADDRESS_HASH_SECRET_ID_KEY,
+    SCHEMA_VERSION_KEY,
+];
+// db metadata keys that are stored unencrypted
+const UNENCRYPTED_KEYS: &[&[u8]] = &[ADDRESS_HASH_SECRET_ID_KEY, SCHEMA_VERSION_KEY];
+pub(crate) const DB_FILE_PATH: &str = "./data_store";
+pub(crate) const SERVER_VERSION: &str = env!("CARGO_PKG_VERSION");
+pub(crate) const MSG_RETENTION_DUR_CONFIG_KEY_NAME: &str = "msg_retention_duration";
+// column family of records that could not be read, keyed by quarantine time and a random suffix
+pub(crate) const QUARANTINE_CF: &str = "quarantine";
+// estimated size of the db files
+const DB_SIZE_PROPERTY: &str = "rocksdb.total-sst-files-size";
+
+/// The server system service. Owns the config and handles maintenance operations.
+/// Rpc requests are handled concurrently with the server's MessageStore.
+#[derive(Default)]
 pub(crate) struct Server {
     config: Config,
-    db: Option<DB>,
+    store: MessageStore,
+}
+
+impl Server {
+    /// Returns a server that uses clock for the current time instead of the system clock
+    #[cfg(test)]
+    pub(crate) fn with_clock(clock: Arc<dyn Clock>) -> Server {
+        Server {
+            config: Config::default(),
+            store: MessageStore::new(clock),
+        }
+    }
 }
 
 #[async_trait::async_trait]
 impl Actor for Server {
     async fn started(&mut self, _ctx: &mut Context<Self>) -> Result<()> {
         info!("Server system service starting...");
-        self.db = Some(DB::open_default(DB_FILE_PATH)?);
-        Ok(())
+        self.store.open(DB_FILE_PATH)
     }
 
     async fn stopped(&mut self, _ctx: &mut Context<Self>) {
+        // close the db. It is closed when in-flight requests release it
+        self.store.set_db(None);
         info!("Server system service stopped");
     }
 }
 
 impl Service for Server {}
-impl Default for Server {
-    fn default() -> Self {
-        Server {
-            config: Config::default(),
-            db: None,
-        }
+
+/// Open the db at path with its column families, creating it if missing.
+/// Column families are created by the schema migrations that add them.
+pub(crate) fn open_db_path(path: impl AsRef<Path>) -> Result<DB> {
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    // a new db has only the default column family
+    let cfs = DB::list_cf(&opts, path.as_ref()).unwrap_or_default();
+    Ok(DB::open_cf(&opts, path, cfs)?)
+}
+
+// Update the db size metric with the db estimate
+fn update_db_size_metric(db: &DB) {
+    match db.property_int_value(DB_SIZE_PROPERTY) {
+        Ok(Some(size)) => metrics::set_db_size(size),
+        Ok(None) => {}
+        Err(e) => warn!("failed to get db size: {}", e),
     }
 }
 
-//////////////////
+fn quarantine_cf(db: &DB) -> Result<&ColumnFamily> {
+    match db.cf_handle(QUARANTINE_CF) {
+        Some(cf) => Ok(cf),
+        None => {
+            error!("internal state error - quarantine column family is missing");
+            bail!("internal data error")
+        }
+    }
+}
 
-#[message(result = "Result<()>")]
-pub(crate) struct DeleteDb;
+/// A message input validation error
+#[derive(Debug)]
+pub(crate) struct InvalidInput(String);
 
-/// Get the current pos compute config
-#[async_trait::async_trait]
-impl Handler<DeleteDb> for Server {
-    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: DeleteDb) -> Result<()> {
-        let _ = DB::destroy(&Options::default(), DB_FILE_PATH);
-        let _ = std::fs::remove_dir_all(DB_FILE_PATH);
-        Ok(())
+impl std::fmt::Display for InvalidInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid input: {}", self.0)
     }
 }
 
-//////////////////
+impl std::error::Error for InvalidInput {}
 
-#[message(result = "Result<(Config)>")]
-pub(crate) struct GetConfig;
+// Count a message rejected by validation and return the rejection error
+fn rejected(reason: &str, error: &str) -> anyhow::Error {
+    metrics::message_rejected(reason);
+    tracing::warn!(reason, "message rejected: {}", error);
+    anyhow::Error::new(InvalidInput(error.to_string()))
+}
 
-/// Get the current pos compute config
-#[async_trait::async_trait]
-impl Handler<GetConfig> for Server {
-    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: GetConfig) -> Result<Config> {
-        Ok(self.config.clone())
+/// Make sure address keys in the db match the configured address hashing.
+/// Keys of a db created without address hashing are hashed when a secret is first provided.
+fn migrate_address_keys(db: &DB, cipher: &DataCipher, hasher: &AddressHasher) -> Result<()> {
+    let secret_id = hasher.secret_id()?;
+    match (db.get(ADDRESS_HASH_SECRET_ID_KEY)?, secret_id) {
+        (None, None) => Ok(()),
+        (Some(db_id), Some(id)) if db_id == id => Ok(()),
+        (Some(_), Some(_)) => bail!("db address keys were hashed with a different secret"),
+        (Some(_), None) => bail!("db address keys are hashed and no address hash secret provided"),
+        (None, Some(id)) => {
+            info!("hashing db address keys...");
+            let mut batch = WriteBatch::default();
+            if let Some(record) = db.get(ALL_ADDRESSES_KEY)? {
+                let data = cipher.decrypt(ALL_ADDRESSES_KEY, &record)?;
+                let addresses: HashSet<Vec<u8>> = bincode::deserialize(data.as_ref())?;
+                let mut hashed_addresses: HashSet<Vec<u8>> = HashSet::new();
+                for address in addresses.iter() {
+                    let key = hasher.db_key(address)?;
+                    if let Some(record) = db.get(address)? {
+                        let data = cipher.decrypt(address, &record)?;
+                        batch.put(&key, cipher.encrypt(&key, &data)?);
+                        batch.delete(address);
+                    }
+                    hashed_addresses.insert(key);
+                }
+                let encoded_addresses: Vec<u8> = bincode::serialize(&hashed_addresses)?;
+                batch.put(
+                    ALL_ADDRESSES_KEY,
+                    cipher.encrypt(ALL_ADDRESSES_KEY, &encoded_addresses)?,
+                );
+                info!("hashed {} address keys", addresses.len());
+            }
+            batch.put(ADDRESS_HASH_SECRET_ID_KEY, id);
+            info_span!("db.write").in_scope(|| db.write(batch))?;
+            Ok(())
+        }
     }
 }
 
-//////////////////
+/// The settings used to read, write and validate messages.
+/// Replaced as a whole when the config changes, so a request uses a consistent snapshot.
+#[derive(Clone, Default)]
+struct StoreSettings {
+    cipher: Arc<DataCipher>,
+    hasher: Arc<AddressHasher>,
+    limits: Limits,
+    // seconds messages are kept
+    msg_retention_duration: u64,
+}
 
-#[message(result = "Result<()>")]
-pub(crate) struct SetConfig(pub(crate) Config);
+impl StoreSettings {
+    /// Read a record from the db and decrypt it
+    #[tracing::instrument(name = "db.get", skip_all)]
+    fn db_get(&self, db: &DB, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match db.get(key)? {
+            Some(record) => Ok(Some(self.cipher.decrypt(key, &record)?)),
+            None => Ok(None),
+        }
+    }
 
-/// Set server config
-#[async_trait::async_trait]
-impl Handler<SetConfig> for Server {
-    async fn handle(&mut self, _ctx: &mut Context<Self>, msg: SetConfig) -> Result<()> {
-        self.config = msg.0;
+    /// Encrypt a record and write it to the db
+    #[tracing::instrument(name = "db.put", skip_all)]
+    fn db_put(&self, db: &DB, key: &[u8], value: &[u8]) -> Result<()> {
+        db.put(key, self.cipher.encrypt(key, value)?)?;
         Ok(())
     }
-}
 
-//////////////////
+    /// Add a record that can't be read to the quarantine, to be inspected by an operator.
+    /// The record is removed from its address by the caller in the same batch.
+    fn quarantine(
+        &self,
+        db: &DB,
+        batch: &mut WriteBatch,
+        key: &[u8],
+        reason: String,
+        data: &[u8],
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        error!(
+            "quarantining a record of address key {}: {}",
+            log_address(key),
+            reason
+        );
+        let record = QuarantinedRecord {
+            id: String::new(),
+            address_key: key.to_vec(),
+            reason,
+            quarantined: now.timestamp() as u64,
+            data_size: data.len() as u32,
+            data: data.to_vec(),
+        };
+        let mut record_bin = Vec::with_capacity(record.encoded_len());
+        record.encode(&mut record_bin)?;
+
+        let mut id = now.timestamp_millis().to_be_bytes().to_vec();
+        id.extend_from_slice(&rand::random::<[u8; 8]>());
+        batch.put_cf(
+            quarantine_cf(db)?,
+            &id,
+            self.cipher.encrypt(&id, &record_bin)?,
+        );
+        Ok(())
+    }
 
-#[message(result = "Result<Vec<UserMessage>>")]
-pub(crate) struct GetMessages(pub(crate) GetMessagesRequest);
+    /// Read a quarantined record by its db key
+    fn quarantined_record(&self, id: &[u8], record: &[u8]) -> Result<QuarantinedRecord> {
+        let data = self.cipher.decrypt(id, record)?;
+        let mut record = QuarantinedRecord::decode(data.as_slice())?;
+        record.id = hex::encode(id);
+        Ok(record)
+    }
+    /// Delete the expired messages stored under an address key, and move the address record or
+    /// its messages to the quarantine when they can't be read. Adds the writes to batch.
+    /// Returns the number of kept, deleted and quarantined messages. A record that can't be read
+    /// is counted as one quarantined message.
+    fn prune_address(
+        &self,
+        db: &DB,
+        key: &[u8],
+        now: DateTime<Utc>,
+        batch: &mut WriteBatch,
+    ) -> Result<(usize, usize, usize)> {
+        let record = match info_span!("db.get").in_scope(|| db.get(key))? {
+            Some(record) => record,
+            None => {
+                warn!("no messages found for address in index {:?}", key);
+                return Ok((0, 0, 0));
+            }
+        };
+        let data = match self.cipher.decrypt(key, &record) {
+            Ok(data) => data,
+            // the master key config is wrong - the record isn't corrupt
+            Err(e) if self.cipher.is_unknown_key(&record) => return Err(e),
+            Err(e) => {
+                let reason = format!("failed to decrypt the record: {}", e);
+                self.quarantine(db, batch, key, reason, &record, now)?;
+                batch.delete(key);
+                return Ok((0, 0, 1));
+            }
+        };
+        let messages: Vec<Vec<u8>> = match bincode::deserialize(data.as_ref()) {
+            Ok(messages) => messages,
+            Err(e) => {
+                let reason = format!("failed to decode the messages: {}", e);
+                self.quarantine(db, batch, key, reason, &data, now)?;
+                batch.delete(key);
+                return Ok((0, 0, 1));
+            }
+        };
+
+        // only keep messages that are not too old
+        let min_created = (now.timestamp() as u64).saturating_sub(self.msg_retention_duration);
+        let mut new_messages = Vec::with_capacity(messages.len());
+        let mut deleted = 0;
+        let mut quarantined = 0;
+        for m in messages {
+            match UserMessage::decode(m.as_slice()) {
+                Ok(user_msg) if user_msg.created >= min_created => new_messages.push(m),
+                Ok(_) => deleted += 1,
+                Err(e) => {
+                    let reason = format!("failed to decode a message: {}", e);
+                    self.quarantine(db, batch, key, reason, &m, now)?;
+                    quarantined += 1;
+                }
+            }
+        }
 
-/// Get all messages for an address
-#[async_trait::async_trait]
-impl Handler<GetMessages> for Server {
-    async fn handle(
-        &mut self,
-        _ctx: &mut Context<Self>,
-        msg: GetMessages,
-    ) -> Result<Vec<UserMessage>> {
-        let address = msg.0.address;
+        if new_messages.is_empty() {
+            // no messages for this address - delete the address from the db
+            batch.delete(key);
+        } else if deleted + quarantined > 0 {
+            // store messages for this address excluding the old deleted messages
+            let encoded_messages: Vec<u8> = bincode::serialize(&new_messages)?;
+            batch.put(key, self.cipher.encrypt(key, &encoded_messages)?);
+        }
+        Ok((new_messages.len(), deleted, quarantined))
+    }
 
-        if let Some(db) = self.db.as_ref() {
-            match db.get(address) {
-                Ok(Some(data)) => {
-                    let messages: Vec<Vec<u8>> = bincode::deserialize(data.as_ref())?;
-                    let mut res: Vec<UserMessage> = vec![];
-                    for m in messages {
-                        res.push(UserMessage::decode(m.as_ref())?);
-                    }
-                    Ok(res)
+    /// Returns the db keys of all addresses with stored messages
+    fn stored_addresses(&self, db: &DB) -> Result<HashSet<Vec<u8>>> {
+        match self.db_get(db, ALL_ADDRESSES_KEY)? {
+            Some(data) => Ok(bincode::deserialize(data.as_ref())?),
+            None => Ok(HashSet::new()),
+        }
+    }
+
+    /// Returns the encoded messages stored for an address db key
+    fn stored_messages(&self, db: &DB, key: &[u8]) -> Result<Vec<Vec<u8>>> {
+        match self.db_get(db, key)? {
+            Some(data) => Ok(bincode::deserialize(data.as_ref())?),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Validate a new message with the configured limits. The message creation time is checked
+    /// against the accepted time window around now when now is provided - imported messages are
+    /// not checked.
+    fn validate_message(&self, user_msg: &UserMessage, now: Option<DateTime<Utc>>) -> Result<()> {
+        let address = &user_msg.address;
+        if address.is_empty() || address.len() > self.limits.max_address_size {
+            return Err(rejected("address_size", "address size failed validation"));
+        }
+
+        if RESERVED_KEYS.contains(&address.as_slice()) {
+            return Err(rejected("reserved_address", "address failed validation"));
+        }
+
+        // verify that message creation time is not outside of the server acceptable time window
+        if let Some(now) = now {
+            let t = user_msg.created as i64;
+            if i64::abs(now.timestamp() - t) > self.limits.accepted_time_window_secs {
+                return Err(rejected(
+                    "time_window",
+                    "message creation time outside of acceptable server time window",
+                ));
+            }
+        }
+
+        let max_tx_data_size = self.limits.max_tx_data_size(user_msg.transaction_type);
+
+        let tx_data = &user_msg.transaction_data;
+        match user_msg.encrypted_transaction_data.as_ref() {
+            Some(envelope) => {
+                // encrypted data is opaque to the server - only validate the envelope structure
+                if !tx_data.is_empty() {
+                    return Err(rejected(
+                        "transaction_data",
+                        "both transaction data and encrypted transaction data provided",
+                    ));
                 }
-                Ok(None) => Ok(vec![]),
-                Err(e) => {
-                    error!("failed db get: {}", e);
-                    bail!("internal data error")
+                envelope
+                    .validate()
+                    .map_err(|e| rejected("envelope", &e.to_string()))?;
+                if envelope.ciphertext.len() > max_tx_data_size + ENVELOPE_TAG_SIZE
+                    || envelope.recipients.len() > self.limits.max_envelope_recipients
+                {
+                    return Err(rejected(
+                        "envelope",
+                        "encrypted transaction data failed validation",
+                    ));
+                }
+            }
+            None => {
+                if tx_data.is_empty() || tx_data.len() > max_tx_data_size {
+                    return Err(rejected(
+                        "transaction_data",
+                        "transaction data failed validation",
+                    ));
                 }
             }
-        } else {
-            error!("internal state error - db is none");
-            bail!("internal data error")
         }
+        Ok(())
+    }
+
+    /// Returns true if an encoded message is older than the retention duration
+    fn is_expired(&self, message: &[u8], now: u64) -> Result<bool> {
+        let created = UserMessage::decode(message)?.created;
+        Ok(created < now.saturating_sub(self.msg_retention_duration))
     }
 }
 
-///////////////////////
+/// The messages db and the settings used to access it, shared by the server and concurrent
+/// rpc handlers. Reads run concurrently, writes are serialized per address, and updates of
+/// the addresses index are serialized with a separate lock held only while the index is
+/// written. Clones share the same db.
+#[derive(Clone)]
+pub(crate) struct MessageStore {
+    inner: Arc<StoreInner>,
+}
 
-#[message(result = "Result<()>")]
-pub(crate) struct StoreMessage(pub(crate) StoreMessageRequest);
+struct StoreInner {
+    clock: Arc<dyn Clock>,
+    db: RwLock<Option<Arc<DB>>>,
+    settings: RwLock<Arc<StoreSettings>>,
+    address_locks: AddressLocks,
+    index_lock: Mutex<()>,
+    // only one pruning runs at a time
+    prune_lock: Mutex<()>,
+}
 
-/// Set server config
-#[async_trait::async_trait]
-impl Handler<StoreMessage> for Server {
-    async fn handle(&mut self, _ctx: &mut Context<Self>, msg: StoreMessage) -> Result<()> {
-        // validate all input
-        let user_msg = msg
-            .0
-            .user_message
-            .ok_or_else(|| anyhow!("invalid input: missing user message"))?;
+impl Default for MessageStore {
+    fn default() -> Self {
+        MessageStore::new(Arc::new(SystemClock))
+    }
+}
 
-        let address = &user_msg.address;
-        if address.is_empty() || address.len() > MAX_ADDRESS_SIZE_BYTES {
-            bail!("invalid input: address size failed validation")
+impl MessageStore {
+    /// Returns a store without an open db that uses clock for the current time
+    fn new(clock: Arc<dyn Clock>) -> MessageStore {
+        MessageStore {
+            inner: Arc::new(StoreInner {
+                clock,
+                db: RwLock::default(),
+                settings: RwLock::default(),
+                address_locks: AddressLocks::default(),
+                index_lock: Mutex::default(),
+                prune_lock: Mutex::default(),
+            }),
         }
+    }
 
-        if *address == *ALL_ADDRESSES_KEY {
-            bail!("invalid input: address failed validation")
+    /// Returns the current time of the store's clock
+    fn now(&self) -> DateTime<Utc> {
+        self.inner.clock.now()
+    }
+
+    /// Returns the db, or None if it is not open
+    fn open_db(&self) -> Option<Arc<DB>> {
+        self.inner
+            .db
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Returns the open db
+    pub(crate) fn db(&self) -> Result<Arc<DB>> {
+        match self.open_db() {
+            Some(db) => Ok(db),
+            None => {
+                error!("internal state error - db is none");
+                bail!("internal data error")
+            }
         }
+    }
 
-        // verify that message creation time is not outside of the server acceptable time window
-        let now = Utc::now().timestamp() as i64;
-        let t = user_msg.created as i64;
-        if i64::abs(now - t) > ACCEPTED_MESSAGES_TIME_WINDOW_SECS {
-            bail!("invalid input: message creation time outside of acceptable server time window")
+    pub(crate) fn set_db(&self, db: Option<DB>) {
+        *self.inner.db.write().unwrap_or_else(|e| e.into_inner()) = db.map(Arc::new);
+    }
+
+    /// Open the db at path, and check its schema version
+    pub(crate) fn open(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut db = open_db_path(path)?;
+        check_schema_version(&mut db)?;
+        self.set_db(Some(db));
+        Ok(())
+    }
+
+    /// Apply the settings of a config, and upgrade the open db to the current schema version and
+    /// the configured address hashing. Called on startup, before requests use the db.
+    pub(crate) fn configure(&self, config: &Config) -> Result<()> {
+        let cipher = DataCipher::from_config(config)?;
+        let hasher = AddressHasher::from_config(config)?;
+        let limits = Limits::from_config(config)?;
+        let msg_retention_duration = config.get::<u64>(MSG_RETENTION_DUR_CONFIG_KEY_NAME)?;
+        self.migrate_schema(&cipher)?;
+        if let Some(db) = self.open_db() {
+            migrate_address_keys(&db, &cipher, &hasher)?;
         }
+        if cipher.is_enabled() {
+            info!("db encryption at rest enabled");
+        }
+        self.set_settings(StoreSettings {
+            cipher: Arc::new(cipher),
+            hasher: Arc::new(hasher),
+            limits,
+            msg_retention_duration,
+        });
+        Ok(())
+    }
 
-        let tx_data = &user_msg.transaction_data;
-        if tx_data.is_empty() || tx_data.len() > MAX_TX_DATA_SIZE_BYTES {
-            bail!("invalid input: transaction data failed validation")
+    /// Upgrade the db to the current schema version. Migrations can change the db layout, so they
+    /// fail while requests use the db - they run when the config is set on startup.
+    fn migrate_schema(&self, cipher: &DataCipher) -> Result<()> {
+        let mut db = self.inner.db.write().unwrap_or_else(|e| e.into_inner());
+        let db = match db.as_mut() {
+            Some(db) => db,
+            None => return Ok(()),
+        };
+        if !needs_migration(db)? {
+            return Ok(());
+        }
+        match Arc::get_mut(db) {
+            Some(db) => migrate_schema(db, cipher),
+            None => bail!("db schema can't be migrated while the db is in use"),
+        }
+    }
+
+    /// Returns the current settings
+    fn settings(&self) -> Arc<StoreSettings> {
+        self.inner
+            .settings
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn set_settings(&self, settings: StoreSettings) {
+        *self
+            .inner
+            .settings
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = Arc::new(settings);
+    }
+
+    /// Lock the writes of an address db key until the returned guard is dropped
+    fn lock_address(&self, key: &[u8]) -> MutexGuard<'_, ()> {
+        self.inner.address_locks.lock(key)
+    }
+
+    /// Lock updates of the addresses index until the returned guard is dropped
+    fn lock_index(&self) -> MutexGuard<'_, ()> {
+        self.inner
+            .index_lock
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Run a store operation on the blocking thread pool in the current span, so db reads and
+    /// writes don't block the async runtime
+    pub(crate) async fn spawn<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&MessageStore) -> Result<T> + Send + 'static,
+    {
+        let store = self.clone();
+        let span = Span::current();
+        tokio::task::spawn_blocking(move || span.in_scope(|| f(&store))).await?
+    }
+
+    /// Add an address db key to the addresses index, used to prune old messages from the db
+    fn index_address(&self, db: &DB, settings: &StoreSettings, key: &[u8]) -> Result<()> {
+        let _index = self.lock_index();
+        let mut addresses = settings.stored_addresses(db)?;
+        if addresses.insert(key.to_vec()) {
+            let encoded_addresses: Vec<u8> = bincode::serialize(&addresses)?;
+            settings.db_put(db, ALL_ADDRESSES_KEY, &encoded_addresses)?;
+        }
+        Ok(())
+    }
+
+    /// Get the server version and the messages it accepts
+    pub(crate) fn server_info(&self) -> GetServerInfoResponse {
+        let settings = self.settings();
+        GetServerInfoResponse {
+            version: SERVER_VERSION.to_string(),
+            supported_transaction_types: settings
+                .limits
+                .supported_transaction_types()
+                .map(|(t, size)| TransactionTypeLimits {
+                    transaction_type: t as i32,
+                    max_transaction_data_size: size as u32,
+                })
+                .collect(),
+            max_address_size: settings.limits.max_address_size as u32,
+            max_envelope_recipients: settings.limits.max_envelope_recipients as u32,
+            accepted_time_window: settings.limits.accepted_time_window_secs as u64,
+            message_retention_duration: settings.msg_retention_duration,
+            server_time: self.now().timestamp() as u64,
+            default_max_transaction_data_size: settings.limits.default_max_tx_data_size as u32,
+        }
+    }
+
+    /// Get all messages for an address
+    pub(crate) fn get_messages(&self, request: GetMessagesRequest) -> Result<Vec<UserMessage>> {
+        let settings = self.settings();
+        let address = settings.hasher.db_key(&request.address)?;
+        // server records are stored under the reserved keys
+        if RESERVED_KEYS.contains(&address.as_slice()) {
+            return Ok(vec![]);
+        }
+        let db = self.db()?;
+        match settings.db_get(&db, &address) {
+            Ok(Some(data)) => {
+                let messages: Vec<Vec<u8>> = bincode::deserialize(data.as_ref())?;
+                let mut res: Vec<UserMessage> = vec![];
+                for m in messages {
+                    res.push(UserMessage::decode(m.as_ref())?);
+                }
+                Ok(res)
+            }
+            Ok(None) => Ok(vec![]),
+            Err(e) => {
+                error!("failed db get: {}", e);
+                bail!("internal data error")
+            }
         }
+    }
+
+    /// Validate and store a user message
+    pub(crate) fn store_message(&self, request: StoreMessageRequest) -> Result<()> {
+        let settings = self.settings();
+
+        // validate all input
+        let user_msg = request
+            .user_message
+            .ok_or_else(|| rejected("missing_message", "missing user message"))?;
+
+        settings.validate_message(&user_msg, Some(self.now()))?;
 
         // todo: verify that tx_data is signed by the private key matching one of the multi-sig addresses for an account
         // or a smart contract by using the Spacemesh public API to get these addresses from a network.
@@ -167,194 +624,1696 @@ impl Handler<StoreMessage> for Server {
 
         // input data is valid - store it
         // we store UserMessage in a vector indexed by address
-        if let Some(db) = self.db.as_ref() {
-            match db.get(address.clone()) {
-                Ok(Some(data)) => {
-                    let mut messages: Vec<Vec<u8>> = bincode::deserialize(data.as_ref())?;
-                    messages.push(user_msg_bin);
-                    let encoded_messages: Vec<u8> = bincode::serialize(&messages)?;
-                    db.put(address.clone(), encoded_messages)?;
-                }
-                Ok(None) => {
-                    let messages: Vec<Vec<u8>> = vec![user_msg_bin];
-                    let encoded_messages: Vec<u8> = bincode::serialize(&messages)?;
-                    db.put(address.clone(), encoded_messages)?;
-                }
-                Err(e) => {
-                    error!("failed db get: {}", e);
-                    bail!("internal data error")
-                }
+        let address = settings.hasher.db_key(&user_msg.address)?;
+        let db = self.db()?;
+        let _lock = self.lock_address(&address);
+        let mut messages: Vec<Vec<u8>> = match settings.db_get(&db, &address) {
+            Ok(Some(data)) => bincode::deserialize(data.as_ref())?,
+            Ok(None) => vec![],
+            Err(e) => {
+                error!("failed db get: {}", e);
+                bail!("internal data error")
             }
-            // Add address (e.g. vault's address) to global addresses hashset. Used to prune old messages from the db.
-            match db.get(ALL_ADDRESSES_KEY) {
-                Ok(Some(data)) => {
-                    let mut addresses: HashSet<Vec<u8>> = bincode::deserialize(data.as_ref())?;
-                    addresses.insert(address.clone());
-                    let encoded_addresses: Vec<u8> = bincode::serialize(&addresses)?;
-                    db.put(ALL_ADDRESSES_KEY, encoded_addresses)?;
-                }
-                Ok(None) => {
-                    let mut addresses: HashSet<Vec<u8>> = HashSet::default();
-                    addresses.insert(address.clone());
-                    let encoded_addresses: Vec<u8> = bincode::serialize(&addresses)?;
-                    db.put(ALL_ADDRESSES_KEY, encoded_addresses)?;
-                }
-                Err(e) => {
-                    error!("failed db get: {}", e);
-                    bail!("internal data error")
+        };
+        let new_address = messages.is_empty();
+        messages.push(user_msg_bin);
+        let encoded_messages: Vec<u8> = bincode::serialize(&messages)?;
+        settings.db_put(&db, &address, &encoded_messages)?;
+
+        // an address with stored messages is already in the index
+        if new_address {
+            if let Err(e) = self.index_address(&db, &settings, &address) {
+                error!("failed to update the addresses index: {}", e);
+                bail!("internal data error")
+            }
+        }
+        metrics::message_stored(new_address);
+        Ok(())
+    }
+
+    /// Delete old messages from the db.
+    /// Address records and messages that can't be read are moved to the quarantine, and the other
+    /// addresses are pruned. Each address is locked only while it is pruned, so messages are
+    /// stored and read while this runs.
+    pub(crate) fn delete_old_messages(&self) -> Result<()> {
+        info!("delete old messages task...");
+
+        let _prune = self
+            .inner
+            .prune_lock
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let start = Instant::now();
+        let now = self.now();
+        let settings = self.settings();
+        let db = self.db()?;
+        let addresses = settings.stored_addresses(&db)?;
+        if addresses.is_empty() {
+            info!("No messages stored");
+            metrics::cleanup_completed(start, 0, 0, 0, 0);
+            return Ok(());
+        }
+
+        // addresses that should be removed from the db as they have no messages after messages deletion
+        let mut remove_addresses: HashSet<Vec<u8>> = HashSet::new();
+        let mut deleted_messages = 0;
+        let mut stored_messages = 0;
+        let mut quarantined_messages = 0;
+        for address in addresses.iter() {
+            let _lock = self.lock_address(address);
+            let mut batch = WriteBatch::default();
+            let (kept, deleted, quarantined) =
+                settings.prune_address(&db, address, now, &mut batch)?;
+            if !batch.is_empty() {
+                info_span!("db.write").in_scope(|| db.write(batch))?;
+            }
+            if kept == 0 {
+                remove_addresses.insert(address.clone());
+            }
+            stored_messages += kept;
+            deleted_messages += deleted;
+            quarantined_messages += quarantined;
+        }
+
+        // update the addresses global index based on removed addresses.
+        // the index is read again as addresses may have been added while pruning, and an
+        // address is kept if a message was stored for it after it was pruned
+        let mut removed_addresses = 0;
+        let address_count = {
+            let _index = self.lock_index();
+            let mut addresses = settings.stored_addresses(&db)?;
+            for a in remove_addresses.iter() {
+                if db.get(a)?.is_none() && addresses.remove(a) {
+                    removed_addresses += 1;
                 }
             }
-        } else {
-            error!("internal state error - db is none");
-            bail!("internal data error")
+            if removed_addresses > 0 {
+                let encoded_addresses: Vec<u8> = bincode::serialize(&addresses)?;
+                settings.db_put(&db, ALL_ADDRESSES_KEY, &encoded_addresses)?;
+            }
+            addresses.len()
+        };
+
+        info!(
+            "deleted {} old messages and {} addresses",
+            deleted_messages, removed_addresses
+        );
+        if quarantined_messages > 0 {
+            warn!(
+                "quarantined {} unreadable records - list them with the admin service",
+                quarantined_messages
+            );
+        }
+        metrics::cleanup_completed(
+            start,
+            deleted_messages as u64,
+            removed_addresses as u64,
+            stored_messages as i64,
+            address_count as i64,
+        );
+        update_db_size_metric(&db);
+        Ok(())
+    }
+
+    /// List stored addresses and their message counts, sorted by address.
+    /// Addresses are read from their stored messages, as db keys are hashed when address hashing
+    /// is enabled.
+    pub(crate) fn list_addresses(&self) -> Result<Vec<AddressInfo>> {
+        let settings = self.settings();
+        let db = self.db()?;
+        let mut res = vec![];
+        for key in settings.stored_addresses(&db)? {
+            let messages = settings.stored_messages(&db, &key)?;
+            if let Some(m) = messages.first() {
+                res.push(AddressInfo {
+                    address: UserMessage::decode(m.as_slice())?.address,
+                    message_count: messages.len() as u32,
+                });
+            }
+        }
+        res.sort_by(|a, b| a.address.cmp(&b.address));
+        Ok(res)
+    }
+
+    /// Get the number of stored addresses and messages, and the estimated db size
+    fn db_stats(&self) -> Result<GetDbStatsResponse> {
+        let settings = self.settings();
+        let db = self.db()?;
+        let addresses = settings.stored_addresses(&db)?;
+        let mut message_count = 0;
+        for key in addresses.iter() {
+            message_count += settings.stored_messages(&db, key)?.len() as u64;
         }
+        Ok(GetDbStatsResponse {
+            address_count: addresses.len() as u64,
+            message_count,
+            db_size: db.property_int_value(DB_SIZE_PROPERTY)?.unwrap_or(0),
+        })
+    }
 
+    /// Set the stored messages and addresses metrics from the db stats. The metrics are updated
+    /// as messages are stored, purged and imported, and recounted on each db cleanup.
+    fn update_stored_metrics(&self) -> Result<()> {
+        let stats = self.db_stats()?;
+        metrics::set_stored(stats.message_count as i64, stats.address_count as i64);
         Ok(())
     }
-}
 
-//////////////////
+    /// Delete all messages of an address. Returns the number of deleted messages.
+    fn purge_address(&self, address: Vec<u8>) -> Result<usize> {
+        if RESERVED_KEYS.contains(&address.as_slice()) {
+            return Err(anyhow::Error::new(InvalidInput(
+                "reserved address".to_string(),
+            )));
+        }
+        let settings = self.settings();
+        let key = settings.hasher.db_key(&address)?;
+        let db = self.db()?;
+        let _lock = self.lock_address(&key);
+        let _index = self.lock_index();
+        let deleted_messages = settings.stored_messages(&db, &key)?.len();
+        let mut addresses = settings.stored_addresses(&db)?;
+        let indexed = addresses.remove(&key);
+        if !indexed && deleted_messages == 0 {
+            return Ok(0);
+        }
 
-#[message(result = "Result<()>")]
-pub(crate) struct DeleteOldMessages;
+        let mut batch = WriteBatch::default();
+        batch.delete(&key);
+        let encoded_addresses: Vec<u8> = bincode::serialize(&addresses)?;
+        batch.put(
+            ALL_ADDRESSES_KEY,
+            settings
+                .cipher
+                .encrypt(ALL_ADDRESSES_KEY, &encoded_addresses)?,
+        );
+        info_span!("db.write").in_scope(|| db.write(batch))?;
+        if indexed {
+            metrics::address_purged(deleted_messages);
+        }
+        info!(
+            "purged {} messages of address {}",
+            deleted_messages,
+            log_address(&address)
+        );
+        Ok(deleted_messages)
+    }
 
-/// Delete old messages from the service
-#[async_trait::async_trait]
-impl Handler<DeleteOldMessages> for Server {
-    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: DeleteOldMessages) -> Result<()> {
-        info!("delete old messages task...");
+    /// Store the messages of an exported address that are valid, not expired and not already
+    /// stored, and add the address to the addresses index. Returns the number of imported and
+    /// skipped messages.
+    fn import_record(&self, record: ExportRecord) -> Result<(usize, usize)> {
+        let now = self.now().timestamp() as u64;
+        let settings = self.settings();
+        let key = settings.hasher.db_key(&record.address)?;
+        let db = self.db()?;
+        let _lock = self.lock_address(&key);
+        let mut messages = settings.stored_messages(&db, &key)?;
+        let stored = messages.len();
+        let mut skipped = 0;
+        for user_msg in record.user_messages {
+            let mut user_msg_bin: Vec<u8> = Vec::with_capacity(user_msg.encoded_len());
+            user_msg.encode(&mut user_msg_bin)?;
+            let valid = user_msg.address == record.address
+                && settings.validate_message(&user_msg, None).is_ok();
+            if !valid
+                || settings.is_expired(&user_msg_bin, now)?
+                || messages.contains(&user_msg_bin)
+            {
+                skipped += 1;
+            } else {
+                messages.push(user_msg_bin);
+            }
+        }
 
-        let now = Utc::now().timestamp() as u64;
-        let retention_duration = self
-            .config
-            .get_int(MSG_RETENTION_DUR_CONFIG_KEY_NAME)
-            .unwrap() as u64;
-
-        if let Some(db) = self.db.as_ref() {
-            match db.get(ALL_ADDRESSES_KEY) {
-                Ok(Some(data)) => {
-                    let mut addresses: HashSet<Vec<u8>> = bincode::deserialize(data.as_ref())?;
-
-                    // addresses that should be removed from the db as they have no messages after messages deletion
-                    let mut remove_addresses: HashSet<Vec<u8>> = HashSet::new();
-                    for address in addresses.iter() {
-                        match db.get(address.clone()) {
-                            Ok(Some(data)) => {
-                                let messages: Vec<Vec<u8>> = bincode::deserialize(data.as_ref())?;
-                                // only keep messages that are not too old
-                                let new_messages = messages
-                                    .into_iter()
-                                    .filter(|m| {
-                                        // we can unwrap because we ensured that only UserMessages were inserted previously
-                                        let user_msg: UserMessage =
-                                            UserMessage::decode(m.as_slice()).unwrap();
-
-                                        user_msg.created >= now - retention_duration
-                                    })
-                                    .collect::<Vec<_>>();
-
-                                if new_messages.is_empty() {
-                                    // no messages for this address - delete the address from the db
-                                    db.delete(address.clone())?;
-                                    remove_addresses.insert(address.clone());
-                                } else {
-                                    // store messages for this address excluding the old deleted messages
-                                    let encoded_messages: Vec<u8> =
-                                        bincode::serialize(&new_messages)?;
-                                    db.put(address.clone(), encoded_messages)?;
-                                }
-                            }
-                            Ok(None) => {
-                                warn!("no messages found for address in index {:?}", address)
-                            }
-                            Err(e) => {
-                                error!("failed db get: {}", e);
-                                bail!("internal data error")
-                            }
-                        }
-                    }
+        let imported = messages.len() - stored;
+        if imported > 0 {
+            let mut batch = WriteBatch::default();
+            let encoded_messages: Vec<u8> = bincode::serialize(&messages)?;
+            batch.put(&key, settings.cipher.encrypt(&key, &encoded_messages)?);
+            let _index = self.lock_index();
+            let mut addresses = settings.stored_addresses(&db)?;
+            let new_address = addresses.insert(key);
+            if new_address {
+                let encoded_addresses: Vec<u8> = bincode::serialize(&addresses)?;
+                batch.put(
+                    ALL_ADDRESSES_KEY,
+                    settings
+                        .cipher
+                        .encrypt(ALL_ADDRESSES_KEY, &encoded_addresses)?,
+                );
+            }
+            info_span!("db.write").in_scope(|| db.write(batch))?;
+            metrics::messages_imported(imported, new_address);
+        }
+        Ok((imported, skipped))
+    }
+
+    /// Count the messages DeleteOldMessages would delete, and the addresses it would remove as they
+    /// have no other messages
+    fn old_messages(&self) -> Result<(usize, usize)> {
+        let now = self.now().timestamp() as u64;
+        let settings = self.settings();
+        let db = self.db()?;
+        let mut old_messages = 0;
+        let mut old_addresses = 0;
+        for key in settings.stored_addresses(&db)? {
+            let messages = settings.stored_messages(&db, &key)?;
+            let mut expired = 0;
+            for m in messages.iter() {
+                if settings.is_expired(m, now)? {
+                    expired += 1;
+                }
+            }
+            old_messages += expired;
+            if expired == messages.len() {
+                old_addresses += 1;
+            }
+        }
+        Ok((old_messages, old_addresses))
+    }
+
+    /// Compact all db files, dropping deleted records from disk
+    fn compact_db(&self) -> Result<()> {
+        let db = self.db()?;
+        info_span!("db.compact").in_scope(|| db.compact_range::<&[u8], &[u8]>(None, None));
+        update_db_size_metric(&db);
+        Ok(())
+    }
+
+    /// Check that all db records are readable and that the addresses index matches the stored
+    /// addresses. Returns the problems found.
+    fn verify_db(&self) -> Result<Vec<String>> {
+        let settings = self.settings();
+        let db = self.db()?;
+        let mut problems = vec![];
+        let addresses = match settings.stored_addresses(&db) {
+            Ok(addresses) => addresses,
+            Err(e) => {
+                problems.push(format!("failed to read the addresses index: {}", e));
+                HashSet::new()
+            }
+        };
+
+        for (key, _) in db.iterator(IteratorMode::Start) {
+            if !RESERVED_KEYS.contains(&key.as_ref()) && !addresses.contains(key.as_ref()) {
+                problems.push(format!(
+                    "address {} is not in the addresses index",
+                    hex::encode(&key)
+                ));
+            }
+        }
 
-                    // update the addresses global index based on removed addresses
-                    if !remove_addresses.is_empty() {
-                        for a in remove_addresses.iter() {
-                            addresses.remove(a);
+        for key in addresses.iter() {
+            match settings.stored_messages(&db, key) {
+                Ok(messages) if messages.is_empty() => problems.push(format!(
+                    "indexed address {} has no messages",
+                    hex::encode(key)
+                )),
+                Ok(messages) => {
+                    for m in messages {
+                        if let Err(e) = UserMessage::decode(m.as_slice()) {
+                            problems.push(format!(
+                                "address {} has an invalid message: {}",
+                                hex::encode(key),
+                                e
+                            ));
                         }
-                        let encoded_addresses: Vec<u8> = bincode::serialize(&addresses)?;
-                        db.put(ALL_ADDRESSES_KEY, encoded_addresses)?;
                     }
                 }
-                Ok(None) => {
-                    info!("No messages stored");
-                    return Ok(());
-                }
-                Err(e) => {
-                    error!("failed db get: {}", e);
-                    bail!("internal data error")
-                }
+                Err(e) => problems.push(format!(
+                    "failed to read the messages of address {}: {}",
+                    hex::encode(key),
+                    e
+                )),
             }
-        } else {
-            error!("internal state error - db is none");
-            bail!("internal data error")
         }
+        Ok(problems)
+    }
 
+    /// Create a checkpoint of the db in a new directory
+    fn create_checkpoint(&self, path: PathBuf) -> Result<()> {
+        let db = self.db()?;
+        info_span!("db.checkpoint").in_scope(|| Checkpoint::new(&db)?.create_checkpoint(&path))?;
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
+    /// List quarantined records by quarantine time, without their data
+    pub(crate) fn quarantined_records(&self) -> Result<Vec<QuarantinedRecord>> {
+        let settings = self.settings();
+        let db = self.db()?;
+        let mut res = vec![];
+        for (id, record) in db.iterator_cf(quarantine_cf(&db)?, IteratorMode::Start) {
+            let mut record = settings.quarantined_record(&id, &record)?;
+            record.data = vec![];
+            res.push(record);
+        }
+        Ok(res)
+    }
 
-    use super::*;
-    use crate::get_default_config;
-    use api::api::TransactionType;
-    use log::LevelFilter;
-    use serial_test::*;
+    /// Get a quarantined record and its data by id. Returns None when there is no such record.
+    fn quarantined_record(&self, id: &str) -> Result<Option<QuarantinedRecord>> {
+        let id = match hex::decode(id) {
+            Ok(id) => id,
+            Err(_) => return Ok(None),
+        };
+        let settings = self.settings();
+        let db = self.db()?;
+        match db.get_cf(quarantine_cf(&db)?, &id)? {
+            Some(record) => Ok(Some(settings.quarantined_record(&id, &record)?)),
+            None => Ok(None),
+        }
+    }
 
-    fn setup_test() {
-        // enable logging
-        let _ = env_logger::builder()
-            .is_test(false)
-            .filter_level(LevelFilter::Info)
-            .try_init();
+    /// Delete a quarantined record by id. Returns false when there is no such record.
+    fn delete_quarantined_record(&self, id: &str) -> Result<bool> {
+        let key = match hex::decode(id) {
+            Ok(key) => key,
+            Err(_) => return Ok(false),
+        };
+        let db = self.db()?;
+        let cf = quarantine_cf(&db)?;
+        if db.get_cf(cf, &key)?.is_none() {
+            return Ok(false);
+        }
+        db.delete_cf(cf, &key)?;
+        info!("deleted quarantined record {}", id);
+        Ok(true)
+    }
 
-        // delete the db
+    /// Re-encrypt up to batch_size records that are not encrypted with the current master key.
+    /// Returns the key to resume from, or None when all records were processed.
+    /// Quarantined records are re-encrypted after the last batch of address records.
+    fn reencrypt_records(
+        &self,
+        from: Option<Vec<u8>>,
+        batch_size: usize,
+    ) -> Result<Option<Vec<u8>>> {
+        let settings = self.settings();
+        if !settings.cipher.is_enabled() {
+            return Ok(None);
+        }
+
+        let db = self.db()?;
+        let mode = match from.as_ref() {
+            Some(key) => IteratorMode::From(key, Direction::Forward),
+            None => IteratorMode::Start,
+        };
+        for (count, (key, _)) in db.iterator(mode).enumerate() {
+            if count == batch_size {
+                return Ok(Some(key.to_vec()));
+            }
+            if UNENCRYPTED_KEYS.contains(&key.as_ref()) {
+                continue;
+            }
+            // the record is read again while locked, so a concurrent write isn't overwritten
+            let _lock = if key.as_ref() == ALL_ADDRESSES_KEY {
+                self.lock_index()
+            } else {
+                self.lock_address(&key)
+            };
+            if let Some(record) = db.get(&key)? {
+                if settings.cipher.needs_reencryption(&record) {
+                    let value = settings.cipher.decrypt(&key, &record)?;
+                    settings.db_put(&db, &key, &value)?;
+                }
+            }
+        }
+        let cf = quarantine_cf(&db)?;
+        for (id, record) in db.iterator_cf(cf, IteratorMode::Start) {
+            if settings.cipher.needs_reencryption(&record) {
+                let value = settings.cipher.decrypt(&id, &record)?;
+                db.put_cf(cf, &id, settings.cipher.encrypt(&id, &value)?)?;
+            }
+        }
+        Ok(None)
+    }
+}
+
+//////////////////
+
+/// A message handled in a tracing span, so events logged by its handler carry the span's fields.
+/// Handler calls are run in the actor's task and don't otherwise inherit the caller's span.
+pub(crate) struct Traced<M>(pub(crate) M, pub(crate) Span);
+
+impl<M> Traced<M> {
+    /// Wrap a message to be handled in the current span
+    pub(crate) fn new(msg: M) -> Traced<M> {
+        Traced(msg, Span::current())
+    }
+}
+
+impl<M: xactor::Message> xactor::Message for Traced<M> {
+    type Result = M::Result;
+}
+
+#[async_trait::async_trait]
+impl<M: xactor::Message> Handler<Traced<M>> for Server
+where
+    Server: Handler<M>,
+{
+    async fn handle(&mut self, ctx: &mut Context<Self>, msg: Traced<M>) -> M::Result {
+        let Traced(msg, span) = msg;
+        Handler::<M>::handle(self, ctx, msg).instrument(span).await
+    }
+}
+
+//////////////////
+
+#[message(result = "Result<()>")]
+pub(crate) struct DeleteDb;
+
+/// Get the current pos compute config
+#[async_trait::async_trait]
+impl Handler<DeleteDb> for Server {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: DeleteDb) -> Result<()> {
+        let _ = DB::destroy(&Options::default(), DB_FILE_PATH);
         let _ = std::fs::remove_dir_all(DB_FILE_PATH);
+        Ok(())
+    }
+}
+
+//////////////////
+
+#[message(result = "Result<()>")]
+pub(crate) struct CheckDb;
+
+/// Check that the db is open and readable, and update the db size metric
+#[async_trait::async_trait]
+impl Handler<CheckDb> for Server {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: CheckDb) -> Result<()> {
+        if let Some(db) = self.store.open_db() {
+            db.get(ALL_ADDRESSES_KEY)?;
+            update_db_size_metric(&db);
+            Ok(())
+        } else {
+            bail!("db is not open")
+        }
+    }
+}
+
+//////////////////
+
+#[message(result = "Result<()>")]
+pub(crate) struct FlushDb;
+
+/// Flush db writes to disk before the server is stopped
+#[async_trait::async_trait]
+impl Handler<FlushDb> for Server {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: FlushDb) -> Result<()> {
+        if let Some(db) = self.store.open_db() {
+            db.flush()?;
+            info!("db flushed");
+            Ok(())
+        } else {
+            bail!("db is not open")
+        }
+    }
+}
+
+//////////////////
+
+#[message(result = "Result<()>")]
+pub(crate) struct BackupDb(pub(crate) PathBuf);
+
+/// Create a checkpoint of the db in a new directory
+#[async_trait::async_trait]
+impl Handler<BackupDb> for Server {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, msg: BackupDb) -> Result<()> {
+        self.store
+            .spawn(move |store| store.create_checkpoint(msg.0))
+            .await
+    }
+}
+
+//////////////////
+
+#[message(result = "Result<(Config)>")]
+pub(crate) struct GetConfig;
+
+/// Get the current pos compute config
+#[async_trait::async_trait]
+impl Handler<GetConfig> for Server {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: GetConfig) -> Result<Config> {
+        Ok(self.config.clone())
+    }
+}
+
+//////////////////
+
+#[message(result = "Result<()>")]
+pub(crate) struct SetConfig(pub(crate) Config);
+
+/// Set server config
+#[async_trait::async_trait]
+impl Handler<SetConfig> for Server {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, msg: SetConfig) -> Result<()> {
+        self.store.configure(&msg.0)?;
+        if self.store.open_db().is_some() {
+            if let Err(e) = self
+                .store
+                .spawn(|store| store.update_stored_metrics())
+                .await
+            {
+                warn!("failed to count stored messages: {}", e);
+            }
+        }
+        self.config = msg.0;
+        Ok(())
+    }
+}
+
+//////////////////
+
+#[message(result = "Result<()>")]
+pub(crate) struct ReloadConfig(pub(crate) Config);
+
+/// Apply a reloaded config. Only settings that can change at runtime are applied - the db
+/// encryption and address hashing secrets loaded by SetConfig are kept.
+#[async_trait::async_trait]
+impl Handler<ReloadConfig> for Server {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, msg: ReloadConfig) -> Result<()> {
+        let limits = Limits::from_config(&msg.0)?;
+        let msg_retention_duration = msg.0.get::<u64>(MSG_RETENTION_DUR_CONFIG_KEY_NAME)?;
+        let settings = self.store.settings();
+        self.store.set_settings(StoreSettings {
+            cipher: settings.cipher.clone(),
+            hasher: settings.hasher.clone(),
+            limits,
+            msg_retention_duration,
+        });
+        self.config = msg.0;
+        Ok(())
+    }
+}
+
+//////////////////
+
+#[message(result = "Result<MessageStore>")]
+pub(crate) struct GetMessageStore;
+
+/// Get the message store, to handle rpc requests concurrently without calling the server
+#[async_trait::async_trait]
+impl Handler<GetMessageStore> for Server {
+    async fn handle(
+        &mut self,
+        _ctx: &mut Context<Self>,
+        _msg: GetMessageStore,
+    ) -> Result<MessageStore> {
+        Ok(self.store.clone())
+    }
+}
+
+//////////////////
+
+#[message(result = "Result<Vec<UserMessage>>")]
+pub(crate) struct GetMessages(pub(crate) GetMessagesRequest);
+
+/// Get all messages for an address
+#[async_trait::async_trait]
+impl Handler<GetMessages> for Server {
+    async fn handle(
+        &mut self,
+        _ctx: &mut Context<Self>,
+        msg: GetMessages,
+    ) -> Result<Vec<UserMessage>> {
+        self.store.get_messages(msg.0)
+    }
+}
+
+///////////////////////
+
+#[message(result = "Result<()>")]
+pub(crate) struct StoreMessage(pub(crate) StoreMessageRequest);
+
+/// Validate and store a user message
+#[async_trait::async_trait]
+impl Handler<StoreMessage> for Server {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, msg: StoreMessage) -> Result<()> {
+        self.store.store_message(msg.0)
+    }
+}
+
+//////////////////
+
+#[message(result = "Result<()>")]
+pub(crate) struct DeleteOldMessages;
+
+/// Delete old messages from the service
+#[async_trait::async_trait]
+impl Handler<DeleteOldMessages> for Server {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: DeleteOldMessages) -> Result<()> {
+        self.store.spawn(|store| store.delete_old_messages()).await
+    }
+}
+
+//////////////////
+
+#[message(result = "Result<Vec<AddressInfo>>")]
+pub(crate) struct ListAddresses;
+
+/// List stored addresses and their message counts, sorted by address.
+/// Addresses are read from their stored messages, as db keys are hashed when address hashing
+/// is enabled.
+#[async_trait::async_trait]
+impl Handler<ListAddresses> for Server {
+    async fn handle(
+        &mut self,
+        _ctx: &mut Context<Self>,
+        _msg: ListAddresses,
+    ) -> Result<Vec<AddressInfo>> {
+        self.store.spawn(|store| store.list_addresses()).await
+    }
+}
+
+//////////////////
+
+#[message(result = "Result<GetDbStatsResponse>")]
+pub(crate) struct GetDbStats;
+
+/// Get the number of stored addresses and messages, and the estimated db size
+#[async_trait::async_trait]
+impl Handler<GetDbStats> for Server {
+    async fn handle(
+        &mut self,
+        _ctx: &mut Context<Self>,
+        _msg: GetDbStats,
+    ) -> Result<GetDbStatsResponse> {
+        self.store.spawn(|store| store.db_stats()).await
+    }
+}
+
+//////////////////
+
+#[message(result = "Result<usize>")]
+pub(crate) struct PurgeAddress(pub(crate) Vec<u8>);
+
+/// Delete all messages of an address. Returns the number of deleted messages.
+#[async_trait::async_trait]
+impl Handler<PurgeAddress> for Server {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, msg: PurgeAddress) -> Result<usize> {
+        self.store
+            .spawn(move |store| store.purge_address(msg.0))
+            .await
+    }
+}
+
+//////////////////
+
+#[message(result = "Result<(usize, usize)>")]
+pub(crate) struct ImportRecord(pub(crate) ExportRecord);
+
+/// Store the messages of an exported address that are valid, not expired and not already stored,
+/// and add the address to the addresses index. Returns the number of imported and skipped
+/// messages.
+#[async_trait::async_trait]
+impl Handler<ImportRecord> for Server {
+    async fn handle(
+        &mut self,
+        _ctx: &mut Context<Self>,
+        msg: ImportRecord,
+    ) -> Result<(usize, usize)> {
+        self.store
+            .spawn(move |store| store.import_record(msg.0))
+            .await
+    }
+}
+
+//////////////////
+
+#[message(result = "Result<(usize, usize)>")]
+pub(crate) struct OldMessages;
+
+/// Count the messages DeleteOldMessages would delete, and the addresses it would remove as they
+/// have no other messages
+#[async_trait::async_trait]
+impl Handler<OldMessages> for Server {
+    async fn handle(
+        &mut self,
+        _ctx: &mut Context<Self>,
+        _msg: OldMessages,
+    ) -> Result<(usize, usize)> {
+        self.store.spawn(|store| store.old_messages()).await
+    }
+}
+
+//////////////////
+
+#[message(result = "Result<()>")]
+pub(crate) struct CompactDb;
+
+/// Compact all db files, dropping deleted records from disk
+#[async_trait::async_trait]
+impl Handler<CompactDb> for Server {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: CompactDb) -> Result<()> {
+        self.store.spawn(|store| store.compact_db()).await
+    }
+}
+
+//////////////////
+
+#[message(result = "Result<Vec<String>>")]
+pub(crate) struct VerifyDb;
+
+/// Check that all db records are readable and that the addresses index matches the stored
+/// addresses. Returns the problems found.
+#[async_trait::async_trait]
+impl Handler<VerifyDb> for Server {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, _msg: VerifyDb) -> Result<Vec<String>> {
+        self.store.spawn(|store| store.verify_db()).await
+    }
+}
+
+//////////////////
+
+#[message(result = "Result<Vec<QuarantinedRecord>>")]
+pub(crate) struct ListQuarantinedRecords;
+
+/// List quarantined records by quarantine time, without their data
+#[async_trait::async_trait]
+impl Handler<ListQuarantinedRecords> for Server {
+    async fn handle(
+        &mut self,
+        _ctx: &mut Context<Self>,
+        _msg: ListQuarantinedRecords,
+    ) -> Result<Vec<QuarantinedRecord>> {
+        self.store.spawn(|store| store.quarantined_records()).await
+    }
+}
+
+//////////////////
+
+#[message(result = "Result<Option<QuarantinedRecord>>")]
+pub(crate) struct GetQuarantinedRecord(pub(crate) String);
+
+/// Get a quarantined record and its data by id. Returns None when there is no such record.
+#[async_trait::async_trait]
+impl Handler<GetQuarantinedRecord> for Server {
+    async fn handle(
+        &mut self,
+        _ctx: &mut Context<Self>,
+        msg: GetQuarantinedRecord,
+    ) -> Result<Option<QuarantinedRecord>> {
+        self.store
+            .spawn(move |store| store.quarantined_record(&msg.0))
+            .await
+    }
+}
+
+//////////////////
+
+#[message(result = "Result<bool>")]
+pub(crate) struct DeleteQuarantinedRecord(pub(crate) String);
+
+/// Delete a quarantined record by id. Returns false when there is no such record.
+#[async_trait::async_trait]
+impl Handler<DeleteQuarantinedRecord> for Server {
+    async fn handle(
+        &mut self,
+        _ctx: &mut Context<Self>,
+        msg: DeleteQuarantinedRecord,
+    ) -> Result<bool> {
+        self.store
+            .spawn(move |store| store.delete_quarantined_record(&msg.0))
+            .await
+    }
+}
+
+//////////////////
+
+#[message(result = "Result<Option<Vec<u8>>>")]
+pub(crate) struct ReencryptRecords {
+    // db key to resume from. None to start from the first record
+    pub(crate) from: Option<Vec<u8>>,
+    pub(crate) batch_size: usize,
+}
+
+/// Re-encrypt up to batch_size records that are not encrypted with the current master key.
+/// Returns the key to resume from, or None when all records were processed.
+/// Quarantined records are re-encrypted after the last batch of address records.
+#[async_trait::async_trait]
+impl Handler<ReencryptRecords> for Server {
+    async fn handle(
+        &mut self,
+        _ctx: &mut Context<Self>,
+        msg: ReencryptRecords,
+    ) -> Result<Option<Vec<u8>>> {
+        self.store
+            .spawn(move |store| store.reencrypt_records(msg.from, msg.batch_size))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::address_hash::ADDRESS_HASH_SECRET_FILE_CONFIG_KEY_NAME;
+    use crate::clock::MockClock;
+    use crate::encryption::{
+        MASTER_KEY_FILE_CONFIG_KEY_NAME, PREV_MASTER_KEY_FILE_CONFIG_KEY_NAME,
+    };
+    use crate::get_default_config;
+    use crate::limits::{
+        DEFAULT_ACCEPTED_MESSAGES_TIME_WINDOW_SECS, DEFAULT_MAX_TX_DATA_SIZE_BYTES,
+        MAX_ADDRESS_SIZE_CONFIG_KEY_NAME, MAX_TX_DATA_SIZE_CONFIG_KEY_NAME,
+        TIME_WINDOW_CONFIG_KEY_NAME, TRANSACTION_TYPES,
+    };
+    use api::api::TransactionType;
+    use api::api_extensions::EnvelopeKeyPair;
+    use log::LevelFilter;
+    use proptest::prelude::{any, prop, prop_oneof, proptest, Just, ProptestConfig, Strategy};
+    use proptest::sample::Index;
+    use serial_test::*;
+    use std::collections::BTreeMap;
+
+    #[message(result = "Result<Option<Vec<u8>>>")]
+    struct GetRawRecord(Vec<u8>);
+
+    /// Returns a record as stored in the db
+    #[async_trait::async_trait]
+    impl Handler<GetRawRecord> for Server {
+        async fn handle(
+            &mut self,
+            _ctx: &mut Context<Self>,
+            msg: GetRawRecord,
+        ) -> Result<Option<Vec<u8>>> {
+            Ok(self.store.db()?.get(msg.0)?)
+        }
+    }
+
+    #[message(result = "Result<()>")]
+    struct PutRawRecord(Vec<u8>, Vec<u8>);
+
+    /// Writes a record to the db as is
+    #[async_trait::async_trait]
+    impl Handler<PutRawRecord> for Server {
+        async fn handle(&mut self, _ctx: &mut Context<Self>, msg: PutRawRecord) -> Result<()> {
+            Ok(self.store.db()?.put(msg.0, msg.1)?)
+        }
+    }
+
+    /// Writes a new random secret to a temp file and returns the file path
+    fn new_secret_file() -> String {
+        let key: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
+        let path = std::env::temp_dir().join(format!("multisig_key_{}", hex::encode(&key[..8])));
+        std::fs::write(&path, hex::encode(key)).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn setup_test() {
+        // enable logging
+        let _ = env_logger::builder()
+            .is_test(false)
+            .filter_level(LevelFilter::Info)
+            .try_init();
+
+        // delete the db
+        let _ = std::fs::remove_dir_all(DB_FILE_PATH);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_server_service() {
+        setup_test();
+
+        let server = Server::default().start().await.unwrap();
+        let config = get_default_config();
+        server.call(SetConfig(config)).await.unwrap().unwrap();
+
+        let address1: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
+        let address2: Vec<u8> = (0..48).map(|_| rand::random::<u8>()).collect();
+        let _address3: Vec<u8> = (0..24).map(|_| rand::random::<u8>()).collect();
+
+        let tx1: Vec<u8> = (0..1024).map(|_| rand::random::<u8>()).collect();
+        let tx2: Vec<u8> = (0..1000).map(|_| rand::random::<u8>()).collect();
+        let tx3: Vec<u8> = (0..150).map(|_| rand::random::<u8>()).collect();
+
+        let t1 = Utc::now().timestamp() as u64;
+        let net_id = 1;
+
+        server
+            .call(StoreMessage(StoreMessageRequest {
+                user_message: Some(UserMessage {
+                    net_id,
+                    created: t1,
+                    address: address1.clone(),
+                    transaction_type: TransactionType::VaultWithdraw as i32,
+                    transaction_data: tx1.clone(),
+                    encrypted_transaction_data: None,
+                }),
+            }))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let messages: Vec<UserMessage> = server
+            .call(GetMessages(GetMessagesRequest {
+                address: address1.clone(),
+            }))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].address, address1);
+        assert_eq!(messages[0].created, t1);
+        assert_eq!(messages[0].transaction_data, tx1);
+        assert_eq!(messages[0].net_id, net_id);
+
+        let t2 = Utc::now().timestamp() as u64;
+
+        server
+            .call(StoreMessage(StoreMessageRequest {
+                user_message: Some(UserMessage {
+                    net_id,
+                    created: t2,
+                    address: address1.clone(),
+                    transaction_type: TransactionType::VaultWithdraw as i32,
+                    transaction_data: tx2.clone(),
+                    encrypted_transaction_data: None,
+                }),
+            }))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let messages: Vec<UserMessage> = server
+            .call(GetMessages(GetMessagesRequest {
+                address: address1.clone(),
+            }))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].address, address1);
+        assert_eq!(messages[0].created, t1);
+        assert_eq!(messages[0].transaction_data, tx1);
+        assert_eq!(messages[0].net_id, net_id);
+
+        assert_eq!(messages[1].address, address1);
+        assert_eq!(messages[1].created, t2);
+        assert_eq!(messages[1].transaction_data, tx2);
+        assert_eq!(messages[1].net_id, net_id);
+
+        let t3 = Utc::now().timestamp() as u64;
+
+        server
+            .call(StoreMessage(StoreMessageRequest {
+                user_message: Some(UserMessage {
+                    net_id,
+                    created: t3,
+                    address: address2.clone(),
+                    transaction_type: TransactionType::VaultWithdraw as i32,
+                    transaction_data: tx3.clone(),
+                    encrypted_transaction_data: None,
+                }),
+            }))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let messages: Vec<UserMessage> = server
+            .call(GetMessages(GetMessagesRequest {
+                address: address2.clone(),
+            }))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].address, address2);
+        assert_eq!(messages[0].created, t2);
+        assert_eq!(messages[0].transaction_data, tx3);
+        assert_eq!(messages[0].net_id, net_id);
+
+        // cleanup
+        server.call(DeleteDb {}).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn verify_messages_pruning() {
+        setup_test();
+        let clock = Arc::new(MockClock::new());
+        let server = Server::with_clock(clock.clone()).start().await.unwrap();
+
+        // set messages retention policy to 10 seconds
+        let mut config = get_default_config();
+        let c = config
+            .set_default(MSG_RETENTION_DUR_CONFIG_KEY_NAME, 10)
+            .unwrap()
+            .clone();
+        server.call(SetConfig(c)).await.unwrap().unwrap();
+        let address1: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
+        let tx1: Vec<u8> = (0..1024).map(|_| rand::random::<u8>()).collect();
+        let t1 = clock.now().timestamp() as u64;
+        let net_id = 1;
+
+        server
+            .call(StoreMessage(StoreMessageRequest {
+                user_message: Some(UserMessage {
+                    net_id,
+                    created: t1,
+                    address: address1.clone(),
+                    transaction_type: TransactionType::VaultWithdraw as i32,
+                    transaction_data: tx1.clone(),
+                    encrypted_transaction_data: None,
+                }),
+            }))
+            .await
+            .unwrap()
+            .unwrap();
+
+        server.call(DeleteOldMessages {}).await.unwrap().unwrap();
+        let messages: Vec<UserMessage> = server
+            .call(GetMessages(GetMessagesRequest {
+                address: address1.clone(),
+            }))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].address, address1);
+        assert_eq!(messages[0].created, t1);
+        assert_eq!(messages[0].transaction_data, tx1);
+        assert_eq!(messages[0].net_id, net_id);
+
+        // messages are kept for the retention duration
+        clock.advance(10);
+        server.call(DeleteOldMessages {}).await.unwrap().unwrap();
+        let messages: Vec<UserMessage> = server
+            .call(GetMessages(GetMessagesRequest {
+                address: address1.clone(),
+            }))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(messages.len(), 1);
+
+        clock.advance(1);
+        server.call(DeleteOldMessages {}).await.unwrap().unwrap();
+        let messages: Vec<UserMessage> = server
+            .call(GetMessages(GetMessagesRequest {
+                address: address1.clone(),
+            }))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(messages.len(), 0);
+
+        // cleanup
+        server.call(DeleteDb {}).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn quarantine_corrupt_records() {
+        setup_test();
+        let server = Server::default().start().await.unwrap();
+        server
+            .call(SetConfig(get_default_config()))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let addresses: Vec<Vec<u8>> = (1..=3).map(|i| vec![i; 32]).collect();
+        for address in addresses.iter() {
+            server
+                .call(StoreMessage(StoreMessageRequest {
+                    user_message: Some(UserMessage {
+                        net_id: 1,
+                        created: Utc::now().timestamp() as u64,
+                        address: address.clone(),
+                        transaction_type: TransactionType::VaultWithdraw as i32,
+                        transaction_data: vec![1; 32],
+                        encrypted_transaction_data: None,
+                    }),
+                }))
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        let get_messages = |address: &Vec<u8>| {
+            server.call(GetMessages(GetMessagesRequest {
+                address: address.clone(),
+            }))
+        };
+        let messages1 = get_messages(&addresses[0]).await.unwrap().unwrap();
+
+        // corrupt the record of address 2 and add an invalid message to address 3
+        server
+            .call(PutRawRecord(addresses[1].clone(), b"corrupt".to_vec()))
+            .await
+            .unwrap()
+            .unwrap();
+        let record = server
+            .call(GetRawRecord(addresses[2].clone()))
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let mut messages: Vec<Vec<u8>> = bincode::deserialize(&record).unwrap();
+        messages.push(vec![0xff; 8]);
+        server
+            .call(PutRawRecord(
+                addresses[2].clone(),
+                bincode::serialize(&messages).unwrap(),
+            ))
+            .await
+            .unwrap()
+            .unwrap();
+
+        server.call(DeleteOldMessages {}).await.unwrap().unwrap();
+        assert_eq!(
+            get_messages(&addresses[0]).await.unwrap().unwrap(),
+            messages1
+        );
+        assert!(get_messages(&addresses[1])
+            .await
+            .unwrap()
+            .unwrap()
+            .is_empty());
+        assert_eq!(get_messages(&addresses[2]).await.unwrap().unwrap().len(), 1);
+        let stats = server.call(GetDbStats {}).await.unwrap().unwrap();
+        assert_eq!(stats.address_count, 2);
+
+        let records = server
+            .call(ListQuarantinedRecords {})
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(|r| r.data.is_empty()));
+        let mut address_keys: Vec<Vec<u8>> =
+            records.iter().map(|r| r.address_key.clone()).collect();
+        address_keys.sort();
+        assert_eq!(address_keys, addresses[1..].to_vec());
+
+        for r in records {
+            let record = server
+                .call(GetQuarantinedRecord(r.id.clone()))
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            let expected: &[u8] = if r.address_key == addresses[1] {
+                b"corrupt"
+            } else {
+                &[0xff; 8]
+            };
+            assert_eq!(record.data, expected);
+            assert_eq!(record.data_size as usize, expected.len());
+            assert!(!record.reason.is_empty());
+
+            assert!(server
+                .call(DeleteQuarantinedRecord(r.id.clone()))
+                .await
+                .unwrap()
+                .unwrap());
+            assert!(!server
+                .call(DeleteQuarantinedRecord(r.id.clone()))
+                .await
+                .unwrap()
+                .unwrap());
+            assert!(server
+                .call(GetQuarantinedRecord(r.id))
+                .await
+                .unwrap()
+                .unwrap()
+                .is_none());
+        }
+        assert!(server
+            .call(ListQuarantinedRecords {})
+            .await
+            .unwrap()
+            .unwrap()
+            .is_empty());
+        assert!(server.call(VerifyDb {}).await.unwrap().unwrap().is_empty());
+
+        // cleanup
+        server.call(DeleteDb {}).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn reject_large_messages() {
+        setup_test();
+
+        let server = Server::default().start().await.unwrap();
+        server
+            .call(SetConfig(get_default_config()))
+            .await
+            .unwrap()
+            .unwrap();
+        let address1: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
+        let tx1: Vec<u8> = (0..DEFAULT_MAX_TX_DATA_SIZE_BYTES + 1)
+            .map(|_| rand::random::<u8>())
+            .collect();
+        let res = server
+            .call(StoreMessage(StoreMessageRequest {
+                user_message: Some(UserMessage {
+                    net_id: 1,
+                    created: Utc::now().timestamp() as u64,
+                    address: address1,
+                    transaction_type: TransactionType::VaultWithdraw as i32,
+                    transaction_data: tx1,
+                    encrypted_transaction_data: None,
+                }),
+            }))
+            .await
+            .unwrap();
+
+        assert!(res.is_err());
+
+        // cleanup
+        server.call(DeleteDb {}).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn reject_badly_timed_message() {
+        setup_test();
+        let clock = Arc::new(MockClock::new());
+        let server = Server::with_clock(clock.clone()).start().await.unwrap();
+        server
+            .call(SetConfig(get_default_config()))
+            .await
+            .unwrap()
+            .unwrap();
+        let address1: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
+        let tx1: Vec<u8> = (0..1024).map(|_| rand::random::<u8>()).collect();
+        let t = clock.now().timestamp() as u64;
+        let window = DEFAULT_ACCEPTED_MESSAGES_TIME_WINDOW_SECS;
+        let message = || {
+            StoreMessage(StoreMessageRequest {
+                user_message: Some(UserMessage {
+                    net_id: 1,
+                    created: t,
+                    address: address1.clone(),
+                    transaction_type: TransactionType::VaultWithdraw as i32,
+                    transaction_data: tx1.clone(),
+                    encrypted_transaction_data: None,
+                }),
+            })
+        };
+
+        // message time in the past, at the edge of the acceptable time window
+        clock.advance(window);
+        assert!(server.call(message()).await.unwrap().is_ok());
+
+        // message time in the past, before acceptable time window
+        clock.advance(1);
+        assert!(server.call(message()).await.unwrap().is_err());
+
+        // message time in the future, at the edge of the acceptable time window
+        clock.advance(-2 * window - 1);
+        assert!(server.call(message()).await.unwrap().is_ok());
+
+        // message time in the future beyond acceptance window
+        clock.advance(-1);
+        assert!(server.call(message()).await.unwrap().is_err());
+
+        // cleanup
+        server.call(DeleteDb {}).await.unwrap().unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[serial]
+    async fn reject_malicious_address() {
+        setup_test();
+        let server = Server::default().start().await.unwrap();
+        server
+            .call(SetConfig(get_default_config()))
+            .await
+            .unwrap()
+            .unwrap();
+        server
+            .call(StoreMessage(new_message(&[1; 32], vec![1; 1024])))
+            .await
+            .unwrap()
+            .unwrap();
+        let address1: Vec<u8> = Vec::from(ALL_ADDRESSES_KEY);
+        let tx1: Vec<u8> = (0..1024).map(|_| rand::random::<u8>()).collect();
+        let res = server
+            .call(StoreMessage(StoreMessageRequest {
+                user_message: Some(UserMessage {
+                    net_id: 1,
+                    created: Utc::now().timestamp() as u64,
+                    address: address1,
+                    transaction_type: TransactionType::VaultWithdraw as i32,
+                    transaction_data: tx1,
+                    encrypted_transaction_data: None,
+                }),
+            }))
+            .await
+            .unwrap();
+
+        assert!(res.is_err());
+
+        // the addresses index isn't returned as messages
+        for address in RESERVED_KEYS {
+            let messages = server
+                .call(GetMessages(GetMessagesRequest {
+                    address: address.to_vec(),
+                }))
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(messages.is_empty());
+        }
+
+        // cleanup
+        server.call(DeleteDb {}).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn store_encrypted_message() {
+        setup_test();
+        let server = Server::default().start().await.unwrap();
+        server
+            .call(SetConfig(get_default_config()))
+            .await
+            .unwrap()
+            .unwrap();
+        let alice = EnvelopeKeyPair::generate();
+        let bob = EnvelopeKeyPair::generate();
+        let address1: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
+        let tx1: Vec<u8> = (0..1024).map(|_| rand::random::<u8>()).collect();
+        let user_message = UserMessage::new_encrypted(
+            1,
+            Utc::now().timestamp() as u64,
+            address1.clone(),
+            TransactionType::VaultWithdraw,
+            &tx1,
+            &[alice.public_key(), bob.public_key()],
+        )
+        .unwrap();
+
+        server
+            .call(StoreMessage(StoreMessageRequest {
+                user_message: Some(user_message.clone()),
+            }))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let messages: Vec<UserMessage> = server
+            .call(GetMessages(GetMessagesRequest {
+                address: address1.clone(),
+            }))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0], user_message);
+        assert_eq!(messages[0].open_transaction_data(&alice).unwrap(), tx1);
+        assert_eq!(messages[0].open_transaction_data(&bob).unwrap(), tx1);
+
+        // data must be provided either in clear or encrypted, not both
+        let mut user_message = user_message;
+        user_message.transaction_data = tx1.clone();
+        let res = server
+            .call(StoreMessage(StoreMessageRequest {
+                user_message: Some(user_message.clone()),
+            }))
+            .await
+            .unwrap();
+        assert!(res.is_err());
+
+        // malformed envelope
+        user_message.transaction_data = vec![];
+        user_message
+            .encrypted_transaction_data
+            .as_mut()
+            .unwrap()
+            .recipients
+            .clear();
+        let res = server
+            .call(StoreMessage(StoreMessageRequest {
+                user_message: Some(user_message),
+            }))
+            .await
+            .unwrap();
+        assert!(res.is_err());
+
+        // cleanup
+        server.call(DeleteDb {}).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn encrypt_records_at_rest() {
+        setup_test();
+        let server = Server::default().start().await.unwrap();
+        let mut config = get_default_config();
+        config
+            .set(MASTER_KEY_FILE_CONFIG_KEY_NAME, new_secret_file())
+            .unwrap();
+        server.call(SetConfig(config)).await.unwrap().unwrap();
+
+        let address1: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
+        let tx1: Vec<u8> = (0..1024).map(|_| rand::random::<u8>()).collect();
+        let user_message = UserMessage {
+            net_id: 1,
+            created: Utc::now().timestamp() as u64,
+            address: address1.clone(),
+            transaction_type: TransactionType::VaultWithdraw as i32,
+            transaction_data: tx1.clone(),
+            encrypted_transaction_data: None,
+        };
+        server
+            .call(StoreMessage(StoreMessageRequest {
+                user_message: Some(user_message.clone()),
+            }))
+            .await
+            .unwrap()
+            .unwrap();
+
+        // raw db values must not include the message or the address index in clear
+        let mut user_msg_bin: Vec<u8> = vec![];
+        user_message.encode(&mut user_msg_bin).unwrap();
+        let raw = server
+            .call(GetRawRecord(address1.clone()))
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(!raw.windows(tx1.len()).any(|w| w == tx1.as_slice()));
+        assert!(!raw
+            .windows(user_msg_bin.len())
+            .any(|w| w == user_msg_bin.as_slice()));
+        let raw_index = server
+            .call(GetRawRecord(ALL_ADDRESSES_KEY.to_vec()))
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(!raw_index
+            .windows(address1.len())
+            .any(|w| w == address1.as_slice()));
+
+        let messages: Vec<UserMessage> = server
+            .call(GetMessages(GetMessagesRequest {
+                address: address1.clone(),
+            }))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(messages, vec![user_message]);
+
+        // records can't be read without the master key
+        server
+            .call(SetConfig(get_default_config()))
+            .await
+            .unwrap()
+            .unwrap();
+        let res = server
+            .call(GetMessages(GetMessagesRequest { address: address1 }))
+            .await
+            .unwrap();
+        assert!(res.is_err());
+
+        // cleanup
+        server.call(DeleteDb {}).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn rotate_master_key() {
+        setup_test();
+        let server = Server::default().start().await.unwrap();
+
+        // store a message in clear and one encrypted with the first key
+        let key1 = new_secret_file();
+        let key2 = new_secret_file();
+        let mut config1 = get_default_config();
+        config1
+            .set(MASTER_KEY_FILE_CONFIG_KEY_NAME, key1.clone())
+            .unwrap();
+        let address1: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
+        let address2: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
+        let mut stored = vec![];
+        for (config, address) in [
+            (get_default_config(), address1.clone()),
+            (config1, address2.clone()),
+        ] {
+            server.call(SetConfig(config)).await.unwrap().unwrap();
+            let user_message = UserMessage {
+                net_id: 1,
+                created: Utc::now().timestamp() as u64,
+                address,
+                transaction_type: TransactionType::VaultWithdraw as i32,
+                transaction_data: (0..100).map(|_| rand::random::<u8>()).collect(),
+                encrypted_transaction_data: None,
+            };
+            server
+                .call(StoreMessage(StoreMessageRequest {
+                    user_message: Some(user_message.clone()),
+                }))
+                .await
+                .unwrap()
+                .unwrap();
+            stored.push(user_message);
+        }
+        let raw_before = server
+            .call(GetRawRecord(address2.clone()))
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+
+        // rotate to the second key and re-encrypt all records one by one
+        let mut config2 = get_default_config();
+        config2
+            .set(MASTER_KEY_FILE_CONFIG_KEY_NAME, key2.clone())
+            .unwrap()
+            .set(PREV_MASTER_KEY_FILE_CONFIG_KEY_NAME, key1)
+            .unwrap();
+        server.call(SetConfig(config2)).await.unwrap().unwrap();
+        let mut from = None;
+        let mut batches = 0;
+        loop {
+            from = server
+                .call(ReencryptRecords {
+                    from,
+                    batch_size: 1,
+                })
+                .await
+                .unwrap()
+                .unwrap();
+            batches += 1;
+            if from.is_none() {
+                break;
+            }
+        }
+        // 2 addresses, the addresses index and the schema version
+        assert_eq!(batches, 4);
+
+        let raw_after = server
+            .call(GetRawRecord(address2.clone()))
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_ne!(raw_before, raw_after);
+
+        // all records are readable with only the new key
+        let mut config3 = get_default_config();
+        config3.set(MASTER_KEY_FILE_CONFIG_KEY_NAME, key2).unwrap();
+        server.call(SetConfig(config3)).await.unwrap().unwrap();
+        for m in stored {
+            let messages: Vec<UserMessage> = server
+                .call(GetMessages(GetMessagesRequest {
+                    address: m.address.clone(),
+                }))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(messages, vec![m]);
+        }
+
+        // cleanup
+        server.call(DeleteDb {}).await.unwrap().unwrap();
     }
 
     #[tokio::test]
     #[serial]
-    async fn test_server_service() {
+    async fn hash_address_keys() {
         setup_test();
-
-        let server = Server::from_registry().await.unwrap();
-        let config = get_default_config();
-        let _ = server.call(SetConfig(config)).await.unwrap().unwrap();
+        let clock = Arc::new(MockClock::new());
+        let server = Server::with_clock(clock.clone()).start().await.unwrap();
+        server
+            .call(SetConfig(get_default_config()))
+            .await
+            .unwrap()
+            .unwrap();
 
         let address1: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
-        let address2: Vec<u8> = (0..48).map(|_| rand::random::<u8>()).collect();
-        let _address3: Vec<u8> = (0..24).map(|_| rand::random::<u8>()).collect();
-
-        let tx1: Vec<u8> = (0..1024).map(|_| rand::random::<u8>()).collect();
-        let tx2: Vec<u8> = (0..1000).map(|_| rand::random::<u8>()).collect();
-        let tx3: Vec<u8> = (0..150).map(|_| rand::random::<u8>()).collect();
-
-        let t1 = Utc::now().timestamp() as u64;
-        let net_id = 1;
+        let store_message = |server: Addr<Server>, address: Vec<u8>| async move {
+            let user_message = UserMessage {
+                net_id: 1,
+                created: Utc::now().timestamp() as u64,
+                address,
+                transaction_type: TransactionType::VaultWithdraw as i32,
+                transaction_data: (0..100).map(|_| rand::random::<u8>()).collect(),
+                encrypted_transaction_data: None,
+            };
+            server
+                .call(StoreMessage(StoreMessageRequest {
+                    user_message: Some(user_message.clone()),
+                }))
+                .await
+                .unwrap()
+                .unwrap();
+            user_message
+        };
+
+        // store a message before address hashing is enabled
+        let m1 = store_message(server.clone(), address1.clone()).await;
+        assert!(server
+            .call(GetRawRecord(address1.clone()))
+            .await
+            .unwrap()
+            .unwrap()
+            .is_some());
 
-        let _ = server
-            .call(StoreMessage(StoreMessageRequest {
-                user_message: Some(UserMessage {
-                    net_id,
-                    created: t1,
-                    address: address1.clone(),
-                    transaction_type: TransactionType::VaultWithdraw as i32,
-                    transaction_data: tx1.clone(),
-                }),
-            }))
+        // enabling address hashing migrates existing keys
+        let secret = new_secret_file();
+        let mut config = get_default_config();
+        config
+            .set(ADDRESS_HASH_SECRET_FILE_CONFIG_KEY_NAME, secret.clone())
+            .unwrap();
+        server
+            .call(SetConfig(config.clone()))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(server
+            .call(GetRawRecord(address1.clone()))
+            .await
+            .unwrap()
+            .unwrap()
+            .is_none());
+        let raw_index = server
+            .call(GetRawRecord(ALL_ADDRESSES_KEY.to_vec()))
             .await
             .unwrap()
+            .unwrap()
             .unwrap();
+        assert!(!raw_index
+            .windows(address1.len())
+            .any(|w| w == address1.as_slice()));
+
+        // setting the same config again is a no-op
+        server.call(SetConfig(config)).await.unwrap().unwrap();
 
+        let m2 = store_message(server.clone(), address1.clone()).await;
         let messages: Vec<UserMessage> = server
             .call(GetMessages(GetMessagesRequest {
                 address: address1.clone(),
@@ -362,29 +2321,18 @@ mod tests {
             .await
             .unwrap()
             .unwrap();
+        assert_eq!(messages, vec![m1, m2]);
 
-        assert_eq!(messages.len(), 1);
-        assert_eq!(messages[0].address, address1);
-        assert_eq!(messages[0].created, t1);
-        assert_eq!(messages[0].transaction_data, tx1);
-        assert_eq!(messages[0].net_id, net_id);
-
-        let t2 = Utc::now().timestamp() as u64;
-
-        let _ = server
-            .call(StoreMessage(StoreMessageRequest {
-                user_message: Some(UserMessage {
-                    net_id,
-                    created: t2,
-                    address: address1.clone(),
-                    transaction_type: TransactionType::VaultWithdraw as i32,
-                    transaction_data: tx2.clone(),
-                }),
-            }))
-            .await
+        // pruning works with hashed keys
+        let mut config = get_default_config();
+        config
+            .set(ADDRESS_HASH_SECRET_FILE_CONFIG_KEY_NAME, secret)
             .unwrap()
+            .set(MSG_RETENTION_DUR_CONFIG_KEY_NAME, 0)
             .unwrap();
-
+        server.call(SetConfig(config)).await.unwrap().unwrap();
+        clock.advance(10);
+        server.call(DeleteOldMessages {}).await.unwrap().unwrap();
         let messages: Vec<UserMessage> = server
             .call(GetMessages(GetMessagesRequest {
                 address: address1.clone(),
@@ -392,232 +2340,509 @@ mod tests {
             .await
             .unwrap()
             .unwrap();
-
-        assert_eq!(messages.len(), 2);
-        assert_eq!(messages[0].address, address1);
-        assert_eq!(messages[0].created, t1);
-        assert_eq!(messages[0].transaction_data, tx1);
-        assert_eq!(messages[0].net_id, net_id);
-
-        assert_eq!(messages[1].address, address1);
-        assert_eq!(messages[1].created, t2);
-        assert_eq!(messages[1].transaction_data, tx2);
-        assert_eq!(messages[1].net_id, net_id);
-
-        let t3 = Utc::now().timestamp() as u64;
-
-        let _ = server
-            .call(StoreMessage(StoreMessageRequest {
-                user_message: Some(UserMessage {
-                    net_id,
-                    created: t3,
-                    address: address2.clone(),
-                    transaction_type: TransactionType::VaultWithdraw as i32,
-                    transaction_data: tx3.clone(),
-                }),
-            }))
+        assert!(messages.is_empty());
+        let index: HashSet<Vec<u8>> = server
+            .call(GetRawRecord(ALL_ADDRESSES_KEY.to_vec()))
             .await
             .unwrap()
-            .unwrap();
+            .unwrap()
+            .map_or(HashSet::new(), |data| bincode::deserialize(&data).unwrap());
+        assert!(index.is_empty());
 
-        let messages: Vec<UserMessage> = server
-            .call(GetMessages(GetMessagesRequest {
-                address: address2.clone(),
-            }))
+        // a hashed db can't be used without the secret or with another secret
+        assert!(server
+            .call(SetConfig(get_default_config()))
             .await
             .unwrap()
+            .is_err());
+        let mut config = get_default_config();
+        config
+            .set(ADDRESS_HASH_SECRET_FILE_CONFIG_KEY_NAME, new_secret_file())
             .unwrap();
-
-        assert_eq!(messages.len(), 1);
-        assert_eq!(messages[0].address, address2);
-        assert_eq!(messages[0].created, t2);
-        assert_eq!(messages[0].transaction_data, tx3);
-        assert_eq!(messages[0].net_id, net_id);
+        assert!(server.call(SetConfig(config)).await.unwrap().is_err());
 
         // cleanup
-        let _ = server.call(DeleteDb {}).await.unwrap().unwrap();
+        server.call(DeleteDb {}).await.unwrap().unwrap();
     }
 
     #[tokio::test]
     #[serial]
-    async fn verify_messages_pruning() {
+    async fn reencrypt_hashed_address_keys() {
         setup_test();
-        let server = Server::from_registry().await.unwrap();
-
-        // set messages retention policy to 10 seconds
-        let mut config = get_default_config();
-        let c = config
-            .set_default(MSG_RETENTION_DUR_CONFIG_KEY_NAME, 10)
+        let server = Server::default().start().await.unwrap();
+        let secret = new_secret_file();
+        let key1 = new_secret_file();
+        let key2 = new_secret_file();
+        let mut config1 = get_default_config();
+        config1
+            .set(ADDRESS_HASH_SECRET_FILE_CONFIG_KEY_NAME, secret.clone())
             .unwrap()
-            .clone();
-        let _ = server.call(SetConfig(c)).await.unwrap().unwrap();
-        let address1: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
-        let tx1: Vec<u8> = (0..1024).map(|_| rand::random::<u8>()).collect();
-        let t1 = Utc::now().timestamp() as u64;
-        let net_id = 1;
-
-        let _ = server
+            .set(MASTER_KEY_FILE_CONFIG_KEY_NAME, key1.clone())
+            .unwrap();
+        server.call(SetConfig(config1)).await.unwrap().unwrap();
+        let user_message = UserMessage {
+            net_id: 1,
+            created: Utc::now().timestamp() as u64,
+            address: (0..32).map(|_| rand::random::<u8>()).collect(),
+            transaction_type: TransactionType::VaultWithdraw as i32,
+            transaction_data: (0..100).map(|_| rand::random::<u8>()).collect(),
+            encrypted_transaction_data: None,
+        };
+        server
             .call(StoreMessage(StoreMessageRequest {
-                user_message: Some(UserMessage {
-                    net_id,
-                    created: t1,
-                    address: address1.clone(),
-                    transaction_type: TransactionType::VaultWithdraw as i32,
-                    transaction_data: tx1.clone(),
-                }),
+                user_message: Some(user_message.clone()),
             }))
             .await
             .unwrap()
             .unwrap();
-
-        let _ = server.call(DeleteOldMessages {}).await.unwrap().unwrap();
-        let messages: Vec<UserMessage> = server
-            .call(GetMessages(GetMessagesRequest {
-                address: address1.clone(),
-            }))
+        let raw_secret_id = server
+            .call(GetRawRecord(ADDRESS_HASH_SECRET_ID_KEY.to_vec()))
             .await
             .unwrap()
             .unwrap();
 
-        assert_eq!(messages.len(), 1);
-        assert_eq!(messages[0].address, address1);
-        assert_eq!(messages[0].created, t1);
-        assert_eq!(messages[0].transaction_data, tx1);
-        assert_eq!(messages[0].net_id, net_id);
-
-        // sleep for 11 seconds
-        tokio::time::sleep(tokio::time::Duration::from_secs(11)).await;
+        // rotate to the second key and re-encrypt all records
+        let mut config2 = get_default_config();
+        config2
+            .set(ADDRESS_HASH_SECRET_FILE_CONFIG_KEY_NAME, secret.clone())
+            .unwrap()
+            .set(MASTER_KEY_FILE_CONFIG_KEY_NAME, key2.clone())
+            .unwrap()
+            .set(PREV_MASTER_KEY_FILE_CONFIG_KEY_NAME, key1)
+            .unwrap();
+        server.call(SetConfig(config2)).await.unwrap().unwrap();
+        let from = server
+            .call(ReencryptRecords {
+                from: None,
+                batch_size: 100,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(from.is_none());
 
-        let _ = server.call(DeleteOldMessages {}).await.unwrap().unwrap();
+        // the secret id is stored in clear, so the hashed keys are still accepted
+        let raw_secret_id_after = server
+            .call(GetRawRecord(ADDRESS_HASH_SECRET_ID_KEY.to_vec()))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(raw_secret_id, raw_secret_id_after);
+        let mut config3 = get_default_config();
+        config3
+            .set(ADDRESS_HASH_SECRET_FILE_CONFIG_KEY_NAME, secret)
+            .unwrap()
+            .set(MASTER_KEY_FILE_CONFIG_KEY_NAME, key2)
+            .unwrap();
+        server.call(SetConfig(config3)).await.unwrap().unwrap();
         let messages: Vec<UserMessage> = server
             .call(GetMessages(GetMessagesRequest {
-                address: address1.clone(),
+                address: user_message.address.clone(),
             }))
             .await
             .unwrap()
             .unwrap();
-
-        assert_eq!(messages.len(), 0);
+        assert_eq!(messages, vec![user_message]);
 
         // cleanup
-        let _ = server.call(DeleteDb {}).await.unwrap().unwrap();
+        server.call(DeleteDb {}).await.unwrap().unwrap();
     }
 
     #[tokio::test]
     #[serial]
-    async fn reject_large_messages() {
+    async fn configurable_limits() {
         setup_test();
-
-        let server = Server::from_registry().await.unwrap();
-        let _ = server
-            .call(SetConfig(get_default_config()))
-            .await
+        let server = Server::default().start().await.unwrap();
+        let mut config = get_default_config();
+        config
+            .set(MAX_TX_DATA_SIZE_CONFIG_KEY_NAME, 100)
             .unwrap()
+            .set("max_tx_data_size_coin_spend", 500)
             .unwrap();
+        server.call(SetConfig(config)).await.unwrap().unwrap();
+
         let address1: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
-        let tx1: Vec<u8> = (0..MAX_TX_DATA_SIZE_BYTES + 1)
-            .map(|_| rand::random::<u8>())
-            .collect();
-        let res = server
-            .call(StoreMessage(StoreMessageRequest {
+        let store_message = |transaction_type: i32, size: usize| {
+            server.call(StoreMessage(StoreMessageRequest {
                 user_message: Some(UserMessage {
                     net_id: 1,
                     created: Utc::now().timestamp() as u64,
-                    address: address1,
-                    transaction_type: TransactionType::VaultWithdraw as i32,
-                    transaction_data: tx1,
+                    address: address1.clone(),
+                    transaction_type,
+                    transaction_data: (0..size).map(|_| rand::random::<u8>()).collect(),
+                    encrypted_transaction_data: None,
                 }),
             }))
-            .await
-            .unwrap();
-
-        assert!(res.is_err());
+        };
+
+        let vault_withdraw = TransactionType::VaultWithdraw as i32;
+        let coin_spend = TransactionType::CoinSpend as i32;
+        assert!(store_message(vault_withdraw, 100).await.unwrap().is_ok());
+        assert!(store_message(vault_withdraw, 101).await.unwrap().is_err());
+        assert!(store_message(coin_spend, 500).await.unwrap().is_ok());
+        assert!(store_message(coin_spend, 501).await.unwrap().is_err());
+
+        // unknown transaction types get the max_tx_data_size limit
+        assert!(store_message(100, 100).await.unwrap().is_ok());
+        assert!(store_message(100, 101).await.unwrap().is_err());
+
+        // out of bounds and invalid values are rejected
+        for (key, value) in [
+            (MAX_TX_DATA_SIZE_CONFIG_KEY_NAME, "0"),
+            (TIME_WINDOW_CONFIG_KEY_NAME, "1"),
+            ("max_tx_data_size_vault_withdraw", "100000000"),
+            (TIME_WINDOW_CONFIG_KEY_NAME, "a day"),
+        ] {
+            let mut config = get_default_config();
+            config.set(key, value).unwrap();
+            assert!(server.call(SetConfig(config)).await.unwrap().is_err());
+        }
 
         // cleanup
-        let _ = server.call(DeleteDb {}).await.unwrap().unwrap();
+        server.call(DeleteDb {}).await.unwrap().unwrap();
     }
 
     #[tokio::test]
     #[serial]
-    async fn reject_badly_timed_message() {
+    async fn get_server_info() {
         setup_test();
-        let server = Server::from_registry().await.unwrap();
-        let _ = server
-            .call(SetConfig(get_default_config()))
-            .await
+        let server = Server::default().start().await.unwrap();
+        let mut config = get_default_config();
+        config
+            .set("max_tx_data_size_coin_spend", 4096)
             .unwrap()
+            .set(MSG_RETENTION_DUR_CONFIG_KEY_NAME, 3600)
             .unwrap();
-        let address1: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
-        let tx1: Vec<u8> = (0..1024).map(|_| rand::random::<u8>()).collect();
-
-        // set time in past, before acceptable time window
-        let t = Utc::now().timestamp() as u64 - (ACCEPTED_MESSAGES_TIME_WINDOW_SECS as u64) - 1;
-
-        let res = server
-            .call(StoreMessage(StoreMessageRequest {
-                user_message: Some(UserMessage {
-                    net_id: 1,
-                    created: t,
-                    address: address1.clone(),
-                    transaction_type: TransactionType::VaultWithdraw as i32,
-                    transaction_data: tx1.clone(),
-                }),
-            }))
-            .await
-            .unwrap();
+        server.call(SetConfig(config)).await.unwrap().unwrap();
+
+        let t = Utc::now().timestamp() as u64;
+        let store = server.call(GetMessageStore {}).await.unwrap().unwrap();
+        let info = store.server_info();
+        assert_eq!(info.version, SERVER_VERSION);
+        assert_eq!(info.max_address_size, 128);
+        assert_eq!(
+            info.accepted_time_window,
+            DEFAULT_ACCEPTED_MESSAGES_TIME_WINDOW_SECS as u64
+        );
+        assert_eq!(info.message_retention_duration, 3600);
+        assert!(info.server_time >= t);
+        let types: Vec<i32> = info
+            .supported_transaction_types
+            .iter()
+            .map(|limits| limits.transaction_type)
+            .collect();
+        let expected: Vec<i32> = TRANSACTION_TYPES.iter().map(|(t, _)| *t as i32).collect();
+        assert_eq!(types, expected);
+        for limits in info.supported_transaction_types {
+            let expected = if limits.transaction_type == TransactionType::CoinSpend as i32 {
+                4096
+            } else {
+                DEFAULT_MAX_TX_DATA_SIZE_BYTES as u32
+            };
+            assert_eq!(limits.max_transaction_data_size, expected);
+        }
+        assert_eq!(
+            info.default_max_transaction_data_size,
+            DEFAULT_MAX_TX_DATA_SIZE_BYTES as u32
+        );
 
-        assert!(res.is_err());
+        // cleanup
+        server.call(DeleteDb {}).await.unwrap().unwrap();
+    }
 
-        // set time in the future beyond acceptance window
-        let t = Utc::now().timestamp() as u64 + (ACCEPTED_MESSAGES_TIME_WINDOW_SECS as u64) + 1;
+    /// Returns a new message of an address created now
+    fn new_message(address: &[u8], transaction_data: Vec<u8>) -> StoreMessageRequest {
+        StoreMessageRequest {
+            user_message: Some(UserMessage {
+                net_id: 1,
+                created: Utc::now().timestamp() as u64,
+                address: address.to_vec(),
+                transaction_type: TransactionType::VaultWithdraw as i32,
+                transaction_data,
+                encrypted_transaction_data: None,
+            }),
+        }
+    }
 
-        let res = server
-            .call(StoreMessage(StoreMessageRequest {
-                user_message: Some(UserMessage {
-                    net_id: 1,
-                    created: t,
-                    address: address1.clone(),
-                    transaction_type: TransactionType::VaultWithdraw as i32,
-                    transaction_data: tx1.clone(),
-                }),
-            }))
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    #[serial]
+    async fn concurrent_store_and_get() {
+        setup_test();
+        let server = Server::default().start().await.unwrap();
+        server
+            .call(SetConfig(get_default_config()))
             .await
+            .unwrap()
             .unwrap();
+        let store = server.call(GetMessageStore {}).await.unwrap().unwrap();
 
-        assert!(res.is_err());
+        // messages of each address are stored and read by a few tasks, while the db is pruned
+        let addresses: Vec<Vec<u8>> = (0..16)
+            .map(|_| (0..32).map(|_| rand::random::<u8>()).collect())
+            .collect();
+        let mut tasks = vec![];
+        for address in addresses.iter() {
+            for i in 0..8u8 {
+                let store = store.clone();
+                let address = address.clone();
+                tasks.push(tokio::spawn(async move {
+                    store
+                        .spawn(move |store| {
+                            store.store_message(new_message(&address, vec![i; 64]))?;
+                            store.get_messages(GetMessagesRequest { address })
+                        })
+                        .await
+                }));
+            }
+            let store = store.clone();
+            tasks.push(tokio::spawn(async move {
+                store
+                    .spawn(|store| store.delete_old_messages())
+                    .await
+                    .map(|_| vec![])
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        // no message or addresses index update was lost
+        for address in addresses.iter() {
+            let messages = store
+                .get_messages(GetMessagesRequest {
+                    address: address.clone(),
+                })
+                .unwrap();
+            let mut data: Vec<u8> = messages.iter().map(|m| m.transaction_data[0]).collect();
+            data.sort_unstable();
+            assert_eq!(data, (0..8).collect::<Vec<u8>>());
+        }
+        let stats = server.call(GetDbStats {}).await.unwrap().unwrap();
+        assert_eq!(stats.address_count, 16);
+        assert_eq!(stats.message_count, 128);
+        assert!(server.call(VerifyDb {}).await.unwrap().unwrap().is_empty());
 
         // cleanup
-        let _ = server.call(DeleteDb {}).await.unwrap().unwrap();
+        server.call(DeleteDb {}).await.unwrap().unwrap();
     }
 
-    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[tokio::test]
     #[serial]
-    async fn reject_malicious_address() {
+    async fn reads_dont_wait_for_writes() {
         setup_test();
-        let server = Server::from_registry().await.unwrap();
-        let _ = server
+        let server = Server::default().start().await.unwrap();
+        server
             .call(SetConfig(get_default_config()))
             .await
             .unwrap()
             .unwrap();
-        let address1: Vec<u8> = Vec::from(ALL_ADDRESSES_KEY);
-        let tx1: Vec<u8> = (0..1024).map(|_| rand::random::<u8>()).collect();
-        let res = server
-            .call(StoreMessage(StoreMessageRequest {
-                user_message: Some(UserMessage {
-                    net_id: 1,
-                    created: Utc::now().timestamp() as u64,
-                    address: address1,
-                    transaction_type: TransactionType::VaultWithdraw as i32,
-                    transaction_data: tx1,
+        let store = server.call(GetMessageStore {}).await.unwrap().unwrap();
+        let address: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
+        store
+            .store_message(new_message(&address, vec![1; 64]))
+            .unwrap();
+
+        // a read completes while a pruning is running and the address is being written
+        {
+            let _prune = store.inner.prune_lock.lock().unwrap();
+            let _index = store.lock_index();
+            let _lock = store.lock_address(&address);
+            let messages = store
+                .get_messages(GetMessagesRequest {
+                    address: address.clone(),
+                })
+                .unwrap();
+            assert_eq!(messages.len(), 1);
+        }
+
+        // cleanup
+        server.call(DeleteDb {}).await.unwrap().unwrap();
+    }
+
+    // limits of the store model test, small enough for generated messages to hit them
+    const MODEL_MAX_ADDRESS_SIZE: usize = 16;
+    const MODEL_MAX_TX_DATA_SIZE: usize = 64;
+    const MODEL_TIME_WINDOW: i64 = 600;
+    const MODEL_RETENTION: u64 = 300;
+
+    /// An operation of the store model test
+    #[derive(Debug, Clone)]
+    enum ModelOp {
+        /// store a message for an address, created offset seconds from the current time
+        Store {
+            address: Index,
+            created_offset: i64,
+            data_size: usize,
+        },
+        Get {
+            address: Index,
+        },
+        /// advance the clock by seconds and delete old messages
+        Prune {
+            advance: i64,
+        },
+    }
+
+    /// Addresses of stored messages - mostly valid ones, and some that are rejected
+    fn model_address() -> impl Strategy<Value = Vec<u8>> {
+        prop_oneof![
+            8 => prop::collection::vec(any::<u8>(), 1..=MODEL_MAX_ADDRESS_SIZE),
+            1 => prop::collection::vec(any::<u8>(), 0..=MODEL_MAX_ADDRESS_SIZE + 2),
+            1 => Just(ALL_ADDRESSES_KEY.to_vec()),
+            1 => Just(SCHEMA_VERSION_KEY.to_vec()),
+        ]
+    }
+
+    fn model_op() -> impl Strategy<Value = ModelOp> {
+        prop_oneof![
+            4 => (
+                any::<Index>(),
+                -2 * MODEL_TIME_WINDOW..=2 * MODEL_TIME_WINDOW,
+                0..=MODEL_MAX_TX_DATA_SIZE + 2
+            )
+                .prop_map(|(address, created_offset, data_size)| ModelOp::Store {
+                    address,
+                    created_offset,
+                    data_size,
                 }),
-            }))
-            .await
+            2 => any::<Index>().prop_map(|address| ModelOp::Get { address }),
+            1 => (0..=2 * MODEL_RETENTION as i64).prop_map(|advance| ModelOp::Prune { advance }),
+        ]
+    }
+
+    /// Returns true if a new message passes validation with the model test limits
+    fn model_accepts(user_msg: &UserMessage, now: i64) -> bool {
+        let address = &user_msg.address;
+        !address.is_empty()
+            && address.len() <= MODEL_MAX_ADDRESS_SIZE
+            && !RESERVED_KEYS.contains(&address.as_slice())
+            && (user_msg.created as i64 - now).abs() <= MODEL_TIME_WINDOW
+            && (1..=MODEL_MAX_TX_DATA_SIZE).contains(&user_msg.transaction_data.len())
+    }
+
+    /// Run operations on a message store and check the results against a model of the messages
+    /// stored for each address
+    async fn check_store_model(addresses: Vec<Vec<u8>>, ops: Vec<ModelOp>) {
+        setup_test();
+        let clock = Arc::new(MockClock::new());
+        let server = Server::with_clock(clock.clone()).start().await.unwrap();
+        let mut config = get_default_config();
+        config
+            .set(
+                MAX_ADDRESS_SIZE_CONFIG_KEY_NAME,
+                MODEL_MAX_ADDRESS_SIZE as i64,
+            )
+            .unwrap()
+            .set(
+                MAX_TX_DATA_SIZE_CONFIG_KEY_NAME,
+                MODEL_MAX_TX_DATA_SIZE as i64,
+            )
+            .unwrap()
+            .set(TIME_WINDOW_CONFIG_KEY_NAME, MODEL_TIME_WINDOW)
+            .unwrap()
+            .set(MSG_RETENTION_DUR_CONFIG_KEY_NAME, MODEL_RETENTION as i64)
             .unwrap();
+        server.call(SetConfig(config)).await.unwrap().unwrap();
+        let store = server.call(GetMessageStore {}).await.unwrap().unwrap();
+
+        // accepted messages of each address with stored messages, in stored order
+        let mut model: BTreeMap<Vec<u8>, Vec<UserMessage>> = BTreeMap::new();
+        for op in ops {
+            let now = clock.now().timestamp();
+            match op {
+                ModelOp::Store {
+                    address,
+                    created_offset,
+                    data_size,
+                } => {
+                    let user_msg = UserMessage {
+                        net_id: 1,
+                        created: (now + created_offset) as u64,
+                        address: address.get(&addresses).clone(),
+                        transaction_type: TransactionType::VaultWithdraw as i32,
+                        transaction_data: vec![data_size as u8; data_size],
+                        encrypted_transaction_data: None,
+                    };
+                    let accepted = model_accepts(&user_msg, now);
+                    let res = store.store_message(StoreMessageRequest {
+                        user_message: Some(user_msg.clone()),
+                    });
+                    assert_eq!(res.is_ok(), accepted, "{:?}: {:?}", user_msg, res);
+                    if accepted {
+                        model
+                            .entry(user_msg.address.clone())
+                            .or_default()
+                            .push(user_msg);
+                    }
+                }
+                ModelOp::Get { address } => {
+                    let address = address.get(&addresses);
+                    let messages = store
+                        .get_messages(GetMessagesRequest {
+                            address: address.clone(),
+                        })
+                        .unwrap();
+                    assert_eq!(messages, model.get(address).cloned().unwrap_or_default());
+                }
+                ModelOp::Prune { advance } => {
+                    clock.advance(advance);
+                    store.delete_old_messages().unwrap();
+                    let min_created =
+                        (clock.now().timestamp() as u64).saturating_sub(MODEL_RETENTION);
+                    for messages in model.values_mut() {
+                        messages.retain(|m| m.created >= min_created);
+                    }
+                    model.retain(|_, messages| !messages.is_empty());
+                }
+            }
 
-        assert!(res.is_err());
+            // the addresses index has exactly the addresses with stored messages
+            let db = store.db().unwrap();
+            let indexed = store.settings().stored_addresses(&db).unwrap();
+            let expected: HashSet<Vec<u8>> = model.keys().cloned().collect();
+            assert_eq!(indexed, expected);
+            let listed: Vec<(Vec<u8>, u32)> = server
+                .call(ListAddresses {})
+                .await
+                .unwrap()
+                .unwrap()
+                .into_iter()
+                .map(|a| (a.address, a.message_count))
+                .collect();
+            let expected: Vec<(Vec<u8>, u32)> = model
+                .iter()
+                .map(|(address, messages)| (address.clone(), messages.len() as u32))
+                .collect();
+            assert_eq!(listed, expected);
+        }
+
+        // every address has its accepted and unexpired messages, in stored order
+        for address in addresses {
+            let messages = store
+                .get_messages(GetMessagesRequest {
+                    address: address.clone(),
+                })
+                .unwrap();
+            assert_eq!(messages, model.remove(&address).unwrap_or_default());
+        }
 
         // cleanup
-        let _ = server.call(DeleteDb {}).await.unwrap().unwrap();
+        server.call(DeleteDb {}).await.unwrap().unwrap();
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        #[serial]
+        fn store_get_prune_model(
+            addresses in prop::collection::vec(model_address(), 1..=4),
+            ops in prop::collection::vec(model_op(), 1..=50),
+        ) {
+            // each case runs a new server, as tokio tests do
+            tokio::runtime::Runtime::new()
+                .unwrap()
+                .block_on(check_store_model(addresses, ops));
+        }
     }
 }
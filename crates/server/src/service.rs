@@ -1,24 +1,134 @@
 use crate::api::api::multi_sig_service_server::MultiSigService;
-use crate::server::{GetMessages, Server, StoreMessage};
+use crate::logging::log_address;
+use crate::metrics;
+use crate::server::{InvalidInput, MessageStore};
+use crate::telemetry;
 use anyhow::Result;
 use api::api::{
-    GetMessagesRequest, GetMessagesResponse, StoreMessageRequest, StoreMessageResponse,
+    GetMessagesRequest, GetMessagesResponse, GetServerInfoRequest, GetServerInfoResponse,
+    StoreMessageRequest, StoreMessageResponse,
 };
+use std::time::Instant;
+use tonic::metadata::MetadataMap;
 use tonic::{Request, Response, Status};
-use xactor::Service;
+use tracing::field::Empty;
+use tracing::info_span;
+use tracing::{Instrument, Span};
 
-/// GrpcService implements MultiSigService
-#[derive(Debug)]
-pub(crate) struct GrpcService {}
+/// GrpcService implements MultiSigService.
+/// Requests are handled concurrently with the server's message store, on the blocking thread
+/// pool, instead of being serialized by the server system service.
+#[derive(Clone)]
+pub(crate) struct GrpcService {
+    store: MessageStore,
+}
 
-impl Default for GrpcService {
-    fn default() -> Self {
+impl GrpcService {
+    pub(crate) fn new(store: MessageStore) -> Self {
         info!("Multisig message grpc service started");
-        GrpcService {}
+        GrpcService { store }
+    }
+
+    async fn handle_store_message(
+        &self,
+        request: Request<StoreMessageRequest>,
+    ) -> Result<Response<StoreMessageResponse>, Status> {
+        let request = request.into_inner();
+        self.store
+            .spawn(move |store| store.store_message(request))
+            .await
+            .map_err(to_status)?;
+
+        Ok(Response::new(StoreMessageResponse {}))
+    }
+
+    async fn handle_get_messages(
+        &self,
+        request: Request<GetMessagesRequest>,
+    ) -> Result<Response<GetMessagesResponse>, Status> {
+        let request = request.into_inner();
+        let user_messages = self
+            .store
+            .spawn(move |store| store.get_messages(request))
+            .await
+            .map_err(to_status)?;
+
+        Ok(Response::new(GetMessagesResponse { user_messages }))
+    }
+
+    async fn handle_get_server_info(
+        &self,
+        _request: Request<GetServerInfoRequest>,
+    ) -> Result<Response<GetServerInfoResponse>, Status> {
+        Ok(Response::new(self.store.server_info()))
+    }
+}
+
+// Map a server error to a grpc status. Input validation errors are invalid argument errors.
+pub(crate) fn to_status(e: anyhow::Error) -> Status {
+    if e.downcast_ref::<InvalidInput>().is_some() {
+        Status::invalid_argument(e.to_string())
+    } else {
+        Status::internal(format!("error: {}", e))
     }
 }
 
-impl GrpcService {}
+// Header of an optional client provided request id
+const REQUEST_ID_HEADER: &str = "x-request-id";
+// Max length of a client provided request id
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+// Returns the request's x-request-id header, or a new request id if it is missing, longer than
+// MAX_REQUEST_ID_LEN or has characters other than visible ascii
+fn request_id(metadata: &MetadataMap) -> String {
+    metadata
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| v.len() <= MAX_REQUEST_ID_LEN && v.bytes().all(|b| b.is_ascii_graphic()))
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| hex::encode(rand::random::<[u8; 8]>()))
+}
+
+// Create the tracing span of an rpc request.
+// The request id is taken from the request's x-request-id header, or generated if it is missing.
+// The span's parent is the caller's span when the request has a w3c trace context.
+pub(crate) fn rpc_span<T>(rpc: &str, request: &Request<T>, address: Option<&[u8]>) -> Span {
+    let request_id = request_id(request.metadata());
+
+    let span = info_span!(
+        "rpc",
+        request_id = %request_id,
+        rpc,
+        address = Empty,
+        outcome = Empty
+    );
+    if let Some(address) = address {
+        span.record("address", log_address(address).as_str());
+    }
+    telemetry::set_remote_parent(&span, request.metadata());
+    span
+}
+
+// Handle an rpc request in its span and record its outcome
+pub(crate) async fn traced_rpc<T>(
+    rpc: &str,
+    span: Span,
+    handler: impl std::future::Future<Output = Result<Response<T>, Status>>,
+) -> Result<Response<T>, Status> {
+    let start = Instant::now();
+    let res = handler.instrument(span.clone()).await;
+    let outcome = match &res {
+        Ok(_) => "Ok".to_string(),
+        Err(status) => format!("{:?}", status.code()),
+    };
+    metrics::observe_rpc(rpc, &outcome, start);
+    span.record("outcome", outcome.as_str());
+    span.in_scope(|| match &res {
+        Ok(_) => tracing::info!("{} completed", rpc),
+        Err(status) => tracing::info!("{} failed: {}", rpc, status.message()),
+    });
+    res
+}
 
 #[tonic::async_trait]
 impl MultiSigService for GrpcService {
@@ -27,17 +137,13 @@ impl MultiSigService for GrpcService {
         &self,
         request: Request<StoreMessageRequest>,
     ) -> Result<Response<StoreMessageResponse>, Status> {
-        let server = Server::from_registry()
-            .await
-            .map_err(|e| Status::internal(format!("internal error: {}", e)))?;
-
-        let _ = server
-            .call(StoreMessage(request.into_inner()))
-            .await
-            .map_err(|e| Status::internal(format!("internal call error: {}", e)))?
-            .map_err(|e| Status::internal(format!("error: {}", e)))?;
-
-        Ok(Response::new(StoreMessageResponse {}))
+        let address = request
+            .get_ref()
+            .user_message
+            .as_ref()
+            .map(|m| m.address.as_slice());
+        let span = rpc_span("StoreMessage", &request, address);
+        traced_rpc("StoreMessage", span, self.handle_store_message(request)).await
     }
 
     /// Returns stored messages for a provided address
@@ -45,16 +151,44 @@ impl MultiSigService for GrpcService {
         &self,
         request: Request<GetMessagesRequest>,
     ) -> Result<Response<GetMessagesResponse>, Status> {
-        let server = Server::from_registry()
-            .await
-            .map_err(|e| Status::internal(format!("internal error: {}", e)))?;
+        let span = rpc_span("GetMessages", &request, Some(&request.get_ref().address));
+        traced_rpc("GetMessages", span, self.handle_get_messages(request)).await
+    }
 
-        let user_messages = server
-            .call(GetMessages(request.into_inner()))
-            .await
-            .map_err(|e| Status::internal(format!("internal call error: {}", e)))?
-            .map_err(|e| Status::internal(format!("error: {}", e)))?;
+    /// Returns the server version and the messages it accepts
+    async fn get_server_info(
+        &self,
+        request: Request<GetServerInfoRequest>,
+    ) -> Result<Response<GetServerInfoResponse>, Status> {
+        let span = rpc_span("GetServerInfo", &request, None);
+        traced_rpc("GetServerInfo", span, self.handle_get_server_info(request)).await
+    }
+}
 
-        Ok(Response::new(GetMessagesResponse { user_messages }))
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_request_id() {
+        let with_id = |id: &str| {
+            let mut metadata = MetadataMap::new();
+            metadata.insert(REQUEST_ID_HEADER, id.parse().unwrap());
+            request_id(&metadata)
+        };
+        assert_eq!(with_id("req-1"), "req-1");
+        let id = "a".repeat(MAX_REQUEST_ID_LEN);
+        assert_eq!(with_id(&id), id);
+
+        // a new id replaces missing and long ids, and ids with spaces or control characters
+        let long_id = "a".repeat(MAX_REQUEST_ID_LEN + 1);
+        for id in [
+            with_id(&long_id),
+            with_id("req 1"),
+            request_id(&MetadataMap::new()),
+        ] {
+            assert_eq!(id.len(), 16);
+            assert!(id.bytes().all(|b| b.is_ascii_hexdigit()));
+        }
     }
 }
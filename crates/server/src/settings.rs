@@ -0,0 +1,506 @@
+use crate::address_hash::ADDRESS_HASH_SECRET_FILE_CONFIG_KEY_NAME;
+use crate::admin::{ADMIN_HOST_CONFIG_KEY_NAME, ADMIN_PORT_CONFIG_KEY_NAME};
+use crate::backup::{
+    BACKUP_DIR_CONFIG_KEY_NAME, BACKUP_INTERVAL_CONFIG_KEY_NAME, BACKUP_RETENTION_CONFIG_KEY_NAME,
+};
+use crate::encryption::{MASTER_KEY_FILE_CONFIG_KEY_NAME, PREV_MASTER_KEY_FILE_CONFIG_KEY_NAME};
+use crate::limits::{
+    Limits, MAX_ADDRESS_SIZE_CONFIG_KEY_NAME, MAX_ENVELOPE_RECIPIENTS_CONFIG_KEY_NAME,
+    MAX_TX_DATA_SIZE_CONFIG_KEY_NAME, TIME_WINDOW_CONFIG_KEY_NAME, TRANSACTION_TYPES,
+};
+use crate::logging::{
+    log_filter, LOG_FORMAT_CONFIG_KEY_NAME, LOG_FORMAT_JSON, LOG_FORMAT_TEXT,
+    LOG_LEVEL_CONFIG_KEY_NAME, LOG_REDACT_ADDRESSES_CONFIG_KEY_NAME,
+};
+use crate::metrics::{METRICS_HOST_CONFIG_KEY_NAME, METRICS_PORT_CONFIG_KEY_NAME};
+use crate::server::MSG_RETENTION_DUR_CONFIG_KEY_NAME;
+use crate::telemetry::{
+    OTLP_ENDPOINT_CONFIG_KEY_NAME, OTLP_PROTOCOL_CONFIG_KEY_NAME, OTLP_PROTOCOL_GRPC,
+    OTLP_PROTOCOL_HTTP,
+};
+use crate::{
+    DB_INTERVAL_CONFIG_KEY_NAME, HEALTH_CHECK_INTERVAL_CONFIG_KEY_NAME, HOST_CONFIG_KEY_NAME,
+    PORT_CONFIG_KEY_NAME, SHUTDOWN_DEADLINE_CONFIG_KEY_NAME,
+};
+use anyhow::{anyhow, bail, Result};
+use clap::{App, Arg, ArgMatches};
+use config::{Config, Source};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+
+/// Prefix of environment variables overriding config settings, e.g. MULTISIG_PORT sets port
+pub(crate) const ENV_VAR_PREFIX: &str = "MULTISIG_";
+
+/// Precedence of config sources, from lowest to highest
+pub(crate) const CONFIG_PRECEDENCE_HELP: &str = "Config settings are read from, in increasing \
+    precedence: defaults, the config file, MULTISIG_<KEY> environment variables (e.g. \
+    MULTISIG_PORT) and command line flags (e.g. --port).";
+
+// Config keys and their descriptions
+const CONFIG_KEYS: &[(&str, &str)] = &[
+    (HOST_CONFIG_KEY_NAME, "grpc service host"),
+    (PORT_CONFIG_KEY_NAME, "grpc service port"),
+    (
+        DB_INTERVAL_CONFIG_KEY_NAME,
+        "seconds between db cleanups of old messages",
+    ),
+    (
+        MSG_RETENTION_DUR_CONFIG_KEY_NAME,
+        "seconds messages are kept before they are deleted",
+    ),
+    (
+        SHUTDOWN_DEADLINE_CONFIG_KEY_NAME,
+        "max seconds to wait for in-flight requests on shutdown",
+    ),
+    (
+        HEALTH_CHECK_INTERVAL_CONFIG_KEY_NAME,
+        "seconds between db health checks",
+    ),
+    (
+        LOG_FORMAT_CONFIG_KEY_NAME,
+        "log output format: text or json",
+    ),
+    (LOG_LEVEL_CONFIG_KEY_NAME, "log level, e.g. info or debug"),
+    (
+        LOG_REDACT_ADDRESSES_CONFIG_KEY_NAME,
+        "log only a prefix of message addresses",
+    ),
+    (
+        OTLP_ENDPOINT_CONFIG_KEY_NAME,
+        "OTLP collector endpoint. Traces are not exported when empty",
+    ),
+    (OTLP_PROTOCOL_CONFIG_KEY_NAME, "OTLP protocol: grpc or http"),
+    (METRICS_HOST_CONFIG_KEY_NAME, "metrics http service host"),
+    (
+        METRICS_PORT_CONFIG_KEY_NAME,
+        "metrics http service port. Metrics are not served when 0",
+    ),
+    (
+        ADMIN_HOST_CONFIG_KEY_NAME,
+        "admin grpc service host. Must be a loopback address",
+    ),
+    (
+        ADMIN_PORT_CONFIG_KEY_NAME,
+        "admin grpc service port. The admin service is not served when 0",
+    ),
+    (BACKUP_DIR_CONFIG_KEY_NAME, "directory of db backups"),
+    (
+        BACKUP_INTERVAL_CONFIG_KEY_NAME,
+        "seconds between scheduled db backups. Scheduled backups are disabled when 0",
+    ),
+    (
+        BACKUP_RETENTION_CONFIG_KEY_NAME,
+        "number of latest db backups kept",
+    ),
+    (MAX_ADDRESS_SIZE_CONFIG_KEY_NAME, "max message address size"),
+    (
+        MAX_TX_DATA_SIZE_CONFIG_KEY_NAME,
+        "max message transaction data size",
+    ),
+    (
+        MAX_ENVELOPE_RECIPIENTS_CONFIG_KEY_NAME,
+        "max recipients of an encrypted message",
+    ),
+    (
+        TIME_WINDOW_CONFIG_KEY_NAME,
+        "max seconds between a new message creation time and the server time",
+    ),
+    (
+        MASTER_KEY_FILE_CONFIG_KEY_NAME,
+        "file with the hex master key to encrypt db records with",
+    ),
+    (
+        PREV_MASTER_KEY_FILE_CONFIG_KEY_NAME,
+        "file with the hex previous master key, used while rotating the master key",
+    ),
+    (
+        ADDRESS_HASH_SECRET_FILE_CONFIG_KEY_NAME,
+        "file with the hex secret to hash message addresses with",
+    ),
+];
+
+/// A config key that can be set by an environment variable and a command line flag
+pub(crate) struct ConfigKey {
+    pub(crate) name: String,
+    pub(crate) help: String,
+    pub(crate) env_var: String,
+    pub(crate) flag: String,
+}
+
+impl ConfigKey {
+    fn new(name: &str, help: &str) -> ConfigKey {
+        ConfigKey {
+            name: name.to_string(),
+            help: help.to_string(),
+            env_var: format!("{}{}", ENV_VAR_PREFIX, name.to_uppercase()),
+            flag: name.replace('_', "-"),
+        }
+    }
+}
+
+/// Returns all config keys
+pub(crate) fn config_keys() -> Vec<ConfigKey> {
+    let mut keys: Vec<ConfigKey> = CONFIG_KEYS
+        .iter()
+        .map(|(name, help)| ConfigKey::new(name, help))
+        .collect();
+    for (_, type_name) in TRANSACTION_TYPES {
+        keys.push(ConfigKey::new(
+            &format!("{}_{}", MAX_TX_DATA_SIZE_CONFIG_KEY_NAME, type_name),
+            &format!("max {} message transaction data size", type_name),
+        ));
+    }
+    keys
+}
+
+/// Add a command line flag for each config key
+pub(crate) fn add_config_args<'a>(mut app: App<'a, 'a>, keys: &'a [ConfigKey]) -> App<'a, 'a> {
+    for key in keys {
+        app = app.arg(
+            Arg::with_name(&key.flag)
+                .long(&key.flag)
+                .takes_value(true)
+                .value_name("VALUE")
+                .help(&key.help),
+        );
+    }
+    app
+}
+
+/// Set config keys from their environment variables. env returns the value of a variable.
+pub(crate) fn apply_env_overrides(
+    config: &mut Config,
+    keys: &[ConfigKey],
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<()> {
+    for key in keys {
+        if let Some(value) = env(&key.env_var) {
+            config.set(&key.name, value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Set config keys from their command line flags
+pub(crate) fn apply_arg_overrides(
+    config: &mut Config,
+    keys: &[ConfigKey],
+    args: &ArgMatches,
+) -> Result<()> {
+    for key in keys {
+        if let Some(value) = args.value_of(&key.flag) {
+            config.set(&key.name, value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the config in the toml config file format
+pub(crate) fn config_to_toml(config: &Config) -> Result<String> {
+    let values: BTreeMap<String, String> = config
+        .collect()?
+        .into_iter()
+        .map(|(k, v)| {
+            let debug = format!("{:?}", v);
+            (k, v.into_str().unwrap_or(debug))
+        })
+        .collect();
+
+    Ok(values
+        .into_iter()
+        .map(|(key, value)| {
+            if value.parse::<i64>().is_ok() || value.parse::<bool>().is_ok() {
+                format!("{} = {}\n", key, value)
+            } else {
+                format!("{} = {:?}\n", key, value)
+            }
+        })
+        .collect())
+}
+
+/// Server settings read from a config, validated on startup and on config reload.
+/// Settings read by other modules, e.g. limits and secret files, are validated by them.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct Settings {
+    pub(crate) host: String,
+    // ports are read as u64 as config casts out of range integers
+    pub(crate) port: u64,
+    pub(crate) db_cleanup_interval: u64,
+    pub(crate) msg_retention_duration: u64,
+    pub(crate) shutdown_deadline: u64,
+    pub(crate) health_check_interval: u64,
+    pub(crate) log_format: String,
+    pub(crate) log_level: String,
+    pub(crate) log_redact_addresses: bool,
+    pub(crate) otlp_protocol: String,
+    pub(crate) metrics_host: String,
+    pub(crate) metrics_port: u64,
+    pub(crate) admin_host: String,
+    pub(crate) admin_port: u64,
+    pub(crate) backup_dir: String,
+    pub(crate) backup_interval: u64,
+    pub(crate) backup_retention: u64,
+}
+
+const DB_CLEANUP_INTERVAL_BOUNDS: (u64, u64) = (1, 60 * 60 * 24 * 365);
+const MSG_RETENTION_DURATION_MAX: u64 = 60 * 60 * 24 * 365 * 10;
+const HEALTH_CHECK_INTERVAL_BOUNDS: (u64, u64) = (1, 60 * 60);
+const SHUTDOWN_DEADLINE_BOUNDS: (u64, u64) = (0, 60 * 60);
+const PORT_BOUNDS: (u64, u64) = (0, u16::MAX as u64);
+const BACKUP_INTERVAL_BOUNDS: (u64, u64) = (0, 60 * 60 * 24 * 365);
+const BACKUP_RETENTION_BOUNDS: (u64, u64) = (1, 1000);
+
+impl Settings {
+    /// Read and validate settings. Unknown config keys and invalid values are rejected.
+    pub(crate) fn from_config(config: &Config) -> Result<Settings> {
+        let keys: Vec<String> = config_keys().into_iter().map(|k| k.name).collect();
+        let mut unknown: Vec<String> = config
+            .collect()?
+            .into_keys()
+            .filter(|k| !keys.contains(k))
+            .collect();
+        if !unknown.is_empty() {
+            unknown.sort();
+            bail!("unknown config keys: {}", unknown.join(", "));
+        }
+
+        let settings: Settings = config.clone().try_into()?;
+        settings.validate()?;
+        Limits::from_config(config)?;
+        Ok(settings)
+    }
+
+    fn validate(&self) -> Result<()> {
+        check_bounds(PORT_CONFIG_KEY_NAME, self.port, PORT_BOUNDS)?;
+        check_bounds(METRICS_PORT_CONFIG_KEY_NAME, self.metrics_port, PORT_BOUNDS)?;
+        check_bounds(ADMIN_PORT_CONFIG_KEY_NAME, self.admin_port, PORT_BOUNDS)?;
+        self.grpc_addr()?;
+        if self.metrics_port != 0 {
+            self.metrics_addr()?;
+        }
+        if self.admin_port != 0 {
+            // the admin service must not be reachable from other hosts
+            if !self.admin_addr()?.ip().is_loopback() {
+                bail!("{} must be a loopback address", ADMIN_HOST_CONFIG_KEY_NAME);
+            }
+            if self.admin_port == self.port {
+                bail!(
+                    "{} must be different from {}",
+                    ADMIN_PORT_CONFIG_KEY_NAME,
+                    PORT_CONFIG_KEY_NAME
+                );
+            }
+        }
+        check_bounds(
+            DB_INTERVAL_CONFIG_KEY_NAME,
+            self.db_cleanup_interval,
+            DB_CLEANUP_INTERVAL_BOUNDS,
+        )?;
+        // messages must be kept until at least the next cleanup
+        check_bounds(
+            MSG_RETENTION_DUR_CONFIG_KEY_NAME,
+            self.msg_retention_duration,
+            (self.db_cleanup_interval, MSG_RETENTION_DURATION_MAX),
+        )?;
+        check_bounds(
+            HEALTH_CHECK_INTERVAL_CONFIG_KEY_NAME,
+            self.health_check_interval,
+            HEALTH_CHECK_INTERVAL_BOUNDS,
+        )?;
+        check_bounds(
+            SHUTDOWN_DEADLINE_CONFIG_KEY_NAME,
+            self.shutdown_deadline,
+            SHUTDOWN_DEADLINE_BOUNDS,
+        )?;
+        check_bounds(
+            BACKUP_INTERVAL_CONFIG_KEY_NAME,
+            self.backup_interval,
+            BACKUP_INTERVAL_BOUNDS,
+        )?;
+        check_bounds(
+            BACKUP_RETENTION_CONFIG_KEY_NAME,
+            self.backup_retention,
+            BACKUP_RETENTION_BOUNDS,
+        )?;
+        check_one_of(
+            LOG_FORMAT_CONFIG_KEY_NAME,
+            &self.log_format,
+            &[LOG_FORMAT_TEXT, LOG_FORMAT_JSON],
+        )?;
+        check_one_of(
+            OTLP_PROTOCOL_CONFIG_KEY_NAME,
+            &self.otlp_protocol,
+            &[OTLP_PROTOCOL_GRPC, OTLP_PROTOCOL_HTTP],
+        )?;
+        log_filter(&self.log_level)?;
+        Ok(())
+    }
+
+    /// The grpc service listen address
+    pub(crate) fn grpc_addr(&self) -> Result<SocketAddr> {
+        listen_addr(HOST_CONFIG_KEY_NAME, &self.host, self.port)
+    }
+
+    /// The metrics service listen address
+    pub(crate) fn metrics_addr(&self) -> Result<SocketAddr> {
+        listen_addr(
+            METRICS_HOST_CONFIG_KEY_NAME,
+            &self.metrics_host,
+            self.metrics_port,
+        )
+    }
+
+    /// The admin grpc service listen address
+    pub(crate) fn admin_addr(&self) -> Result<SocketAddr> {
+        listen_addr(
+            ADMIN_HOST_CONFIG_KEY_NAME,
+            &self.admin_host,
+            self.admin_port,
+        )
+    }
+}
+
+fn listen_addr(key: &str, host: &str, port: u64) -> Result<SocketAddr> {
+    format!("{}:{}", host, port)
+        .parse()
+        .map_err(|_| anyhow!("invalid {}: {}. expected an ip address", key, host))
+}
+
+fn check_bounds(key: &str, value: u64, bounds: (u64, u64)) -> Result<()> {
+    if value < bounds.0 || value > bounds.1 {
+        bail!("{} must be between {} and {}", key, bounds.0, bounds.1)
+    }
+    Ok(())
+}
+
+fn check_one_of(key: &str, value: &str, allowed: &[&str]) -> Result<()> {
+    if !allowed.contains(&value) {
+        bail!(
+            "invalid {}: {}. expected one of: {}",
+            key,
+            value,
+            allowed.join(", ")
+        )
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_default_config;
+    use std::collections::HashMap;
+
+    fn test_app(keys: &[ConfigKey]) -> App<'_, '_> {
+        add_config_args(App::new("test"), keys)
+    }
+
+    #[test]
+    fn override_precedence() {
+        let keys = config_keys();
+        let mut config = get_default_config();
+        config
+            .set(PORT_CONFIG_KEY_NAME, 7000)
+            .unwrap()
+            .set(HOST_CONFIG_KEY_NAME, "file-host")
+            .unwrap()
+            .set(MSG_RETENTION_DUR_CONFIG_KEY_NAME, 10)
+            .unwrap();
+
+        // the variables are read from a map, so the process environment isn't changed
+        let env: HashMap<&str, &str> = vec![
+            ("MULTISIG_PORT", "7001"),
+            ("MULTISIG_HOST", "env-host"),
+            ("MULTISIG_MAX_TX_DATA_SIZE_COIN_SPEND", "512"),
+        ]
+        .into_iter()
+        .collect();
+        apply_env_overrides(&mut config, &keys, |name| {
+            env.get(name).map(|v| v.to_string())
+        })
+        .unwrap();
+
+        let args = test_app(&keys).get_matches_from(vec!["test", "--port", "7002"]);
+        apply_arg_overrides(&mut config, &keys, &args).unwrap();
+
+        assert_eq!(config.get_int(PORT_CONFIG_KEY_NAME).unwrap(), 7002);
+        assert_eq!(config.get_str(HOST_CONFIG_KEY_NAME).unwrap(), "env-host");
+        assert_eq!(
+            config.get_int(MSG_RETENTION_DUR_CONFIG_KEY_NAME).unwrap(),
+            10
+        );
+        assert_eq!(config.get_int("max_tx_data_size_coin_spend").unwrap(), 512);
+    }
+
+    #[test]
+    fn print_config() {
+        let mut config = get_default_config();
+        config
+            .set(MASTER_KEY_FILE_CONFIG_KEY_NAME, "./key")
+            .unwrap();
+        let toml = config_to_toml(&config).unwrap();
+        assert!(toml.contains("port = 6667\n"));
+        assert!(toml.contains("host = \"[::1]\"\n"));
+        assert!(toml.contains("master_key_file = \"./key\"\n"));
+        assert!(toml.contains("log_redact_addresses = true\n"));
+
+        // printed config can be read as a config file
+        let mut parsed = Config::default();
+        parsed
+            .merge(config::File::from_str(&toml, config::FileFormat::Toml))
+            .unwrap();
+        assert_eq!(parsed.get_int(PORT_CONFIG_KEY_NAME).unwrap(), 6667);
+        assert_eq!(parsed.get_str(HOST_CONFIG_KEY_NAME).unwrap(), "[::1]");
+    }
+
+    #[test]
+    fn validate_settings() {
+        let settings = Settings::from_config(&get_default_config()).unwrap();
+        assert_eq!(settings.port, 6667);
+        assert_eq!(settings.grpc_addr().unwrap().to_string(), "[::1]:6667");
+
+        let invalid = |key: &str, value: &str| {
+            let mut config = get_default_config();
+            config.set(key, value).unwrap();
+            Settings::from_config(&config).unwrap_err().to_string()
+        };
+        assert_eq!(invalid("prot", "7000"), "unknown config keys: prot");
+        assert!(invalid(PORT_CONFIG_KEY_NAME, "port").contains("port"));
+        assert_eq!(
+            invalid(PORT_CONFIG_KEY_NAME, "70000"),
+            "port must be between 0 and 65535"
+        );
+        assert_eq!(
+            invalid(HOST_CONFIG_KEY_NAME, "localhost"),
+            "invalid host: localhost. expected an ip address"
+        );
+        assert_eq!(
+            invalid(MSG_RETENTION_DUR_CONFIG_KEY_NAME, "60"),
+            "msg_retention_duration must be between 864000 and 315360000"
+        );
+        assert_eq!(
+            invalid(DB_INTERVAL_CONFIG_KEY_NAME, "0"),
+            "db_cleanup_interval must be between 1 and 31536000"
+        );
+        assert_eq!(
+            invalid(LOG_FORMAT_CONFIG_KEY_NAME, "xml"),
+            "invalid log_format: xml. expected one of: text, json"
+        );
+        assert!(invalid(MAX_ADDRESS_SIZE_CONFIG_KEY_NAME, "0").contains("max_address_size"));
+
+        // the admin service is local only
+        let mut config = get_default_config();
+        config
+            .set(ADMIN_PORT_CONFIG_KEY_NAME, 6668)
+            .unwrap()
+            .set(ADMIN_HOST_CONFIG_KEY_NAME, "0.0.0.0")
+            .unwrap();
+        assert_eq!(
+            Settings::from_config(&config).unwrap_err().to_string(),
+            "admin_host must be a loopback address"
+        );
+        config.set(ADMIN_HOST_CONFIG_KEY_NAME, "127.0.0.1").unwrap();
+        assert!(Settings::from_config(&config).is_ok());
+    }
+}
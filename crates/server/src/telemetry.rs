@@ -0,0 +1,168 @@
+use anyhow::{bail, Result};
+use config::Config;
+use opentelemetry::global;
+use opentelemetry::propagation::Extractor;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{self, Tracer};
+use opentelemetry_sdk::{runtime, Resource};
+use tonic::metadata::{KeyRef, MetadataMap};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+pub(crate) const OTLP_ENDPOINT_CONFIG_KEY_NAME: &str = "otlp_endpoint";
+pub(crate) const OTLP_PROTOCOL_CONFIG_KEY_NAME: &str = "otlp_protocol";
+
+pub(crate) const OTLP_PROTOCOL_GRPC: &str = "grpc";
+pub(crate) const OTLP_PROTOCOL_HTTP: &str = "http";
+
+const SERVICE_NAME: &str = "multisig-service";
+
+/// Returns a tracer exporting spans to the configured OTLP collector, or None when trace export
+/// is disabled, i.e. no collector endpoint is set.
+/// A grpc collector endpoint is e.g. http://localhost:4317 and an http collector endpoint is e.g.
+/// http://localhost:4318. Spans are posted to the /v1/traces path of an http collector.
+pub(crate) fn tracer(config: &Config) -> Result<Option<Tracer>> {
+    let endpoint = config.get_str(OTLP_ENDPOINT_CONFIG_KEY_NAME)?;
+    if endpoint.is_empty() {
+        return Ok(None);
+    }
+
+    // trace context of incoming requests is read from w3c traceparent headers
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let pipeline = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_trace_config(trace::config().with_resource(Resource::new(vec![
+            KeyValue::new("service.name", SERVICE_NAME),
+            KeyValue::new("service.version", crate::server::SERVER_VERSION),
+        ])));
+
+    let tracer = match config.get_str(OTLP_PROTOCOL_CONFIG_KEY_NAME)?.as_str() {
+        OTLP_PROTOCOL_GRPC => pipeline
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(endpoint),
+            )
+            .install_batch(runtime::Tokio)?,
+        OTLP_PROTOCOL_HTTP => pipeline
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .http()
+                    .with_endpoint(endpoint),
+            )
+            .install_batch(runtime::Tokio)?,
+        other => bail!(
+            "invalid {}: {}. expected {} or {}",
+            OTLP_PROTOCOL_CONFIG_KEY_NAME,
+            other,
+            OTLP_PROTOCOL_GRPC,
+            OTLP_PROTOCOL_HTTP
+        ),
+    };
+    Ok(Some(tracer))
+}
+
+/// Export remaining spans and stop trace export
+pub(crate) fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+/// Set the remote parent of a request span from the request's trace context headers
+pub(crate) fn set_remote_parent(span: &Span, metadata: &MetadataMap) {
+    let context = global::get_text_map_propagator(|p| p.extract(&MetadataExtractor(metadata)));
+    span.set_parent(context);
+}
+
+struct MetadataExtractor<'a>(&'a MetadataMap);
+
+impl Extractor for MetadataExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0
+            .keys()
+            .map(|k| match k {
+                KeyRef::Ascii(k) => k.as_str(),
+                KeyRef::Binary(k) => k.as_str(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response};
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use tokio::sync::mpsc;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+    // A stand-in collector accepting spans exported over http
+    async fn start_collector() -> (SocketAddr, mpsc::UnboundedReceiver<(String, Vec<u8>)>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let make_service = make_service_fn(move |_conn| {
+            let sender = sender.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let sender = sender.clone();
+                    async move {
+                        let path = req.uri().path().to_string();
+                        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                        let _ = sender.send((path, body.to_vec()));
+                        Ok::<_, Infallible>(Response::new(Body::empty()))
+                    }
+                }))
+            }
+        });
+        let server = hyper::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        (addr, receiver)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn export_request_spans() {
+        let (addr, mut receiver) = start_collector().await;
+
+        let mut config = Config::default();
+        config
+            .set(OTLP_ENDPOINT_CONFIG_KEY_NAME, format!("http://{}", addr))
+            .unwrap()
+            .set(OTLP_PROTOCOL_CONFIG_KEY_NAME, OTLP_PROTOCOL_HTTP)
+            .unwrap();
+        let tracer = tracer(&config).unwrap().unwrap();
+        let subscriber =
+            tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+
+        // a request span joins the trace of the request's w3c trace context
+        let mut metadata = MetadataMap::new();
+        metadata.insert(
+            "traceparent",
+            format!("00-{}-00f067aa0ba902b7-01", TRACE_ID)
+                .parse()
+                .unwrap(),
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("rpc");
+            set_remote_parent(&span, &metadata);
+            span.in_scope(|| tracing::info_span!("db.get").in_scope(|| {}));
+        });
+        tokio::task::spawn_blocking(shutdown).await.unwrap();
+
+        let (path, body) = receiver.recv().await.unwrap();
+        assert_eq!(path, "/v1/traces");
+        let trace_id = hex::decode(TRACE_ID).unwrap();
+        assert!(body
+            .windows(trace_id.len())
+            .any(|w| w == trace_id.as_slice()));
+    }
+}
@@ -0,0 +1,179 @@
+mod common;
+
+use api::api::{
+    BackupDbRequest, DeleteOldMessagesRequest, DeleteQuarantinedRecordRequest,
+    ExportMessagesRequest, GetConfigRequest, GetDbStatsRequest, GetMessagesRequest,
+    GetQuarantinedRecordRequest, ImportMessagesRequest, ListAddressesRequest,
+    ListQuarantinedRecordsRequest, PurgeAddressRequest, UpdateConfigRequest,
+};
+use common::*;
+use std::collections::HashMap;
+use tonic::Code;
+
+/// Returns an update config request of a single setting
+fn update_config(key: &str, value: &str) -> UpdateConfigRequest {
+    let mut settings = HashMap::new();
+    settings.insert(key.to_string(), value.to_string());
+    UpdateConfigRequest { settings }
+}
+
+#[tokio::test]
+async fn admin_service() {
+    let dir = test_dir();
+    let port = free_port();
+    let admin_port = free_port();
+    write_config(
+        &dir,
+        port,
+        &format!("admin_host = \"127.0.0.1\"\nadmin_port = {}\n", admin_port),
+    );
+    let (child, mut client) = start_server(&dir, port).await;
+    let mut admin = admin_client(admin_port).await;
+    let address1 = vec![1; 32];
+    let address2 = vec![2; 32];
+    // messages have different data, so none is skipped as already stored on import
+    for (i, address) in [&address1, &address1, &address2].iter().enumerate() {
+        let mut request = store_request(address.to_vec());
+        request.user_message.as_mut().unwrap().transaction_data = vec![i as u8; 128];
+        client.store_message(request).await.unwrap();
+    }
+
+    let stats = admin
+        .get_db_stats(GetDbStatsRequest {})
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(stats.address_count, 2);
+    assert_eq!(stats.message_count, 3);
+
+    let addresses = admin
+        .list_addresses(ListAddressesRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .addresses;
+    let counts: Vec<(Vec<u8>, u32)> = addresses
+        .into_iter()
+        .map(|a| (a.address, a.message_count))
+        .collect();
+    assert_eq!(counts, vec![(address1.clone(), 2), (address2.clone(), 1)]);
+
+    // only runtime settings can be updated, and only to valid values
+    let status = admin
+        .update_config(update_config("port", "1234"))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let status = admin
+        .update_config(update_config("max_address_size", "0"))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    admin
+        .update_config(update_config("max_address_size", "16"))
+        .await
+        .unwrap();
+    let settings = admin
+        .get_config(GetConfigRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .settings;
+    assert_eq!(settings["max_address_size"], "16");
+    let status = client
+        .store_message(store_request(address1.clone()))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    admin
+        .update_config(update_config("max_address_size", "128"))
+        .await
+        .unwrap();
+
+    // purged messages are restored from an export
+    let export_path = dir.join("messages.export").display().to_string();
+    let exported = admin
+        .export_messages(ExportMessagesRequest {
+            path: export_path.clone(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(exported.address_count, 2);
+    assert_eq!(exported.message_count, 3);
+    let purged = admin
+        .purge_address(PurgeAddressRequest {
+            address: address1.clone(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(purged.deleted_messages, 2);
+    let status = admin
+        .purge_address(PurgeAddressRequest {
+            address: b"all_addresses".to_vec(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let imported = admin
+        .import_messages(ImportMessagesRequest { path: export_path })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(imported.imported_messages, 2);
+    assert_eq!(imported.skipped_messages, 1);
+    let messages = client
+        .get_messages(GetMessagesRequest { address: address1 })
+        .await
+        .unwrap()
+        .into_inner()
+        .user_messages;
+    assert_eq!(messages.len(), 2);
+
+    // stored messages are not expired
+    admin
+        .delete_old_messages(DeleteOldMessagesRequest {})
+        .await
+        .unwrap();
+    let stats = admin
+        .get_db_stats(GetDbStatsRequest {})
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(stats.message_count, 3);
+
+    let backup = admin
+        .backup_db(BackupDbRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .path;
+    assert!(dir.join(backup).is_dir());
+
+    // nothing is quarantined
+    let records = admin
+        .list_quarantined_records(ListQuarantinedRecordsRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .records;
+    assert!(records.is_empty());
+    let status = admin
+        .get_quarantined_record(GetQuarantinedRecordRequest {
+            id: "00".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    let status = admin
+        .delete_quarantined_record(DeleteQuarantinedRecordRequest {
+            id: "00".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    assert!(terminate(child).await.status.success());
+    let _ = std::fs::remove_dir_all(&dir);
+}
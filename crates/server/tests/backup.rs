@@ -0,0 +1,68 @@
+mod common;
+
+use api::api::{BackupDbRequest, GetMessagesRequest};
+use common::*;
+
+#[tokio::test]
+async fn restore_backup() {
+    let dir = test_dir();
+    let port = free_port();
+    let admin_port = free_port();
+    write_config(
+        &dir,
+        port,
+        &format!("admin_host = \"127.0.0.1\"\nadmin_port = {}\n", admin_port),
+    );
+    let address: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
+    let get_messages = GetMessagesRequest {
+        address: address.clone(),
+    };
+
+    let (child, mut client) = start_server(&dir, port).await;
+    for _ in 0..2 {
+        client
+            .store_message(store_request(address.clone()))
+            .await
+            .unwrap();
+    }
+    let backed_up = client
+        .get_messages(get_messages.clone())
+        .await
+        .unwrap()
+        .into_inner();
+    let backup = admin_client(admin_port)
+        .await
+        .backup_db(BackupDbRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .path;
+
+    // messages stored after the backup are not restored
+    client
+        .store_message(store_request(address.clone()))
+        .await
+        .unwrap();
+    assert!(terminate(child).await.status.success());
+
+    let (child, mut client) =
+        start_server_with_args(&dir, port, &["--restore-from", &backup]).await;
+    let restored = client
+        .get_messages(get_messages)
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(restored, backed_up);
+    assert_eq!(restored.user_messages.len(), 2);
+    assert!(terminate(child).await.status.success());
+
+    // the replaced db is kept
+    assert!(std::fs::read_dir(&dir).unwrap().any(|e| e
+        .unwrap()
+        .file_name()
+        .to_str()
+        .unwrap()
+        .starts_with("data_store.pre-restore-")));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
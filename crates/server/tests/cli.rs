@@ -0,0 +1,99 @@
+mod common;
+
+use common::*;
+use std::process::Output;
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+#[tokio::test]
+async fn maintenance_commands() {
+    let dir = test_dir();
+    let port = free_port();
+    let address1 = vec![1; 32];
+    let address2 = vec![2; 32];
+
+    let (child, mut client) = start_server(&dir, port).await;
+    for address in [&address1, &address1, &address2] {
+        client
+            .store_message(store_request(address.clone()))
+            .await
+            .unwrap();
+    }
+    assert!(terminate(child).await.status.success());
+
+    let output = run_command(&dir, &["stats"]).await;
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("addresses: 2\nmessages: 3\n"));
+
+    let output = run_command(&dir, &["list-addresses"]).await;
+    assert_eq!(
+        stdout(&output),
+        format!(
+            "{} 2\n{} 1\n",
+            hex::encode(&address1),
+            hex::encode(&address2)
+        )
+    );
+
+    let output = run_command(&dir, &["dump", &hex::encode(&address1)]).await;
+    let dump = stdout(&output);
+    assert_eq!(dump.lines().count(), 2);
+    assert!(dump.contains("transaction_type: VaultWithdraw"));
+    assert!(!run_command(&dir, &["dump", "not hex"])
+        .await
+        .status
+        .success());
+
+    let output = run_command(&dir, &["verify"]).await;
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "no problems found\n");
+
+    assert!(run_command(&dir, &["compact"]).await.status.success());
+
+    // prune messages older than a second
+    write_config(
+        &dir,
+        port,
+        "db_cleanup_interval = 1\nmsg_retention_duration = 1\n",
+    );
+    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+    let output = run_command(&dir, &["prune", "--dry-run"]).await;
+    assert_eq!(
+        stdout(&output),
+        "3 old messages and 2 addresses would be deleted\n"
+    );
+    let output = run_command(&dir, &["prune"]).await;
+    assert_eq!(stdout(&output), "deleted 3 old messages and 2 addresses\n");
+    let output = run_command(&dir, &["stats"]).await;
+    assert!(stdout(&output).contains("addresses: 0\nmessages: 0\n"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn export_and_import() {
+    let dir = test_dir();
+    let port = free_port();
+    let (child, mut client) = start_server(&dir, port).await;
+    for address in [vec![1; 32], vec![2; 32]] {
+        client.store_message(store_request(address)).await.unwrap();
+    }
+    assert!(terminate(child).await.status.success());
+    let addresses = stdout(&run_command(&dir, &["list-addresses"]).await);
+
+    let output = run_command(&dir, &["export", "messages.export"]).await;
+    assert_eq!(stdout(&output), "exported 2 messages of 2 addresses\n");
+
+    // import to a new db
+    std::fs::remove_dir_all(dir.join("data_store")).unwrap();
+    let output = run_command(&dir, &["import", "messages.export"]).await;
+    assert_eq!(stdout(&output), "imported 2 messages, skipped 0\n");
+    assert_eq!(
+        stdout(&run_command(&dir, &["list-addresses"]).await),
+        addresses
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,141 @@
+#![allow(dead_code)]
+
+use api::api::multi_sig_admin_client::MultiSigAdminClient;
+use api::api::multi_sig_service_client::MultiSigServiceClient;
+use api::api::{StoreMessageRequest, TransactionType, UserMessage};
+use chrono::prelude::*;
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+use std::path::{Path, PathBuf};
+use std::process::{Output, Stdio};
+use tokio::process::{Child, Command};
+use tokio::time::{sleep, timeout, Duration};
+use tonic::transport::Channel;
+
+pub const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn test_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "multisig-server-test-{}-{}",
+        std::process::id(),
+        rand::random::<u32>()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// Write the server config file in dir. extra_config is appended to the file.
+pub fn write_config(dir: &Path, port: u16, extra_config: &str) {
+    std::fs::write(
+        dir.join("config.toml"),
+        format!(
+            "host = \"127.0.0.1\"\nport = {}\nshutdown_deadline = 5\n{}",
+            port, extra_config
+        ),
+    )
+    .unwrap();
+}
+
+/// Start the server in dir, so its db is created in dir, with the config file in dir
+pub async fn start_server(dir: &Path, port: u16) -> (Child, MultiSigServiceClient<Channel>) {
+    start_server_with_args(dir, port, &[]).await
+}
+
+/// Start the server in dir with extra command line args
+pub async fn start_server_with_args(
+    dir: &Path,
+    port: u16,
+    args: &[&str],
+) -> (Child, MultiSigServiceClient<Channel>) {
+    let config_file = dir.join("config.toml");
+    if !config_file.exists() {
+        write_config(dir, port, "");
+    }
+
+    let child = Command::new(env!("CARGO_BIN_EXE_multisig-service"))
+        .arg("--config")
+        .arg(&config_file)
+        .args(args)
+        .current_dir(dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = timeout(STARTUP_TIMEOUT, async {
+        loop {
+            match MultiSigServiceClient::connect(endpoint.clone()).await {
+                Ok(client) => break client,
+                Err(_) => sleep(Duration::from_millis(50)).await,
+            }
+        }
+    })
+    .await
+    .expect("server didn't start");
+    (child, client)
+}
+
+/// Connect to the admin service of a started server
+pub async fn admin_client(admin_port: u16) -> MultiSigAdminClient<Channel> {
+    let endpoint = format!("http://127.0.0.1:{}", admin_port);
+    timeout(STARTUP_TIMEOUT, async {
+        loop {
+            match MultiSigAdminClient::connect(endpoint.clone()).await {
+                Ok(client) => break client,
+                Err(_) => sleep(Duration::from_millis(50)).await,
+            }
+        }
+    })
+    .await
+    .expect("admin service didn't start")
+}
+
+/// Run a server command in dir with the config file in dir, and wait for it to exit
+pub async fn run_command(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_multisig-service"))
+        .arg("--config")
+        .arg(dir.join("config.toml"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .await
+        .unwrap()
+}
+
+/// Send SIGTERM to the server and wait for it to exit
+pub async fn terminate(child: Child) -> Output {
+    send_signal(&child, Signal::SIGTERM);
+    timeout(SHUTDOWN_TIMEOUT, child.wait_with_output())
+        .await
+        .expect("server didn't shut down")
+        .unwrap()
+}
+
+pub fn send_signal(child: &Child, signal: Signal) {
+    kill(Pid::from_raw(child.id().unwrap() as i32), signal).unwrap();
+}
+
+/// Returns a store request of a valid message
+pub fn store_request(address: Vec<u8>) -> StoreMessageRequest {
+    StoreMessageRequest {
+        user_message: Some(UserMessage {
+            net_id: 1,
+            created: Utc::now().timestamp() as u64,
+            address,
+            transaction_type: TransactionType::VaultWithdraw as i32,
+            transaction_data: vec![1; 128],
+            encrypted_transaction_data: None,
+        }),
+    }
+}
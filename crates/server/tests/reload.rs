@@ -0,0 +1,55 @@
+mod common;
+
+use common::*;
+use nix::sys::signal::Signal;
+use tokio::time::{sleep, timeout, Duration};
+use tonic::Code;
+
+#[tokio::test]
+async fn reload_config_on_sighup() {
+    let dir = test_dir();
+    let port = free_port();
+    let address: Vec<u8> = (0..100).map(|_| rand::random::<u8>()).collect();
+
+    let (child, mut client) = start_server(&dir, port).await;
+    client
+        .store_message(store_request(address.clone()))
+        .await
+        .unwrap();
+
+    // the new address size limit is applied, and the port is kept until a restart
+    write_config(&dir, free_port(), "max_address_size = 64\n");
+    send_signal(&child, Signal::SIGHUP);
+    timeout(Duration::from_secs(5), async {
+        loop {
+            match client.store_message(store_request(address.clone())).await {
+                Err(status) if status.code() == Code::InvalidArgument => break,
+                _ => sleep(Duration::from_millis(50)).await,
+            }
+        }
+    })
+    .await
+    .expect("config was not reloaded");
+
+    // an invalid config is not applied
+    write_config(&dir, port, "max_address_size = 0\n");
+    send_signal(&child, Signal::SIGHUP);
+    sleep(Duration::from_millis(200)).await;
+    assert_eq!(
+        client
+            .store_message(store_request(address.clone()))
+            .await
+            .unwrap_err()
+            .code(),
+        Code::InvalidArgument
+    );
+
+    let output = terminate(child).await;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("config changed: max_address_size: 128 -> 64"));
+    assert!(stdout.contains("restart required to apply: port"));
+    assert!(stdout.contains("failed to reload config - keeping current config"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
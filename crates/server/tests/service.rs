@@ -0,0 +1,342 @@
+mod common;
+
+use api::api::{
+    GetMessagesRequest, GetServerInfoRequest, StoreMessageRequest, TransactionType, UserMessage,
+};
+use api::api_extensions::EnvelopeKeyPair;
+use chrono::prelude::*;
+use common::*;
+use tonic::Code;
+
+// server defaults
+const MAX_ADDRESS_SIZE: usize = 128;
+const MAX_TX_DATA_SIZE: usize = 2048;
+const MAX_ENVELOPE_RECIPIENTS: usize = 32;
+const ACCEPTED_TIME_WINDOW: u64 = 60 * 60 * 24;
+
+fn new_address() -> Vec<u8> {
+    (0..32).map(|_| rand::random::<u8>()).collect()
+}
+
+/// Returns a store request of a valid message changed by update
+fn store_request_with(
+    address: &[u8],
+    update: impl FnOnce(&mut UserMessage),
+) -> StoreMessageRequest {
+    let mut request = store_request(address.to_vec());
+    update(request.user_message.as_mut().unwrap());
+    request
+}
+
+/// Returns a store request of a message with transaction data encrypted to recipients
+fn encrypted_store_request(
+    address: &[u8],
+    transaction_data: &[u8],
+    recipients: &[EnvelopeKeyPair],
+) -> StoreMessageRequest {
+    let public_keys: Vec<[u8; 32]> = recipients.iter().map(|r| r.public_key()).collect();
+    StoreMessageRequest {
+        user_message: Some(
+            UserMessage::new_encrypted(
+                1,
+                Utc::now().timestamp() as u64,
+                address.to_vec(),
+                TransactionType::VaultWithdraw,
+                transaction_data,
+                &public_keys,
+            )
+            .unwrap(),
+        ),
+    }
+}
+
+#[tokio::test]
+async fn store_and_get_messages() {
+    let dir = test_dir();
+    let port = free_port();
+    let (child, mut client) = start_server(&dir, port).await;
+    let address1 = new_address();
+    let address2 = new_address();
+
+    // messages are returned in stored order
+    let requests: Vec<StoreMessageRequest> = (0..3u8)
+        .map(|i| store_request_with(&address1, |m| m.transaction_data = vec![i; 64]))
+        .collect();
+    for request in requests.iter() {
+        client.store_message(request.clone()).await.unwrap();
+    }
+    client
+        .store_message(store_request(address2.clone()))
+        .await
+        .unwrap();
+
+    let messages = client
+        .get_messages(GetMessagesRequest {
+            address: address1.clone(),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .user_messages;
+    let stored: Vec<UserMessage> = requests
+        .into_iter()
+        .map(|r| r.user_message.unwrap())
+        .collect();
+    assert_eq!(messages, stored);
+
+    let messages = client
+        .get_messages(GetMessagesRequest { address: address2 })
+        .await
+        .unwrap()
+        .into_inner()
+        .user_messages;
+    assert_eq!(messages.len(), 1);
+
+    // no messages for an unknown address
+    let messages = client
+        .get_messages(GetMessagesRequest {
+            address: new_address(),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .user_messages;
+    assert!(messages.is_empty());
+
+    assert!(terminate(child).await.status.success());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn store_encrypted_message() {
+    let dir = test_dir();
+    let port = free_port();
+    let (child, mut client) = start_server(&dir, port).await;
+    let address = new_address();
+    let cosigners = [EnvelopeKeyPair::generate(), EnvelopeKeyPair::generate()];
+    let tx_data = vec![7; MAX_TX_DATA_SIZE];
+
+    client
+        .store_message(encrypted_store_request(&address, &tx_data, &cosigners))
+        .await
+        .unwrap();
+    let messages = client
+        .get_messages(GetMessagesRequest { address })
+        .await
+        .unwrap()
+        .into_inner()
+        .user_messages;
+    assert_eq!(messages.len(), 1);
+    for cosigner in cosigners.iter() {
+        assert_eq!(
+            messages[0].open_transaction_data(cosigner).unwrap(),
+            tx_data
+        );
+    }
+    assert!(messages[0]
+        .open_transaction_data(&EnvelopeKeyPair::generate())
+        .is_err());
+
+    assert!(terminate(child).await.status.success());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn reject_invalid_messages() {
+    let dir = test_dir();
+    let port = free_port();
+    let (child, mut client) = start_server(&dir, port).await;
+    let address = new_address();
+    let now = Utc::now().timestamp() as u64;
+    let cosigner = EnvelopeKeyPair::generate();
+
+    let mut invalid_version = encrypted_store_request(&address, &[1; 64], &[cosigner]);
+    invalid_version
+        .user_message
+        .as_mut()
+        .unwrap()
+        .encrypted_transaction_data
+        .as_mut()
+        .unwrap()
+        .version = 2;
+    let mut data_and_envelope =
+        encrypted_store_request(&address, &[1; 64], &[EnvelopeKeyPair::generate()]);
+    data_and_envelope
+        .user_message
+        .as_mut()
+        .unwrap()
+        .transaction_data = vec![1; 64];
+    let recipients: Vec<EnvelopeKeyPair> = (0..=MAX_ENVELOPE_RECIPIENTS)
+        .map(|_| EnvelopeKeyPair::generate())
+        .collect();
+
+    let invalid_requests = vec![
+        (
+            "missing message",
+            StoreMessageRequest { user_message: None },
+        ),
+        ("empty address", store_request_with(&[], |_| {})),
+        (
+            "large address",
+            store_request_with(&[1; MAX_ADDRESS_SIZE + 1], |_| {}),
+        ),
+        (
+            "reserved address",
+            store_request_with(b"all_addresses", |_| {}),
+        ),
+        (
+            "old message",
+            store_request_with(&address, |m| m.created = now - ACCEPTED_TIME_WINDOW - 60),
+        ),
+        (
+            "future message",
+            store_request_with(&address, |m| m.created = now + ACCEPTED_TIME_WINDOW + 60),
+        ),
+        (
+            "large transaction data of an unknown type",
+            store_request_with(&address, |m| {
+                m.transaction_type = 100;
+                m.transaction_data = vec![1; MAX_TX_DATA_SIZE + 1]
+            }),
+        ),
+        (
+            "empty transaction data",
+            store_request_with(&address, |m| m.transaction_data = vec![]),
+        ),
+        (
+            "large transaction data",
+            store_request_with(&address, |m| {
+                m.transaction_data = vec![1; MAX_TX_DATA_SIZE + 1]
+            }),
+        ),
+        ("transaction data and envelope", data_and_envelope),
+        ("invalid envelope", invalid_version),
+        (
+            "large encrypted transaction data",
+            encrypted_store_request(
+                &address,
+                &[1; MAX_TX_DATA_SIZE + 1],
+                &[EnvelopeKeyPair::generate()],
+            ),
+        ),
+        (
+            "too many envelope recipients",
+            encrypted_store_request(&address, &[1; 64], &recipients),
+        ),
+    ];
+
+    for (name, request) in invalid_requests {
+        let status = client.store_message(request).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument, "{}", name);
+    }
+
+    // rejected messages are not stored
+    let messages = client
+        .get_messages(GetMessagesRequest { address })
+        .await
+        .unwrap()
+        .into_inner()
+        .user_messages;
+    assert!(messages.is_empty());
+
+    assert!(terminate(child).await.status.success());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn get_server_info() {
+    let dir = test_dir();
+    let port = free_port();
+    write_config(&dir, port, "max_tx_data_size_coin_spend = 4096\n");
+    let (child, mut client) = start_server(&dir, port).await;
+
+    let t = Utc::now().timestamp() as u64;
+    let info = client
+        .get_server_info(GetServerInfoRequest {})
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(info.max_address_size as usize, MAX_ADDRESS_SIZE);
+    assert_eq!(
+        info.max_envelope_recipients as usize,
+        MAX_ENVELOPE_RECIPIENTS
+    );
+    assert_eq!(info.accepted_time_window, ACCEPTED_TIME_WINDOW);
+    assert!(info.server_time >= t);
+    // all transaction types of the api are supported. Their values are consecutive from 0
+    let types: Vec<i32> = info
+        .supported_transaction_types
+        .iter()
+        .map(|limits| limits.transaction_type)
+        .collect();
+    let expected: Vec<i32> = (0..)
+        .take_while(|t| TransactionType::is_valid(*t))
+        .collect();
+    assert_eq!(types, expected);
+    for limits in info.supported_transaction_types {
+        let expected = if limits.transaction_type == TransactionType::CoinSpend as i32 {
+            4096
+        } else {
+            MAX_TX_DATA_SIZE
+        };
+        assert_eq!(limits.max_transaction_data_size as usize, expected);
+    }
+    assert_eq!(
+        info.default_max_transaction_data_size as usize,
+        MAX_TX_DATA_SIZE
+    );
+
+    assert!(terminate(child).await.status.success());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn concurrent_requests() {
+    let dir = test_dir();
+    let port = free_port();
+    // request logs are not read
+    write_config(&dir, port, "log_level = \"warn\"\n");
+    let (child, client) = start_server(&dir, port).await;
+    let addresses: Vec<Vec<u8>> = (0..8).map(|_| new_address()).collect();
+
+    // each address gets messages from a few clients at the same time
+    let mut tasks = vec![];
+    for address in addresses.iter() {
+        for _ in 0..4 {
+            let mut client = client.clone();
+            let address = address.clone();
+            tasks.push(tokio::spawn(async move {
+                for _ in 0..5 {
+                    client
+                        .store_message(store_request(address.clone()))
+                        .await
+                        .unwrap();
+                    client
+                        .get_messages(GetMessagesRequest {
+                            address: address.clone(),
+                        })
+                        .await
+                        .unwrap();
+                }
+            }));
+        }
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    let mut client = client;
+    for address in addresses {
+        let messages = client
+            .get_messages(GetMessagesRequest { address })
+            .await
+            .unwrap()
+            .into_inner()
+            .user_messages;
+        assert_eq!(messages.len(), 20);
+    }
+
+    assert!(terminate(child).await.status.success());
+    let _ = std::fs::remove_dir_all(&dir);
+}
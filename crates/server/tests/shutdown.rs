@@ -0,0 +1,69 @@
+mod common;
+
+use api::api::GetMessagesRequest;
+use common::*;
+
+#[tokio::test]
+async fn graceful_shutdown() {
+    let dir = test_dir();
+    let port = free_port();
+    let address: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
+
+    let (child, mut client) = start_server(&dir, port).await;
+    client
+        .store_message(store_request(address.clone()))
+        .await
+        .unwrap();
+
+    // the server exits cleanly after flushing the db
+    let output = terminate(child).await;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("db flushed"));
+    assert!(stdout.contains("server shut down"));
+
+    // stored messages are available after a restart
+    let (child, mut client) = start_server(&dir, port).await;
+    let messages = client
+        .get_messages(GetMessagesRequest { address })
+        .await
+        .unwrap()
+        .into_inner()
+        .user_messages;
+    assert_eq!(messages.len(), 1);
+
+    let output = terminate(child).await;
+    assert!(output.status.success());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn shutdown_stops_admin_and_backup_tasks() {
+    let dir = test_dir();
+    let port = free_port();
+    let admin_port = free_port();
+    write_config(
+        &dir,
+        port,
+        &format!(
+            "admin_host = \"127.0.0.1\"\nadmin_port = {}\nbackup_interval = 1\n",
+            admin_port
+        ),
+    );
+    let (child, _client) = start_server(&dir, port).await;
+    let _admin = admin_client(admin_port).await;
+    // let a scheduled backup run
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+
+    // the admin server and the backup task stop before the db is flushed
+    let output = terminate(child).await;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    let flushed = stdout.find("db flushed").unwrap();
+    assert!(stdout.find("admin grpc server stopped").unwrap() < flushed);
+    assert!(stdout.find("db backup task stopped").unwrap() < flushed);
+    assert!(!stdout.contains("did not complete"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}